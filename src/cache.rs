@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use reqwest::Method;
+
+use crate::types::*;
+
+/// Response bodies cached by the endpoint and the parameters.
+#[derive(Default)]
+pub(crate) struct ResponseCache {
+    /// TTL for all cacheable endpoints.
+    pub global_ttl: Option<Duration>,
+    /// TTL per endpoint path, overriding `global_ttl`.
+    pub endpoint_ttls: HashMap<String, Option<Duration>>,
+    /// The body with the time it was stored and its TTL at that time.
+    entries: HashMap<String, (Instant, Duration, String)>,
+}
+
+impl ResponseCache {
+    /// Endpoints not cached by `global_ttl`, because they change with every order.
+    const EXCLUDED_BY_DEFAULT: [&'static str; 2] = ["/api/accounts/balance", "/api/exchange/"];
+
+    /// Get the TTL for the endpoint. Only `GET` requests are cached.
+    pub fn ttl(&self, method: &Method, path: &str) -> Option<Duration> {
+        if *method != Method::GET {
+            return None;
+        }
+        if let Some(ttl) = self.endpoint_ttls.get(path) {
+            return *ttl;
+        }
+        if Self::EXCLUDED_BY_DEFAULT
+            .iter()
+            .any(|excluded| path.starts_with(excluded))
+        {
+            return None;
+        }
+        self.global_ttl
+    }
+
+    pub fn key(method: &Method, path: &str, params: Option<&Params<'_>>) -> String {
+        let mut params: Vec<_> = params
+            .into_iter()
+            .flatten()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect();
+        params.sort();
+        format!("{} {}?{}", method, path, params.join("&"))
+    }
+
+    pub fn get(&mut self, key: &str, ttl: Duration, now: Instant) -> Option<String> {
+        match self.entries.get(key) {
+            Some((stored_at, _, body)) if now.duration_since(*stored_at) < ttl => {
+                Some(body.clone())
+            }
+            Some(_) => {
+                self.entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Store the body, and drop the expired entries of all endpoints, so that the entries of
+    /// parameters not requested again don't pile up.
+    pub fn insert(&mut self, key: String, body: String, ttl: Duration, now: Instant) {
+        self.entries
+            .retain(|_, (stored_at, ttl, _)| now.duration_since(*stored_at) < *ttl);
+        self.entries.insert(key, (now, ttl, body));
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ttl_by_endpoint() {
+        let mut cache = ResponseCache::default();
+        let minute = Duration::from_secs(60);
+        assert_eq!(cache.ttl(&Method::GET, "/api/accounts"), None);
+
+        cache.global_ttl = Some(minute);
+        assert_eq!(cache.ttl(&Method::GET, "/api/accounts"), Some(minute));
+        assert_eq!(cache.ttl(&Method::POST, "/api/accounts"), None);
+        assert_eq!(cache.ttl(&Method::GET, "/api/accounts/balance"), None);
+        assert_eq!(cache.ttl(&Method::GET, "/api/exchange/orders/opens"), None);
+
+        cache
+            .endpoint_ttls
+            .insert("/api/accounts/balance".to_owned(), Some(minute));
+        cache.endpoint_ttls.insert("/api/ticker".to_owned(), None);
+        assert_eq!(
+            cache.ttl(&Method::GET, "/api/accounts/balance"),
+            Some(minute)
+        );
+        assert_eq!(cache.ttl(&Method::GET, "/api/ticker"), None);
+    }
+
+    #[test]
+    fn entries_expire() {
+        let mut cache = ResponseCache::default();
+        let ttl = Duration::from_secs(60);
+        let now = Instant::now();
+
+        let params: Params = [("pair", "btc_jpy"), ("limit", "1")]
+            .iter()
+            .copied()
+            .collect();
+        let key = ResponseCache::key(&Method::GET, "/api/trades", Some(&params));
+        assert_eq!(key, "GET /api/trades?limit=1&pair=btc_jpy");
+
+        cache.insert(key.clone(), "{}".to_owned(), ttl, now);
+        assert_eq!(cache.get(&key, ttl, now + ttl / 2), Some("{}".to_owned()));
+        assert_eq!(cache.get(&key, ttl, now + ttl), None);
+        assert_eq!(cache.get(&key, ttl, now), None);
+    }
+
+    #[test]
+    fn insert_prunes_expired() {
+        let mut cache = ResponseCache::default();
+        let ttl = Duration::from_secs(60);
+        let now = Instant::now();
+
+        for limit in 0..100 {
+            let key = format!("GET /api/trades?limit={}", limit);
+            cache.insert(key, "{}".to_owned(), ttl, now);
+        }
+        cache.insert("GET /api/ticker?".to_owned(), "{}".to_owned(), ttl * 2, now);
+        assert_eq!(cache.entries.len(), 101);
+
+        // only the entry still fresh is kept
+        let later = now + ttl;
+        cache.insert(
+            "GET /api/rate/btc_jpy?".to_owned(),
+            "{}".to_owned(),
+            ttl,
+            later,
+        );
+        let mut keys: Vec<_> = cache.entries.keys().cloned().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["GET /api/rate/btc_jpy?", "GET /api/ticker?"]);
+    }
+}
@@ -1,18 +1,29 @@
-use std::cell::RefCell;
-use std::rc::Rc;
-use std::time::{Instant, SystemTime};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 
-use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
-use reqwest::{Method, Url};
+use chrono::{DateTime, TimeZone, Utc};
+
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE, DATE, RETRY_AFTER};
+use reqwest::{Method, StatusCode, Url};
 
 use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DurationMilliSeconds};
 
 use hmac::{Hmac, Mac, NewMac};
 use sha2::Sha256;
+use tokio::sync::mpsc;
 type HmacSha256 = Hmac<Sha256>;
 
-use anyhow::{anyhow, Result};
-
+use crate::cache::ResponseCache;
+use crate::error::{Error, Result};
+use crate::exchange::BoxFuture;
+use crate::rate_limit::{RateLimit, RateLimiter};
+use crate::retry::{self, NoRetry, RetryGets, RetryPolicy};
+use crate::schema_drift::SchemaDrift;
+use crate::single_flight::SingleFlight;
 use crate::types::*;
 
 const API_BASE: &str = "https://coincheck.com";
@@ -24,34 +35,559 @@ impl Header {
     const KEY: &'static str = "ACCESS-KEY";
 }
 
-pub type SharedClient = Rc<RefCell<Client>>;
+pub type SharedClient = Arc<Client>;
 
 pub struct Client {
     access_key: Option<String>,
     secret_key: Option<String>,
-    client: reqwest::Client,
-    pub last_request_time: Instant,
+    client: Mutex<reqwest::Client>,
+    last_request_time: Mutex<Instant>,
+    server_time: Mutex<ServerTime>,
+    inspector: Mutex<Option<Arc<Inspector>>>,
+    retry_policy: Mutex<Arc<dyn RetryPolicy>>,
+    config: Mutex<ClientConfig>,
+    clock: Mutex<Arc<dyn Clock>>,
+    last_nonce: Mutex<u128>,
+    nonce_provider: Mutex<Option<Arc<dyn SharedNonceProvider>>>,
+    cache: Mutex<ResponseCache>,
+    rate_limiter: RateLimiter,
+    in_flight: SingleFlight,
+    /// Canned response bodies by path. `Some` if the client is offline.
+    offline: Option<Mutex<HashMap<String, String>>>,
+}
+
+/// A source of the current time, used for the nonce. See [`Client::set_clock`].
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// A source of nonces shared by all the clients using the same API key.
+///
+/// By default each [`Client`] generates nonces from its own clock, so multiple processes sharing
+/// a key (e.g. replicas behind a load balancer) can send the same or a smaller nonce than another
+/// one, and get rejected. A shared provider coordinates the nonces through an atomic counter
+/// outside of the processes.
+///
+/// With the `redis-nonce` feature, `RedisNonce` is a provider using a counter in Redis.
+pub trait SharedNonceProvider: Send + Sync {
+    /// Get the next nonce. It must be greater than all the nonces returned before, to any client.
+    fn next_nonce(&self) -> BoxFuture<'_, u128>;
+}
+
+/// The real system clock. (default)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Settings of the [`Client`], without the API keys.
+///
+/// This can be saved as JSON to create an identically configured client later.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientConfig {
+    /// The API base URL, e.g. a mock server or a proxy for testing. A path prefix is kept, so
+    /// `http://localhost:8080/coincheck` sends `/api/ticker` to
+    /// `http://localhost:8080/coincheck/api/ticker`. Plain HTTP is only allowed to a loopback host
+    /// (`localhost`, `127.0.0.1` or `::1`), so the keys are never sent unencrypted over the
+    /// network. (default: `https://coincheck.com`)
+    pub base_url: String,
+    /// Timeout of each request, including reading the response (milliseconds in JSON).
+    /// (default: 30 seconds)
+    #[serde_as(as = "Option<DurationMilliSeconds<u64>>")]
+    pub timeout: Option<Duration>,
+    /// Timeout of connecting to the server (milliseconds in JSON). (default: 10 seconds)
+    #[serde_as(as = "Option<DurationMilliSeconds<u64>>")]
+    pub connect_timeout: Option<Duration>,
+    /// Timeouts by endpoint path prefix (milliseconds in JSON), overriding `timeout`.
+    /// See [`ClientConfig::endpoint_timeout`].
+    #[serde_as(as = "BTreeMap<_, DurationMilliSeconds<u64>>")]
+    pub endpoint_timeouts: BTreeMap<String, Duration>,
+    pub user_agent: Option<String>,
+    /// See [`Client::set_max_response_bytes`].
+    pub max_response_bytes: Option<usize>,
+    /// Retry policy. Custom policies set with [`Client::set_retry_policy`] can't be saved and
+    /// are reported as `None`.
+    pub retry: Option<RetryGets>,
+    /// See [`Client::set_warn_schema_drift`].
+    pub warn_schema_drift: bool,
+    /// See [`Client::set_max_order_notional`].
+    pub max_order_notional: Option<PriceType>,
+    /// See [`Client::set_max_withdraw_amount`].
+    pub max_withdraw_amount: Option<PriceType>,
+    /// See [`Client::set_rate_limit`].
+    pub rate_limit: Option<RateLimit>,
+    /// See [`Client::set_coalesce_gets`].
+    pub coalesce_gets: bool,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        ClientConfig {
+            base_url: API_BASE.to_owned(),
+            timeout: Some(Duration::from_secs(30)),
+            connect_timeout: Some(Duration::from_secs(10)),
+            endpoint_timeouts: BTreeMap::new(),
+            user_agent: None,
+            max_response_bytes: None,
+            retry: None,
+            warn_schema_drift: false,
+            max_order_notional: None,
+            max_withdraw_amount: None,
+            rate_limit: Some(RateLimit::default()),
+            coalesce_gets: false,
+        }
+    }
+}
+
+impl ClientConfig {
+    /// Serialize to JSON.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Deserialize from JSON. Missing fields are set to the default values.
+    pub fn from_json(json: &str) -> Result<ClientConfig> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Get the timeout of the endpoint `path`, from the longest matching prefix in
+    /// `endpoint_timeouts`. `None` if no prefix matches, then `timeout` is used.
+    ///
+    /// For example, with `/api/exchange/orders` set to 1 second and
+    /// `/api/exchange/orders/transactions` set to 30 seconds, placing an order times out after
+    /// 1 second but the transaction history after 30 seconds.
+    pub fn endpoint_timeout(&self, path: &str) -> Option<Duration> {
+        self.endpoint_timeouts
+            .iter()
+            .filter(|(prefix, _)| path.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, &timeout)| timeout)
+    }
+}
+
+/// A hook called with every request and its response. See [`Client::set_inspector`].
+pub type Inspector = dyn Fn(&RequestInfo, &ResponseInfo) + Send + Sync;
+
+/// A request passed to the [`Inspector`]. The authentication headers are redacted.
+#[derive(Debug, Clone)]
+pub struct RequestInfo {
+    pub method: Method,
+    /// The URL including the query parameters.
+    pub url: String,
+    pub headers: HeaderMap,
+}
+
+/// A response passed to the [`Inspector`].
+#[derive(Debug, Clone)]
+pub struct ResponseInfo {
+    pub status: StatusCode,
+    pub body: String,
+}
+
+/// A response body read in chunks as it arrives, as a blocking [`io::Read`].
+/// See [`Client::request_and_get_reader`].
+///
+/// It waits for the chunks, so read it on a blocking thread (e.g. with
+/// [`tokio::task::spawn_blocking`]).
+pub(crate) struct BodyReader {
+    chunks: mpsc::Receiver<io::Result<Vec<u8>>>,
+    chunk: io::Cursor<Vec<u8>>,
+}
+
+impl BodyReader {
+    /// Number of chunks read ahead of the parser.
+    const READ_AHEAD: usize = 16;
+
+    /// Get back the error of reading the body from an error of parsing it, e.g.
+    /// [`Error::ResponseTooLarge`].
+    pub(crate) fn parse_error(err: serde_json::Error) -> Error {
+        if !err.is_io() {
+            return Error::Deserialize(err);
+        }
+        let err = io::Error::from(err);
+        if err.get_ref().is_none() {
+            return Error::Io(err);
+        }
+        let inner = err.into_inner().unwrap();
+        let inner = match inner.downcast::<Error>() {
+            Ok(err) => return *err,
+            Err(inner) => inner,
+        };
+        match inner.downcast::<reqwest::Error>() {
+            Ok(err) => Error::from(*err),
+            Err(inner) => Error::Other(inner),
+        }
+    }
+}
+
+impl io::Read for BodyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.chunk.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            match self.chunks.blocking_recv() {
+                Some(chunk) => self.chunk = io::Cursor::new(chunk?),
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+/// Server time observed from the `Date` response headers.
+#[derive(Default)]
+struct ServerTime {
+    last_server_date: Option<DateTime<Utc>>,
+    skews_ms: VecDeque<i64>,
+}
+
+impl ServerTime {
+    /// Number of samples used for the rolling skew estimate.
+    const WINDOW: usize = 16;
+
+    fn record(&mut self, server_date: DateTime<Utc>, local_date: DateTime<Utc>) {
+        self.last_server_date = Some(server_date);
+        if self.skews_ms.len() == Self::WINDOW {
+            self.skews_ms.pop_front();
+        }
+        self.skews_ms
+            .push_back((server_date - local_date).num_milliseconds());
+    }
+
+    fn skew(&self) -> Option<chrono::Duration> {
+        if self.skews_ms.is_empty() {
+            return None;
+        }
+        let sum: i64 = self.skews_ms.iter().sum();
+        Some(chrono::Duration::milliseconds(
+            sum / self.skews_ms.len() as i64,
+        ))
+    }
 }
 
 /// A simple wrapper of the HTTP client.
 impl Client {
     /// Create a new instance.
     pub fn shared_new(access_key: Option<String>, secret_key: Option<String>) -> SharedClient {
-        Rc::new(RefCell::new(Client {
+        Client::shared_with_config(access_key, secret_key, ClientConfig::default())
+    }
+
+    /// Create a new instance with the settings.
+    pub fn shared_with_config(
+        access_key: Option<String>,
+        secret_key: Option<String>,
+        config: ClientConfig,
+    ) -> SharedClient {
+        Arc::new(Client::with_config(access_key, secret_key, config))
+    }
+
+    /// Create a new instance that never sends a request.
+    ///
+    /// The requests get the responses set with [`Client::set_offline_response`], or fail with
+    /// [`Error::Offline`]. Useful to run code using this library without network access
+    /// (e.g. a backtest).
+    pub fn shared_offline() -> SharedClient {
+        Arc::new(Client {
+            offline: Some(Mutex::new(HashMap::new())),
+            ..Client::with_config(None, None, ClientConfig::default())
+        })
+    }
+
+    fn with_config(
+        access_key: Option<String>,
+        secret_key: Option<String>,
+        config: ClientConfig,
+    ) -> Client {
+        let retry_policy: Arc<dyn RetryPolicy> = match config.retry {
+            Some(retry) => Arc::new(retry),
+            None => Arc::new(NoRetry),
+        };
+        Client {
             access_key,
             secret_key,
-            client: reqwest::Client::builder().https_only(true).build().unwrap(),
-            last_request_time: Instant::now(),
-        }))
+            client: Mutex::new(Client::build_http_client(&config)),
+            last_request_time: Mutex::new(Instant::now()),
+            server_time: Mutex::new(ServerTime::default()),
+            inspector: Mutex::new(None),
+            retry_policy: Mutex::new(retry_policy),
+            config: Mutex::new(config),
+            clock: Mutex::new(Arc::new(SystemClock)),
+            last_nonce: Mutex::new(0),
+            nonce_provider: Mutex::new(None),
+            cache: Mutex::new(ResponseCache::default()),
+            rate_limiter: RateLimiter::default(),
+            in_flight: SingleFlight::default(),
+            offline: None,
+        }
+    }
+
+    /// Whether the client was created with [`Client::shared_offline`].
+    pub fn is_offline(&self) -> bool {
+        self.offline.is_some()
+    }
+
+    /// Set the response body returned for the requests to `path` (e.g. `/api/ticker`),
+    /// regardless of the method and the parameters. Ignored unless the client is offline.
+    pub fn set_offline_response(&self, path: &str, body: &str) {
+        if let Some(responses) = &self.offline {
+            responses
+                .lock()
+                .unwrap()
+                .insert(path.to_owned(), body.to_owned());
+        }
+    }
+
+    fn build_http_client(config: &ClientConfig) -> reqwest::Client {
+        let https_only = !Client::is_loopback_http(&config.base_url);
+        let mut builder = reqwest::Client::builder().https_only(https_only);
+        if let Some(timeout) = config.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = config.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(user_agent) = &config.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+        builder.build().unwrap()
+    }
+
+    /// Whether `base_url` is a plain HTTP URL to this machine, e.g. a local mock server.
+    fn is_loopback_http(base_url: &str) -> bool {
+        let url = match Url::parse(base_url) {
+            Ok(url) if url.scheme() == "http" => url,
+            _ => return false,
+        };
+        let host = url.host_str().unwrap_or_default();
+        // IPv6 hosts are in brackets
+        let ip = host.trim_start_matches('[').trim_end_matches(']');
+        host.eq_ignore_ascii_case("localhost")
+            || ip
+                .parse::<std::net::IpAddr>()
+                .is_ok_and(|ip| ip.is_loopback())
+    }
+
+    /// Get a snapshot of the current settings.
+    pub fn config(&self) -> ClientConfig {
+        self.config.lock().unwrap().clone()
+    }
+
+    /// Rebuild the underlying HTTP client, dropping all pooled connections.
+    ///
+    /// The keys and settings are kept. This is useful to recover from stale connections after a
+    /// prolonged network failure. (`reqwest` doesn't expose connection pool statistics.)
+    pub fn reset(&self) {
+        let client = Client::build_http_client(&self.config.lock().unwrap());
+        *self.client.lock().unwrap() = client;
+    }
+
+    /// Set a hook called with every request and its response body. For debugging.
+    ///
+    /// The `ACCESS-KEY` and `ACCESS-SIGNATURE` headers are redacted. The hook is called from
+    /// [`Client::request_and_get_json`], [`Client::request_and_get_text`] and
+    /// [`Client::request_and_get_bytes`], and for the error responses of [`Client::request`].
+    pub fn set_inspector(&self, inspector: Box<Inspector>) {
+        *self.inspector.lock().unwrap() = Some(inspector.into());
+    }
+
+    /// Remove the hook set by [`Client::set_inspector`].
+    pub fn clear_inspector(&self) {
+        *self.inspector.lock().unwrap() = None;
+    }
+
+    /// Set the policy deciding which failed requests are retried. (default: [`NoRetry`])
+    ///
+    /// See [`RetryTransient`](crate::retry::RetryTransient) for the usual `429`/`5xx` retries.
+    /// When a request is given up after retries, the last error is returned and the number of
+    /// attempts is logged.
+    pub fn set_retry_policy(&self, policy: Box<dyn RetryPolicy>) {
+        *self.retry_policy.lock().unwrap() = policy.into();
+        self.config.lock().unwrap().retry = None;
+    }
+
+    /// Set the clock used for the nonce. (default: [`SystemClock`])
+    pub fn set_clock(&self, clock: Box<dyn Clock>) {
+        *self.clock.lock().unwrap() = clock.into();
+    }
+
+    /// Cache the responses of all `GET` requests for `ttl`. (default: `None`, disabled)
+    ///
+    /// The balance and the exchange order endpoints (`/api/exchange/...`) are not cached unless
+    /// enabled with [`Client::set_endpoint_cache_ttl`]. Requests that aren't `GET` are never
+    /// cached.
+    pub fn set_cache_ttl(&self, ttl: Option<Duration>) {
+        self.cache.lock().unwrap().global_ttl = ttl;
+    }
+
+    /// Set the cache TTL of an endpoint path (e.g. `/api/accounts`), overriding
+    /// [`Client::set_cache_ttl`]. `None` disables the cache for the endpoint.
+    pub fn set_endpoint_cache_ttl(&self, path: &str, ttl: Option<Duration>) {
+        self.cache
+            .lock()
+            .unwrap()
+            .endpoint_ttls
+            .insert(path.to_owned(), ttl);
+    }
+
+    /// Remove all cached responses.
+    pub fn clear_cache(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    /// Get the nonces from the shared provider instead of the local clock.
+    pub fn set_nonce_provider(&self, provider: Box<dyn SharedNonceProvider>) {
+        *self.nonce_provider.lock().unwrap() = Some(provider.into());
+    }
+
+    /// Set the timeout of the endpoints starting with `path`, overriding the global timeout.
+    /// `None` removes the setting. See [`ClientConfig::endpoint_timeout`].
+    pub fn set_endpoint_timeout(&self, path: &str, timeout: Option<Duration>) {
+        let mut config = self.config.lock().unwrap();
+        match timeout {
+            Some(timeout) => config.endpoint_timeouts.insert(path.to_owned(), timeout),
+            None => config.endpoint_timeouts.remove(path),
+        };
+    }
+
+    /// Set the maximum size of a response body. (default: `None`, unlimited)
+    ///
+    /// A larger response is rejected with [`Error::ResponseTooLarge`] without reading the rest
+    /// of the body.
+    pub fn set_max_response_bytes(&self, max: Option<usize>) {
+        self.config.lock().unwrap().max_response_bytes = max;
+    }
+
+    /// Compare the JSON responses with the models and log a `tracing` warning for each unexpected
+    /// or missing key. (default: `false`)
+    ///
+    /// For catching API changes early. Keys absorbed by a model's catch-all field (e.g. the
+    /// other currencies of [`Balance`](crate::private::account::model::Balance)) are not reported.
+    /// This deserializes every response twice, so it's slower.
+    pub fn set_warn_schema_drift(&self, enabled: bool) {
+        self.config.lock().unwrap().warn_schema_drift = enabled;
+    }
+
+    /// Set the maximum notional (JPY) of a new order. (default: `None`, unlimited)
+    ///
+    /// A safety rail: larger orders are rejected with [`Error::LimitExceeded`] by
+    /// [`Order::new_any`](crate::private::order::Order::new_any) and the `Order::new_*` methods
+    /// before being sent.
+    pub fn set_max_order_notional(&self, max: Option<PriceType>) {
+        self.config.lock().unwrap().max_order_notional = max;
+    }
+
+    /// Set the maximum amount (JPY) of a withdrawal. (default: `None`, unlimited)
+    ///
+    /// A safety rail: larger withdrawals are rejected with [`Error::LimitExceeded`] by
+    /// [`WithdrawsJpy::create_withdraw`](crate::private::withdraws_jpy::WithdrawsJpy::create_withdraw)
+    /// before being sent.
+    pub fn set_max_withdraw_amount(&self, max: Option<PriceType>) {
+        self.config.lock().unwrap().max_withdraw_amount = max;
+    }
+
+    /// Limit the rate of the requests. `None` disables the limit.
+    /// (default: [`RateLimit::default`], 5 requests per second)
+    ///
+    /// The limit is shared by everything using this client, and applies to every request sent,
+    /// including the retries.
+    pub fn set_rate_limit(&self, limit: Option<RateLimit>) {
+        self.config.lock().unwrap().rate_limit = limit;
+    }
+
+    /// Get how many requests had to wait for the rate limit since the client was created.
+    ///
+    /// Each wait is also logged as a `debug` tracing event. A count growing with every poll means
+    /// the polling loop is faster than the limit set with [`Client::set_rate_limit`].
+    pub fn throttled_count(&self) -> u64 {
+        self.rate_limiter.throttled_count()
+    }
+
+    /// Share one response among the identical `GET` requests in flight at the same time.
+    /// (default: `false`)
+    ///
+    /// For example, many tasks polling the ticker send one request at a time, and all get its
+    /// response. The requests are identical if they have the same path and parameters. A failed
+    /// request fails all of them, with a copy of the [`Error`] or the message of the error.
+    pub fn set_coalesce_gets(&self, enabled: bool) {
+        self.config.lock().unwrap().coalesce_gets = enabled;
+    }
+
+    fn inspect(&self, request: &RequestInfo, response: &ResponseInfo) {
+        // Don't hold the lock while calling the hook.
+        let inspector = self.inspector.lock().unwrap().clone();
+        if let Some(inspector) = inspector {
+            inspector(request, response);
+        }
+    }
+
+    /// Get the last request time.
+    pub fn last_request_time(&self) -> Instant {
+        *self.last_request_time.lock().unwrap()
+    }
+
+    /// Get the server time from the `Date` header of the last response.
+    pub fn last_server_date(&self) -> Option<DateTime<Utc>> {
+        self.server_time.lock().unwrap().last_server_date
+    }
+
+    /// Get the estimated clock skew (server time - local time).
+    ///
+    /// This is a rolling average over the recent responses. The `Date` header has a resolution of
+    /// one second, so the estimate is only accurate to about a second.
+    pub fn clock_skew(&self) -> Option<chrono::Duration> {
+        self.server_time.lock().unwrap().skew()
+    }
+
+    /// Record the `Date` header of a response. The local time is taken at the middle of the
+    /// request to compensate for the latency.
+    fn record_server_date(&self, headers: &HeaderMap, sent_at: DateTime<Utc>) {
+        if let Some(server_date) = Client::server_date(headers) {
+            let local_date = sent_at + (Utc::now() - sent_at) / 2;
+            self.server_time
+                .lock()
+                .unwrap()
+                .record(server_date, local_date);
+        }
+    }
+
+    /// Parse the `Date` header.
+    fn server_date(headers: &HeaderMap) -> Option<DateTime<Utc>> {
+        headers
+            .get(DATE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| DateTime::parse_from_rfc2822(v).ok())
+            .map(|date| date.with_timezone(&Utc))
     }
 
     /// Create authentication HTTP header for the Coincheck REST API .
-    fn set_auth_headers(&mut self, headers: &mut HeaderMap, url: &Url) -> Result<()> {
-        let nonce = Client::get_nonce()?;
+    ///
+    /// The request body, if any, is included in the signature.
+    async fn set_auth_headers(
+        &self,
+        headers: &mut HeaderMap,
+        url: &Url,
+        body: Option<&str>,
+    ) -> Result<()> {
+        let provider = self.nonce_provider.lock().unwrap().clone();
+        let nonce = match provider {
+            Some(provider) => provider.next_nonce().await?.to_string(),
+            None => self.get_nonce()?,
+        };
         headers.insert(Header::NONCE, HeaderValue::from_str(&nonce).unwrap());
 
-        let message = nonce + url.as_str();
-        let signature = Client::get_signature(self.secret_key.as_ref().unwrap(), &message)?;
+        let signature = Client::sign(
+            self.secret_key.as_ref().unwrap(),
+            &nonce,
+            url.as_str(),
+            body.unwrap_or_default(),
+        )?;
         headers.insert(Header::SIGNATURE, signature.parse().unwrap());
         headers.insert(
             Header::KEY,
@@ -62,11 +598,26 @@ impl Client {
     }
 
     /// Get nonce for authentication header creation.
-    fn get_nonce() -> Result<String> {
-        match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
-            Ok(dur) => Ok(dur.as_micros().to_string()),
-            Err(_) => Err(anyhow!("SystemTime before UNIX EPOCH!")),
-        }
+    ///
+    /// The nonce is the current time in microseconds, but always greater than the previous one
+    /// even if the clock goes back or two requests are signed within a microsecond.
+    fn get_nonce(&self) -> Result<String> {
+        let now = self.clock.lock().unwrap().now();
+        let micros = match now.duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(dur) => dur.as_micros(),
+            Err(_) => return Err(Error::other("SystemTime before UNIX EPOCH!")),
+        };
+
+        let mut last_nonce = self.last_nonce.lock().unwrap();
+        *last_nonce = micros.max(*last_nonce + 1);
+        Ok(last_nonce.to_string())
+    }
+
+    /// Sign a request: HMAC-SHA256 of `nonce + url + body` with the secret key, in hex.
+    ///
+    /// `body` is empty for the requests without a body.
+    pub(crate) fn sign(secret_key: &str, nonce: &str, url: &str, body: &str) -> Result<String> {
+        Client::get_signature(secret_key, &(nonce.to_owned() + url + body))
     }
 
     /// Get signature for authentication header creation.
@@ -76,56 +627,527 @@ impl Client {
                 mac.update(message.as_bytes());
                 Ok(hex::encode(mac.finalize().into_bytes()))
             }
-            Err(_) => Err(anyhow!("invalid key length for MAC initialization")),
+            Err(_) => Err(Error::other("invalid key length for MAC initialization")),
         }
     }
 
     /// Send a request to the API and get a JSON result.
-    pub async fn request_and_get_json<T: DeserializeOwned>(
-        &mut self,
+    ///
+    /// A response with `success: false` is rejected with [`Error::Api`], and a response not
+    /// matching `T` with [`Error::Deserialize`]. The parameters of a
+    /// `POST` or `DELETE` request are sent as the JSON body, and included in the signature.
+    pub async fn request_and_get_json<T: DeserializeOwned + ApiResponse>(
+        &self,
+        method: Method,
+        path: &str,
+        params: Option<&Params<'_>>,
+        use_auth: bool,
+    ) -> Result<T> {
+        let text = self
+            .request_and_get_text(method, path, params, use_auth)
+            .await?;
+        self.parse_json(path, &text)
+    }
+
+    /// Send a request to the API and get a JSON result, with the status, the latency and the
+    /// `Date` header of the response.
+    ///
+    /// The request is always sent: the responses are neither cached nor shared by
+    /// [`Client::set_coalesce_gets`]. An offline response has the status `200 OK`, no latency and
+    /// no date.
+    pub async fn request_and_get_json_with_meta<T: DeserializeOwned + ApiResponse>(
+        &self,
         method: Method,
         path: &str,
         params: Option<&Params<'_>>,
         use_auth: bool,
+    ) -> Result<WithMeta<T>> {
+        if let Some(responses) = &self.offline {
+            let text = responses.lock().unwrap().get(path).cloned();
+            let text = text.ok_or(Error::Offline)?;
+            return Ok(WithMeta {
+                value: self.parse_json(path, &text)?,
+                status: StatusCode::OK,
+                latency: Duration::ZERO,
+                server_date: None,
+            });
+        }
+
+        let started = Instant::now();
+        let (res, request) = self
+            .request_inner(method, path, params, None, use_auth)
+            .await?;
+        let status = res.status();
+        let server_date = Client::server_date(res.headers());
+        let mut text = self.read_text(res).await?;
+        let latency = started.elapsed();
+        if let Some(request) = request {
+            let response = ResponseInfo { status, body: text };
+            self.inspect(&request, &response);
+            text = response.body;
+        }
+        Ok(WithMeta {
+            value: self.parse_json(path, &text)?,
+            status,
+            latency,
+            server_date,
+        })
+    }
+
+    /// Send a request with a JSON body to the API and get a JSON result.
+    ///
+    /// The body is included in the signature of a private request. The responses are not cached.
+    pub async fn request_with_body_and_get_json<B: Serialize, T: DeserializeOwned + ApiResponse>(
+        &self,
+        method: Method,
+        path: &str,
+        body: &B,
+        use_auth: bool,
     ) -> Result<T> {
-        let res = self.request(method, path, params, use_auth).await?;
-        let data = res.json().await?;
+        if let Some(responses) = &self.offline {
+            let text = responses.lock().unwrap().get(path).cloned();
+            let text = text.ok_or(Error::Offline)?;
+            return self.parse_json(path, &text);
+        }
+
+        let body = serde_json::to_string(body)?;
+        let (res, request) = self
+            .request_inner(method, path, None, Some(&body), use_auth)
+            .await?;
+        let status = res.status();
+        let text = self.read_text(res).await?;
+        if let Some(request) = request {
+            let response = ResponseInfo { status, body: text };
+            self.inspect(&request, &response);
+            return self.parse_json(path, &response.body);
+        }
+        self.parse_json(path, &text)
+    }
+
+    /// Parse a JSON response, warning about the schema drift if enabled.
+    fn parse_json<T: DeserializeOwned + ApiResponse>(&self, path: &str, text: &str) -> Result<T> {
+        let data: T = match serde_json::from_str(text) {
+            Ok(data) => data,
+            // an error response usually lacks the fields of the model
+            Err(_) if Client::is_unsuccessful(text) => {
+                return Err(Client::api_error(StatusCode::OK, text))
+            }
+            Err(err) => return Err(Error::Deserialize(err)),
+        };
+        if !data.success() {
+            return Err(Client::api_error(StatusCode::OK, text));
+        }
+
+        let warn_schema_drift = self.config.lock().unwrap().warn_schema_drift;
+        if warn_schema_drift {
+            let raw: serde_json::Value = serde_json::from_str(text)?;
+            for drift in SchemaDrift::diff::<T>(&raw) {
+                tracing::warn!(path, key = %drift.key(), "{}", drift);
+            }
+        }
+
         Ok(data)
     }
 
     /// Send a request to the API and get a decoded text.
     pub async fn request_and_get_text(
-        &mut self,
+        &self,
         method: Method,
         path: &str,
         params: Option<&Params<'_>>,
         use_auth: bool,
     ) -> Result<String> {
-        let res = self.request(method, path, params, use_auth).await?;
-        let data = res.text().await?;
+        if let Some(responses) = &self.offline {
+            let body = responses.lock().unwrap().get(path).cloned();
+            return body.ok_or(Error::Offline);
+        }
+
+        let ttl = self.cache.lock().unwrap().ttl(&method, path);
+        let cache_key = ttl.map(|ttl| (ttl, ResponseCache::key(&method, path, params)));
+        if let Some((ttl, key)) = &cache_key {
+            let cached = self.cache.lock().unwrap().get(key, *ttl, Instant::now());
+            if let Some(cached) = cached {
+                return Ok(cached);
+            }
+        }
+
+        let coalesce = method == Method::GET && self.config.lock().unwrap().coalesce_gets;
+        let data = if coalesce {
+            let key = ResponseCache::key(&method, path, params);
+            let request = self.fetch_text(method, path, params, use_auth);
+            self.in_flight.run(&key, request).await?
+        } else {
+            self.fetch_text(method, path, params, use_auth).await?
+        };
+
+        if let Some((ttl, key)) = cache_key {
+            self.cache
+                .lock()
+                .unwrap()
+                .insert(key, data.clone(), ttl, Instant::now());
+        }
+        Ok(data)
+    }
+
+    async fn fetch_text(
+        &self,
+        method: Method,
+        path: &str,
+        params: Option<&Params<'_>>,
+        use_auth: bool,
+    ) -> Result<String> {
+        let (res, request) = self
+            .request_inner(method, path, params, None, use_auth)
+            .await?;
+        let status = res.status();
+        let data = self.read_text(res).await?;
+        if let Some(request) = request {
+            let response = ResponseInfo { status, body: data };
+            self.inspect(&request, &response);
+            return Ok(response.body);
+        }
+        Ok(data)
+    }
+
+    /// Send a request to the API and read the body as it arrives, to parse it incrementally.
+    ///
+    /// The body is read in chunks by a task, up to the limit of
+    /// [`Client::set_max_response_bytes`]. It's neither cached nor passed to the inspector.
+    pub(crate) async fn request_and_get_reader(
+        &self,
+        method: Method,
+        path: &str,
+        params: Option<&Params<'_>>,
+        use_auth: bool,
+    ) -> Result<BodyReader> {
+        let (tx, chunks) = mpsc::channel(BodyReader::READ_AHEAD);
+        let reader = BodyReader {
+            chunks,
+            chunk: io::Cursor::default(),
+        };
+        if let Some(responses) = &self.offline {
+            let body = responses.lock().unwrap().get(path).cloned();
+            let body = body.ok_or(Error::Offline)?;
+            tx.try_send(Ok(body.into_bytes())).unwrap();
+            return Ok(reader);
+        }
+
+        let (mut res, _) = self
+            .request_inner(method, path, params, None, use_auth)
+            .await?;
+        let limit = self.config.lock().unwrap().max_response_bytes;
+        if let Some(limit) = limit.filter(|&limit| res.content_length() > Some(limit as u64)) {
+            return Err(Error::ResponseTooLarge(limit));
+        }
+        tokio::spawn(async move {
+            let mut read = 0;
+            loop {
+                let chunk = match res.chunk().await {
+                    Ok(Some(chunk)) => chunk,
+                    Ok(None) => return,
+                    Err(err) => {
+                        let _ = tx.send(Err(io::Error::other(err))).await;
+                        return;
+                    }
+                };
+                read += chunk.len();
+                let chunk = match limit {
+                    Some(limit) if read > limit => {
+                        Err(io::Error::other(Error::ResponseTooLarge(limit)))
+                    }
+                    _ => Ok(chunk.to_vec()),
+                };
+                let failed = chunk.is_err();
+                // the reader is dropped when the parsing stops
+                if tx.send(chunk).await.is_err() || failed {
+                    return;
+                }
+            }
+        });
+        Ok(reader)
+    }
+
+    /// Send a request to the API and get the raw body, without decoding it as text.
+    ///
+    /// The responses are not cached. The inspector gets the body decoded as UTF-8 lossily.
+    pub async fn request_and_get_bytes(
+        &self,
+        method: Method,
+        path: &str,
+        params: Option<&Params<'_>>,
+        use_auth: bool,
+    ) -> Result<Vec<u8>> {
+        if let Some(responses) = &self.offline {
+            let body = responses.lock().unwrap().get(path).cloned();
+            return body.map(String::into_bytes).ok_or(Error::Offline);
+        }
+
+        let (res, request) = self
+            .request_inner(method, path, params, None, use_auth)
+            .await?;
+        let status = res.status();
+        let data = self.read_bytes(res).await?;
+        if let Some(request) = request {
+            let body = String::from_utf8_lossy(&data).into_owned();
+            self.inspect(&request, &ResponseInfo { status, body });
+        }
         Ok(data)
     }
 
     /// Send a request to the API.
+    ///
+    /// A private request rejected with `401 Unauthorized` because of its nonce (e.g. another
+    /// instance sharing the key has already used a newer one) is retried once with a fresh nonce.
     pub async fn request(
-        &mut self,
+        &self,
         method: Method,
         path: &str,
         params: Option<&Params<'_>>,
         use_auth: bool,
     ) -> Result<reqwest::Response> {
-        self.last_request_time = Instant::now();
+        let (res, _) = self
+            .request_inner(method, path, params, None, use_auth)
+            .await?;
+        Ok(res)
+    }
+
+    /// Send a request to the API. The [`RequestInfo`] is returned only if an inspector is set.
+    async fn request_inner(
+        &self,
+        method: Method,
+        path: &str,
+        params: Option<&Params<'_>>,
+        body: Option<&str>,
+        use_auth: bool,
+    ) -> Result<(reqwest::Response, Option<RequestInfo>)> {
+        if self.is_offline() {
+            return Err(Error::Offline);
+        }
 
-        let url = API_BASE.to_owned() + path;
+        *self.last_request_time.lock().unwrap() = Instant::now();
 
-        let url = if let Some(params) = params {
-            Url::parse_with_params(&url, params).unwrap()
-        } else {
-            Url::parse(&url).unwrap()
+        let base_url = self.config.lock().unwrap().base_url.clone();
+        let (url, params_body) = Client::url_and_body(&base_url, &method, path, params)?;
+        let body = body.or(params_body.as_deref());
+
+        let policy = self.retry_policy.lock().unwrap().clone();
+        let mut attempt = 0;
+        loop {
+            let err = match self.request_once(&method, &url, body, use_auth).await {
+                Ok(res) => return Ok(res),
+                Err(err) => err,
+            };
+            attempt += 1;
+            match policy.retry_after(&method, path, &err, attempt) {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => {
+                    if attempt > 1 {
+                        tracing::warn!(path, attempts = attempt, "gave up retrying: {}", err);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    /// Build the URL of a request. The parameters are in the query string of a `GET` request,
+    /// and in the JSON body of the other requests, sorted by key.
+    fn url_and_body(
+        base_url: &str,
+        method: &Method,
+        path: &str,
+        params: Option<&Params<'_>>,
+    ) -> Result<(Url, Option<String>)> {
+        let url = base_url.trim_end_matches('/').to_owned() + path;
+        let invalid_url = |err| Error::InvalidParam(format!("invalid URL {}: {}", url, err));
+        match params {
+            Some(params) if *method == Method::GET => {
+                let url = Url::parse_with_params(&url, params).map_err(invalid_url)?;
+                Ok((url, None))
+            }
+            Some(params) => {
+                let params: BTreeMap<_, _> = params.iter().collect();
+                let url = Url::parse(&url).map_err(invalid_url)?;
+                Ok((url, Some(serde_json::to_string(&params)?)))
+            }
+            None => Ok((Url::parse(&url).map_err(invalid_url)?, None)),
+        }
+    }
+
+    /// Send a request once, except for the retry on a nonce-related `401 Unauthorized`.
+    async fn request_once(
+        &self,
+        method: &Method,
+        url: &Url,
+        body: Option<&str>,
+        use_auth: bool,
+    ) -> Result<(reqwest::Response, Option<RequestInfo>)> {
+        let (res, request) = self.send(method, url, body, use_auth).await?;
+        if use_auth && res.status() == StatusCode::UNAUTHORIZED {
+            let status = res.status();
+            let text = self.read_text(res).await?;
+            if let Some(request) = &request {
+                let response = ResponseInfo {
+                    status,
+                    body: text.clone(),
+                };
+                self.inspect(request, &response);
+            }
+            if !Client::is_nonce_error(&text) {
+                let source = Box::new(Client::api_error(status, &text));
+                return Err(Error::Auth { source });
+            }
+
+            let (res, request) = self.send(method, url, body, use_auth).await?;
+            let res = self.check_status(res, request.as_ref()).await?;
+            return Ok((res, request));
+        }
+
+        let res = self.check_status(res, request.as_ref()).await?;
+
+        Ok((res, request))
+    }
+
+    /// Return an error for an error status, passing the response body to the inspector.
+    ///
+    /// The error is [`Error::Api`] with the `error` message of a JSON body, or [`Error::Http`]
+    /// with the status.
+    async fn check_status(
+        &self,
+        res: reqwest::Response,
+        request: Option<&RequestInfo>,
+    ) -> Result<reqwest::Response> {
+        let err = match res.error_for_status_ref() {
+            Ok(_) => return Ok(res),
+            Err(err) => err,
+        };
+
+        let status = res.status();
+        let now = Utc::now();
+        let retry_after = res
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| retry::parse_retry_after(v, now));
+
+        let body = self.read_text(res).await.unwrap_or_default();
+        if let Some(request) = request {
+            self.inspect(
+                request,
+                &ResponseInfo {
+                    status,
+                    body: body.clone(),
+                },
+            );
+        }
+
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(Error::RateLimited { retry_after });
+        }
+        if status == StatusCode::SERVICE_UNAVAILABLE {
+            if let Some(until) = Client::maintenance_until(&body) {
+                let until = until.or_else(|| {
+                    let delay = chrono::Duration::from_std(retry_after?).ok()?;
+                    Some(now + delay)
+                });
+                return Err(Error::Maintenance { until });
+            }
+        }
+        let err = match Client::api_error_message(&body) {
+            Some(_) => Client::api_error(status, &body),
+            None => Error::from(err),
+        };
+        if status == StatusCode::UNAUTHORIZED {
+            return Err(Error::Auth {
+                source: Box::new(err),
+            });
+        }
+        Err(err)
+    }
+
+    /// Whether the body is a JSON object with `success: false`.
+    fn is_unsuccessful(body: &str) -> bool {
+        serde_json::from_str::<serde_json::Value>(body)
+            .is_ok_and(|v| v.get("success") == Some(&serde_json::Value::Bool(false)))
+    }
+
+    /// Parse the JSON body of a `503 Service Unavailable` during a maintenance.
+    ///
+    /// Returns `None` if the body isn't a JSON object, otherwise the end of the maintenance if
+    /// any of the known keys has an RFC 3339 time or UNIX seconds.
+    fn maintenance_until(body: &str) -> Option<Option<DateTime<Utc>>> {
+        const KEYS: [&str; 4] = ["until", "end_at", "ends_at", "expected_end_at"];
+
+        let value: serde_json::Value = serde_json::from_str(body).ok()?;
+        let object = value.as_object()?;
+        // the details may be nested, e.g. `{"maintenance": {"end_at": ...}}`
+        let nested = object.values().filter_map(|v| v.as_object());
+        let until = std::iter::once(object)
+            .chain(nested)
+            .flat_map(|object| KEYS.iter().filter_map(move |key| object.get(*key)))
+            .find_map(|v| match v {
+                serde_json::Value::String(s) => DateTime::parse_from_rfc3339(s)
+                    .map(|t| t.with_timezone(&Utc))
+                    .ok()
+                    .or_else(|| Utc.timestamp_opt(s.parse().ok()?, 0).single()),
+                serde_json::Value::Number(n) => Utc.timestamp_opt(n.as_i64()?, 0).single(),
+                _ => None,
+            });
+        Some(until)
+    }
+
+    /// Read the response body, up to the maximum size if set.
+    async fn read_text(&self, res: reqwest::Response) -> Result<String> {
+        let limit = self.config.lock().unwrap().max_response_bytes;
+        if limit.is_none() {
+            return Ok(res.text().await?);
+        }
+        let body = self.read_bytes(res).await?;
+        Ok(String::from_utf8_lossy(&body).into_owned())
+    }
+
+    /// Read the response body, up to the size limit.
+    async fn read_bytes(&self, mut res: reqwest::Response) -> Result<Vec<u8>> {
+        let limit = self.config.lock().unwrap().max_response_bytes;
+        let limit = match limit {
+            Some(limit) => limit,
+            None => {
+                let body = res.bytes().await?;
+                return Ok(body.to_vec());
+            }
         };
+
+        if res.content_length().is_some_and(|len| len > limit as u64) {
+            return Err(Error::ResponseTooLarge(limit));
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = res.chunk().await? {
+            if body.len() + chunk.len() > limit {
+                return Err(Error::ResponseTooLarge(limit));
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    }
+
+    /// Send a request with freshly generated authentication headers.
+    async fn send(
+        &self,
+        method: &Method,
+        url: &Url,
+        body: Option<&str>,
+        use_auth: bool,
+    ) -> Result<(reqwest::Response, Option<RequestInfo>)> {
+        // Wait before signing, so that the nonces are sent in increasing order.
+        let rate_limit = self.config.lock().unwrap().rate_limit;
+        if let Some(limit) = rate_limit {
+            self.rate_limiter.acquire(&limit).await;
+        }
+
         let mut headers = HeaderMap::new();
         if use_auth {
-            self.set_auth_headers(&mut headers, &url).unwrap()
+            self.set_auth_headers(&mut headers, url, body).await?
         }
 
         const CONTENT_TYPE_VALUE_JSON: &str = "application/json";
@@ -133,17 +1155,803 @@ impl Client {
             headers.insert(CONTENT_TYPE, CONTENT_TYPE_VALUE_JSON.parse().unwrap());
         }
 
-        let res = match method {
-            Method::GET => self.client.get(url).headers(headers).send().await,
-            Method::POST => self.client.post(url).headers(headers).send().await,
-            Method::DELETE => self.client.delete(url).headers(headers).send().await,
+        let request = if self.inspector.lock().unwrap().is_some() {
+            Some(RequestInfo {
+                method: method.clone(),
+                url: url.to_string(),
+                headers: Client::redact_headers(&headers),
+            })
+        } else {
+            None
+        };
+
+        let client = self.client.lock().unwrap().clone();
+        let timeout = self.config.lock().unwrap().endpoint_timeout(url.path());
+        let url = url.clone();
+        let builder = match *method {
+            Method::GET => client.get(url),
+            Method::POST => client.post(url),
+            Method::DELETE => client.delete(url),
             _ => {
-                return Err(anyhow!("unsupported http method type"));
+                return Err(Error::InvalidParam(format!(
+                    "unsupported HTTP method: {}",
+                    method
+                )));
             }
-        }?;
+        };
+        let mut builder = builder.headers(headers);
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(body) = body {
+            builder = builder.body(body.to_owned());
+        }
 
-        res.error_for_status_ref()?;
+        let sent_at = Utc::now();
+        let res = builder.send().await?;
 
-        Ok(res)
+        self.record_server_date(res.headers(), sent_at);
+
+        Ok((res, request))
+    }
+
+    /// Copy the headers with the authentication keys redacted.
+    fn redact_headers(headers: &HeaderMap) -> HeaderMap {
+        const REDACTED: &str = "[REDACTED]";
+        let mut headers = headers.clone();
+        for name in [Header::KEY, Header::SIGNATURE] {
+            if headers.contains_key(name) {
+                headers.insert(name, HeaderValue::from_static(REDACTED));
+            }
+        }
+        headers
+    }
+
+    /// Whether a `401 Unauthorized` response body is caused by the nonce.
+    fn is_nonce_error(body: &str) -> bool {
+        body.to_ascii_lowercase().contains("nonce")
+    }
+
+    /// Parse a failed response body into [`Error::Api`], with the `error` message of a JSON body
+    /// or the whole body if there is none, and the `code` or `error_code` if any.
+    fn api_error(status: StatusCode, body: &str) -> Error {
+        let value = serde_json::from_str::<serde_json::Value>(body).unwrap_or_default();
+        let code = ["code", "error_code"]
+            .iter()
+            .find_map(|key| match value.get(key)? {
+                serde_json::Value::String(code) => Some(code.clone()),
+                serde_json::Value::Number(code) => Some(code.to_string()),
+                _ => None,
+            });
+        let message = match value.get("error") {
+            Some(serde_json::Value::String(message)) => message.clone(),
+            _ => body.to_owned(),
+        };
+        Error::Api {
+            status,
+            code,
+            message,
+        }
+    }
+
+    /// Get the `error` message of a JSON response body, if any.
+    fn api_error_message(body: &str) -> Option<String> {
+        serde_json::from_str::<serde_json::Value>(body)
+            .ok()?
+            .get("error")?
+            .as_str()
+            .map(str::to_owned)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    use tokio::task::JoinHandle;
+
+    #[test]
+    fn maintenance_body() {
+        let until = Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap();
+        assert_eq!(
+            Client::maintenance_until(
+                r#"{"success": false, "until": "2024-01-01T18:00:00+09:00"}"#
+            ),
+            Some(Some(until))
+        );
+        assert_eq!(
+            Client::maintenance_until(r#"{"maintenance": {"end_at": 1704099600}}"#),
+            Some(Some(until))
+        );
+        assert_eq!(
+            Client::maintenance_until(r#"{"error": "maintenance"}"#),
+            Some(None)
+        );
+        assert_eq!(Client::maintenance_until("<html>503</html>"), None);
+
+        let err = Error::Maintenance { until: Some(until) };
+        assert_eq!(
+            err.to_string(),
+            "under maintenance until 2024-01-01T09:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn reset_keeps_keys() {
+        let client = Client::shared_new("hoge".to_owned().into(), "fuga".to_owned().into());
+        client.reset();
+        assert_eq!(client.access_key.as_deref(), Some("hoge"));
+        assert_eq!(client.secret_key.as_deref(), Some("fuga"));
+    }
+
+    #[tokio::test]
+    async fn timeout_error() {
+        // accepts the connection but never answers the TLS handshake
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _conn = listener.accept().await;
+            std::future::pending::<()>().await;
+        });
+
+        let config = ClientConfig {
+            base_url: format!("https://{}", addr),
+            timeout: Some(Duration::from_millis(100)),
+            rate_limit: None,
+            ..Default::default()
+        };
+        let client = Client::shared_with_config(None, None, config);
+        let err = client
+            .request_and_get_text(Method::GET, "/api/ticker", None, false)
+            .await
+            .unwrap_err();
+        assert!(matches!(&err, Error::Timeout));
+    }
+
+    #[tokio::test]
+    async fn coalesce_gets() {
+        // counts the connections, and never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connections = Arc::new(Mutex::new(Vec::new()));
+        let accepted = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((conn, _)) = listener.accept().await {
+                accepted.lock().unwrap().push(conn);
+            }
+        });
+
+        let config = ClientConfig {
+            base_url: format!("https://{}", addr),
+            timeout: Some(Duration::from_millis(200)),
+            rate_limit: None,
+            coalesce_gets: true,
+            ..Default::default()
+        };
+        let client = Client::shared_with_config(None, None, config);
+        let mut params = Params::new();
+        params.insert("pair", "btc_jpy");
+        let requests = (0..10)
+            .map(|_| client.request_and_get_text(Method::GET, "/api/ticker", Some(&params), false));
+        for result in futures_util::future::join_all(requests).await {
+            let err = result.unwrap_err();
+            assert!(matches!(&err, Error::Timeout));
+        }
+        assert_eq!(connections.lock().unwrap().len(), 1);
+    }
+
+    /// Answer one request on a local plain HTTP server with `status` (e.g. `200 OK`) and `body`,
+    /// and get the base URL, and the request head (lowercased) and body.
+    pub(crate) async fn serve_once(
+        status: &'static str,
+        body: &'static str,
+    ) -> (String, JoinHandle<(String, String)>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut head = Vec::new();
+            while !head.ends_with(b"\r\n\r\n") {
+                head.push(conn.read_u8().await.unwrap());
+            }
+            let head = String::from_utf8(head).unwrap().to_lowercase();
+            let length = head
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length: "))
+                .map_or(0, |len| len.parse().unwrap());
+            let mut request_body = vec![0; length];
+            conn.read_exact(&mut request_body).await.unwrap();
+
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            conn.write_all(response.as_bytes()).await.unwrap();
+            (head, String::from_utf8(request_body).unwrap())
+        });
+        (format!("http://{}", addr), server)
+    }
+
+    /// Get a header from a request head got by [`serve_once`].
+    fn header_of(head: &str, name: &str) -> String {
+        let prefix = format!("{}: ", name.to_lowercase());
+        let line = head.lines().find(|line| line.starts_with(&prefix)).unwrap();
+        line[prefix.len()..].to_owned()
+    }
+
+    #[tokio::test]
+    async fn custom_base_url() {
+        let (base_url, server) = serve_once("200 OK", r#"{"success":true}"#).await;
+        let config = ClientConfig {
+            base_url: format!("{}/coincheck/", base_url),
+            rate_limit: None,
+            ..Default::default()
+        };
+        let client = Client::shared_with_config(Some("key".into()), Some("secret".into()), config);
+        let body = client
+            .request_and_get_text(Method::GET, "/api/accounts/balance", None, true)
+            .await
+            .unwrap();
+        assert_eq!(body, r#"{"success":true}"#);
+
+        let (head, _) = server.await.unwrap();
+        assert!(head.starts_with("get /coincheck/api/accounts/balance http/1.1\r\n"));
+        // signed with the full URL of the custom host
+        let url = format!("{}/coincheck/api/accounts/balance", base_url);
+        let nonce = header_of(&head, Header::NONCE);
+        let signature = Client::sign("secret", &nonce, &url, "").unwrap();
+        assert_eq!(header_of(&head, Header::SIGNATURE), signature);
+        assert_eq!(header_of(&head, Header::KEY), "key");
+
+        // invalid base URL
+        let config = ClientConfig {
+            base_url: "not a url".to_owned(),
+            ..Default::default()
+        };
+        let client = Client::shared_with_config(None, None, config);
+        assert!(client
+            .request_and_get_text(Method::GET, "/api/ticker", None, false)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn plain_http_only_to_loopback() {
+        assert!(Client::is_loopback_http("http://127.0.0.1:8080"));
+        assert!(Client::is_loopback_http("http://localhost/coincheck"));
+        assert!(Client::is_loopback_http("http://[::1]:8080"));
+        assert!(!Client::is_loopback_http("http://coincheck.com"));
+        assert!(!Client::is_loopback_http("http://192.168.0.1"));
+        assert!(!Client::is_loopback_http("https://127.0.0.1"));
+        assert!(!Client::is_loopback_http("not a url"));
+
+        // refused before anything is sent
+        let config = ClientConfig {
+            base_url: "http://192.0.2.1".to_owned(),
+            rate_limit: None,
+            ..Default::default()
+        };
+        let client = Client::shared_with_config(Some("key".into()), Some("secret".into()), config);
+        let err = client
+            .request_and_get_text(Method::GET, "/api/accounts/balance", None, true)
+            .await
+            .unwrap_err();
+        assert!(matches!(&err, Error::Http(err) if err.is_builder()));
+    }
+
+    #[test]
+    fn params_in_body() {
+        let mut params = Params::new();
+        params.insert("pair", "btc_jpy");
+        params.insert("order_type", "buy");
+        params.insert("rate", "3000000");
+        params.insert("amount", "0.01");
+        let base_url = "https://coincheck.com";
+
+        let (url, body) =
+            Client::url_and_body(base_url, &Method::GET, "/api/ticker", Some(&params)).unwrap();
+        assert_eq!(url.path(), "/api/ticker");
+        assert_eq!(url.query_pairs().count(), 4);
+        assert_eq!(body, None);
+
+        let path = "/api/exchange/orders";
+        let (url, body) =
+            Client::url_and_body(base_url, &Method::POST, path, Some(&params)).unwrap();
+        let body = body.unwrap();
+        assert_eq!(url.as_str(), "https://coincheck.com/api/exchange/orders");
+        assert_eq!(
+            body,
+            r#"{"amount":"0.01","order_type":"buy","pair":"btc_jpy","rate":"3000000"}"#
+        );
+        assert_eq!(
+            Client::sign("secret", "1", url.as_str(), &body).unwrap(),
+            "9a81c965eecb60fd6fe841f6ef3fab57e09d3a6be0e7d5a3a9c6ad794032502f"
+        );
+    }
+
+    #[tokio::test]
+    async fn post_params_signed_in_body() {
+        let (base_url, server) = serve_once("200 OK", r#"{"success":true}"#).await;
+        let config = ClientConfig {
+            base_url: base_url.clone(),
+            rate_limit: None,
+            ..Default::default()
+        };
+        let client = Client::shared_with_config(Some("key".into()), Some("secret".into()), config);
+        let mut params = Params::new();
+        params.insert("pair", "btc_jpy");
+        params.insert("order_type", "buy");
+        client
+            .request_and_get_text(Method::POST, "/api/exchange/orders", Some(&params), true)
+            .await
+            .unwrap();
+
+        let (head, body) = server.await.unwrap();
+        assert!(head.starts_with("post /api/exchange/orders http/1.1\r\n"));
+        assert_eq!(header_of(&head, CONTENT_TYPE.as_str()), "application/json");
+        assert_eq!(body, r#"{"order_type":"buy","pair":"btc_jpy"}"#);
+        let url = format!("{}/api/exchange/orders", base_url);
+        let nonce = header_of(&head, Header::NONCE);
+        let signature = Client::sign("secret", &nonce, &url, &body).unwrap();
+        assert_eq!(header_of(&head, Header::SIGNATURE), signature);
+    }
+
+    #[tokio::test]
+    async fn json_with_meta() {
+        #[derive(Debug, PartialEq, Deserialize, Serialize)]
+        struct Response {
+            success: bool,
+        }
+        impl ApiResponse for Response {}
+
+        let (base_url, server) = serve_once("200 OK", r#"{"success":true}"#).await;
+        let config = ClientConfig {
+            base_url,
+            rate_limit: None,
+            ..Default::default()
+        };
+        let client = Client::shared_with_config(None, None, config);
+        let meta: WithMeta<Response> = client
+            .request_and_get_json_with_meta(Method::GET, "/api/ticker", None, false)
+            .await
+            .unwrap();
+        server.await.unwrap();
+        assert_eq!(meta.value, Response { success: true });
+        assert_eq!(meta.status, StatusCode::OK);
+        assert!(meta.latency > Duration::ZERO);
+        // the mock server doesn't send the `Date` header
+        assert_eq!(meta.server_date, None);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(DATE, "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap());
+        assert_eq!(
+            Client::server_date(&headers),
+            Some(Utc.with_ymd_and_hms(2015, 10, 21, 7, 28, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn config_round_trip() {
+        let config = ClientConfig {
+            timeout: Some(Duration::from_secs(10)),
+            connect_timeout: None,
+            endpoint_timeouts: BTreeMap::from([(
+                "/api/exchange/orders".to_owned(),
+                Duration::from_secs(1),
+            )]),
+            user_agent: Some("coinchecker-test".to_owned()),
+            max_response_bytes: Some(1 << 20),
+            max_order_notional: Some(100_000 as PriceType),
+            rate_limit: None,
+            retry: Some(RetryGets {
+                max_retries: 3,
+                backoff: Default::default(),
+            }),
+            ..Default::default()
+        };
+        let json = config.to_json().unwrap();
+        assert_eq!(ClientConfig::from_json(&json).unwrap(), config);
+        assert_eq!(
+            ClientConfig::from_json("{}").unwrap(),
+            ClientConfig::default()
+        );
+
+        let client = Client::shared_with_config(None, None, config.clone());
+        assert_eq!(client.config(), config);
+
+        client.set_retry_policy(Box::new(NoRetry));
+        assert_eq!(client.config().retry, None);
+    }
+
+    #[tokio::test]
+    async fn offline_bytes() {
+        let client = Client::shared_offline();
+        let err = client
+            .request_and_get_bytes(Method::GET, "/api/ticker", None, false)
+            .await
+            .unwrap_err();
+        assert!(matches!(&err, Error::Offline));
+
+        client.set_offline_response("/api/ticker", "{}");
+        let body = client
+            .request_and_get_bytes(Method::GET, "/api/ticker", None, false)
+            .await
+            .unwrap();
+        assert_eq!(body, b"{}");
+    }
+
+    #[test]
+    fn endpoint_timeout_by_longest_prefix() {
+        let client = Client::shared_new(None, None);
+        client.set_endpoint_timeout("/api/exchange/orders", Some(Duration::from_secs(1)));
+        client.set_endpoint_timeout(
+            "/api/exchange/orders/transactions",
+            Some(Duration::from_secs(30)),
+        );
+
+        let config = client.config();
+        assert_eq!(
+            config.endpoint_timeout("/api/exchange/orders"),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(
+            config.endpoint_timeout("/api/exchange/orders/transactions_pagination"),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(config.endpoint_timeout("/api/ticker"), None);
+
+        client.set_endpoint_timeout("/api/exchange/orders/transactions", None);
+        assert_eq!(
+            client
+                .config()
+                .endpoint_timeout("/api/exchange/orders/transactions"),
+            Some(Duration::from_secs(1))
+        );
+    }
+
+    #[tokio::test]
+    async fn redact_auth_headers() {
+        let client = Client::shared_new("hoge".to_owned().into(), "fuga".to_owned().into());
+        let url = Url::parse(API_BASE).unwrap();
+        let mut headers = HeaderMap::new();
+        client
+            .set_auth_headers(&mut headers, &url, None)
+            .await
+            .unwrap();
+
+        let redacted = Client::redact_headers(&headers);
+        assert_eq!(redacted[Header::KEY], "[REDACTED]");
+        assert_eq!(redacted[Header::SIGNATURE], "[REDACTED]");
+        assert_eq!(redacted[Header::NONCE], headers[Header::NONCE]);
+    }
+
+    #[test]
+    fn inspector_is_called() {
+        let client = Client::shared_new(None, None);
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let sink = calls.clone();
+        client.set_inspector(Box::new(move |req, res| {
+            sink.lock()
+                .unwrap()
+                .push((req.url.clone(), res.body.clone()));
+        }));
+
+        let request = RequestInfo {
+            method: Method::GET,
+            url: API_BASE.to_owned(),
+            headers: HeaderMap::new(),
+        };
+        let response = ResponseInfo {
+            status: StatusCode::OK,
+            body: "{}".to_owned(),
+        };
+        client.inspect(&request, &response);
+        client.clear_inspector();
+        client.inspect(&request, &response);
+
+        let calls = calls.lock().unwrap();
+        assert_eq!(*calls, vec![(API_BASE.to_owned(), "{}".to_owned())]);
+    }
+
+    /// A clock that returns the time set by the test.
+    struct FakeClock(Arc<Mutex<SystemTime>>);
+
+    impl Clock for FakeClock {
+        fn now(&self) -> SystemTime {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    #[test]
+    fn nonce_strictly_increases() {
+        let time = Arc::new(Mutex::new(
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+        ));
+        let client = Client::shared_new(None, None);
+        client.set_clock(Box::new(FakeClock(time.clone())));
+
+        let nonce = |client: &Client| client.get_nonce().unwrap().parse::<u128>().unwrap();
+
+        // Fixed clock
+        let first = nonce(&client);
+        assert_eq!(first, 1_600_000_000_000_000);
+        assert_eq!(nonce(&client), first + 1);
+        assert_eq!(nonce(&client), first + 2);
+
+        // Advancing clock
+        *time.lock().unwrap() += Duration::from_secs(1);
+        assert_eq!(nonce(&client), first + 1_000_000);
+
+        // Clock going back
+        *time.lock().unwrap() -= Duration::from_secs(10);
+        assert_eq!(nonce(&client), first + 1_000_001);
+    }
+
+    #[test]
+    fn nonce_unique_across_threads() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let client = Client::shared_new(None, None);
+        client.set_clock(Box::new(FakeClock(Arc::new(Mutex::new(time)))));
+
+        let nonces: Vec<String> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        (0..100)
+                            .map(|_| client.get_nonce().unwrap())
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            threads
+                .into_iter()
+                .flat_map(|thread| thread.join().unwrap())
+                .collect()
+        });
+        let unique: std::collections::HashSet<_> = nonces.iter().collect();
+        assert_eq!(unique.len(), nonces.len());
+    }
+
+    /// An in-process counter standing in for a shared store.
+    struct CounterNonce(Arc<std::sync::atomic::AtomicU64>);
+
+    impl SharedNonceProvider for CounterNonce {
+        fn next_nonce(&self) -> BoxFuture<'_, u128> {
+            let nonce = self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+            Box::pin(async move { Ok(nonce as u128) })
+        }
+    }
+
+    #[tokio::test]
+    async fn shared_nonce_provider() {
+        let counter = Arc::new(std::sync::atomic::AtomicU64::new(0));
+        let replicas: Vec<_> = (0..2)
+            .map(|_| {
+                let client = Client::shared_new("hoge".to_owned().into(), "fuga".to_owned().into());
+                client.set_nonce_provider(Box::new(CounterNonce(counter.clone())));
+                client
+            })
+            .collect();
+
+        let url = Url::parse(API_BASE).unwrap();
+        let mut nonces = Vec::new();
+        for _ in 0..3 {
+            for client in &replicas {
+                let mut headers = HeaderMap::new();
+                client
+                    .set_auth_headers(&mut headers, &url, None)
+                    .await
+                    .unwrap();
+                nonces.push(
+                    headers[Header::NONCE]
+                        .to_str()
+                        .unwrap()
+                        .parse::<u128>()
+                        .unwrap(),
+                );
+            }
+        }
+        assert_eq!(nonces, (1..=6).collect::<Vec<u128>>());
+    }
+
+    #[test]
+    fn signature_vectors() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            Client::get_signature("Jefe", "what do ya want for nothing?").unwrap(),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+
+        assert_eq!(
+            Client::sign(
+                "fuga",
+                "1423020200000000",
+                "https://coincheck.com/api/accounts/balance",
+                ""
+            )
+            .unwrap(),
+            "41fbf3a6b3b21edd5263b8197ffd33513d212e3964fcbb603d6c9c6d6ea87b6d"
+        );
+        assert_eq!(
+            Client::sign(
+                "secret",
+                "1",
+                "https://coincheck.com/api/exchange/orders?amount=0.01&order_type=buy&pair=btc_jpy&rate=3000000",
+                ""
+            )
+            .unwrap(),
+            "c3a958b69e9bd4797ff33dd8fc7e02977fe1e9f2d80eb31682a5c8702e55e658"
+        );
+
+        // the body is appended to the message
+        let url = "https://coincheck.com/api/send_money";
+        let body = r#"{"address":"1Gp9MCp7FWqNgaUWdiUiRPjGqNVdqug2hY","amount":"0.0002"}"#;
+        assert_eq!(
+            Client::sign("secret", "1", url, body).unwrap(),
+            Client::get_signature("secret", &format!("1{}{}", url, body)).unwrap()
+        );
+        assert_ne!(
+            Client::sign("secret", "1", url, body).unwrap(),
+            Client::sign("secret", "1", url, "").unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn reject_unsuccessful_response() {
+        #[derive(Deserialize, Serialize)]
+        struct Response {
+            success: bool,
+        }
+        impl ApiResponse for Response {
+            fn success(&self) -> bool {
+                self.success
+            }
+        }
+
+        let client = Client::shared_offline();
+        client.set_offline_response("/api/ok", r#"{"success":true}"#);
+        client.set_offline_response("/api/ng", r#"{"success":false,"error":"invalid"}"#);
+
+        let ok: Result<Response> = client
+            .request_and_get_json(Method::GET, "/api/ok", None, false)
+            .await;
+        assert!(ok.is_ok());
+        let err = client
+            .request_and_get_json::<Response>(Method::GET, "/api/ng", None, false)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            &err, Error::Api { status: StatusCode::OK, code: None, message } if message == "invalid"
+        ));
+
+        // without the other fields of the model
+        #[derive(Deserialize, Serialize)]
+        struct Cancelled {
+            success: bool,
+            id: u64,
+        }
+        impl ApiResponse for Cancelled {}
+        let err = client
+            .request_and_get_json::<Cancelled>(Method::GET, "/api/ng", None, false)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            &err, Error::Api { message, .. } if message == "invalid"
+        ));
+    }
+
+    #[tokio::test]
+    async fn error_status_message() {
+        let body = r#"{"success":false,"error":"Amount 0.001 is less than the minimum","code":"amount_too_small"}"#;
+        let (base_url, server) = serve_once("400 Bad Request", body).await;
+        let config = ClientConfig {
+            base_url,
+            rate_limit: None,
+            ..Default::default()
+        };
+        let client = Client::shared_with_config(None, None, config);
+        let err = client
+            .request_and_get_text(Method::POST, "/api/exchange/orders", None, false)
+            .await
+            .unwrap_err();
+        server.await.unwrap();
+        match &err {
+            Error::Api {
+                status,
+                code,
+                message,
+            } => {
+                assert_eq!(*status, StatusCode::BAD_REQUEST);
+                assert_eq!(code.as_deref(), Some("amount_too_small"));
+                assert_eq!(message, "Amount 0.001 is less than the minimum");
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert_eq!(
+            err.to_string(),
+            "API error: Amount 0.001 is less than the minimum (amount_too_small)"
+        );
+
+        // without a JSON body
+        let (base_url, server) = serve_once("502 Bad Gateway", "<html></html>").await;
+        let config = ClientConfig {
+            base_url,
+            rate_limit: None,
+            ..Default::default()
+        };
+        let client = Client::shared_with_config(None, None, config);
+        let err = client
+            .request_and_get_text(Method::GET, "/api/ticker", None, false)
+            .await
+            .unwrap_err();
+        server.await.unwrap();
+        assert!(matches!(&err, Error::Http(_)));
+        assert_eq!(err.status(), Some(StatusCode::BAD_GATEWAY));
+
+        // the status is typed over the message
+        let (base_url, server) =
+            serve_once("429 Too Many Requests", r#"{"error":"too many"}"#).await;
+        let config = ClientConfig {
+            base_url,
+            rate_limit: None,
+            ..Default::default()
+        };
+        let client = Client::shared_with_config(None, None, config);
+        let err = client
+            .request_and_get_text(Method::GET, "/api/ticker", None, false)
+            .await
+            .unwrap_err();
+        server.await.unwrap();
+        assert!(matches!(&err, Error::RateLimited { retry_after: None }));
+        assert_eq!(err.status(), Some(StatusCode::TOO_MANY_REQUESTS));
+    }
+
+    #[test]
+    fn detect_nonce_error() {
+        assert!(Client::is_nonce_error(
+            r#"{"success":false,"error":"Nonce must be incremented"}"#
+        ));
+        assert!(!Client::is_nonce_error(
+            r#"{"success":false,"error":"invalid authentication"}"#
+        ));
+    }
+
+    #[test]
+    fn record_server_date() {
+        let client = Client::shared_new(None, None);
+        assert!(client.last_server_date().is_none());
+        assert!(client.clock_skew().is_none());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(DATE, "Tue, 15 Nov 1994 08:12:31 GMT".parse().unwrap());
+        client.record_server_date(&headers, Utc::now());
+
+        let server_date = Utc.with_ymd_and_hms(1994, 11, 15, 8, 12, 31).unwrap();
+        assert_eq!(client.last_server_date(), Some(server_date));
+        assert!(client.clock_skew().unwrap() < chrono::Duration::zero());
+    }
+
+    #[test]
+    fn clock_skew_is_rolling_average() {
+        let mut server_time = ServerTime::default();
+        let local = Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap();
+        for skew in [1000, 3000] {
+            server_time.record(local + chrono::Duration::milliseconds(skew), local);
+        }
+        assert_eq!(
+            server_time.skew(),
+            Some(chrono::Duration::milliseconds(2000))
+        );
+
+        for _ in 0..ServerTime::WINDOW {
+            server_time.record(local, local);
+        }
+        assert_eq!(server_time.skew(), Some(chrono::Duration::zero()));
     }
 }
@@ -0,0 +1,290 @@
+use std::fmt;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+
+use crate::types::*;
+
+/// Errors of this library.
+///
+/// All the API methods return [`Result`] with this error, so match on it to handle the kinds of
+/// failures. A failed request is one of [`CoincheckError::Http`], [`CoincheckError::Api`] and
+/// [`CoincheckError::Deserialize`], unless a more specific variant applies (e.g.
+/// [`CoincheckError::Auth`], which keeps the [`CoincheckError::Api`] as its source).
+#[derive(Debug)]
+pub enum CoincheckError {
+    /// The request failed to be sent, or the response has an error status without a JSON error
+    /// body.
+    Http(reqwest::Error),
+    /// The API returned an error: `success: false`, or an error status with a JSON body.
+    /// `message` is the `error` of the body (the whole body if there is none), and `code` is the
+    /// `code` or `error_code` of the body if any. `status` is `200 OK` for `success: false` in a
+    /// successful response.
+    Api {
+        status: StatusCode,
+        code: Option<String>,
+        message: String,
+    },
+    /// The response doesn't match the model, or the parameters can't be serialized.
+    Deserialize(serde_json::Error),
+    /// Reading or writing a file (e.g. a WebSocket recording) or a connection failed.
+    Io(std::io::Error),
+    /// The WebSocket connection failed, or sent an unexpected message.
+    WebSocket(String),
+    /// The order is not in the open orders.
+    OrderNotFound(OrderId),
+    /// The response body is larger than the limit (bytes).
+    /// See [`Client::set_max_response_bytes`](crate::client::Client::set_max_response_bytes).
+    ResponseTooLarge(usize),
+    /// The bank account is not registered.
+    BankAccountNotFound(BankAccountId),
+    /// The client is offline and has no response for the request.
+    /// See [`Client::shared_offline`](crate::client::Client::shared_offline).
+    Offline,
+    /// The order notional or the withdrawal amount (JPY) exceeds the configured limit.
+    /// See [`Client::set_max_order_notional`](crate::client::Client::set_max_order_notional)
+    /// and [`Client::set_max_withdraw_amount`](crate::client::Client::set_max_withdraw_amount).
+    LimitExceeded {
+        limit: PriceType,
+        requested: PriceType,
+    },
+    /// The API returned `503 Service Unavailable` with a JSON body, during a maintenance.
+    /// `until` is the expected end of the maintenance, from the body or the `Retry-After` header.
+    Maintenance { until: Option<DateTime<Utc>> },
+    /// The withdrawal can't be cancelled, e.g. it's already being processed. `source` is the
+    /// error returned by the API.
+    /// See [`WithdrawsJpy::cancel_withdraw`](crate::private::withdraws_jpy::WithdrawsJpy::cancel_withdraw).
+    WithdrawNotCancellable {
+        id: WithdrawId,
+        source: Box<CoincheckError>,
+    },
+    /// The request parameters are invalid, detected before sending the request.
+    InvalidParam(String),
+    /// The API rejected the API key or the signature (`401 Unauthorized`). `source` is the error
+    /// of the response.
+    Auth { source: Box<CoincheckError> },
+    /// Too many requests (`429 Too Many Requests`), with the delay of the `Retry-After` header
+    /// if any. See [`Client::set_rate_limit`](crate::client::Client::set_rate_limit).
+    RateLimited { retry_after: Option<Duration> },
+    /// The request, the connection or a wait timed out.
+    /// See [`ClientConfig::timeout`](crate::client::ClientConfig::timeout).
+    Timeout,
+    /// Any other failure, e.g. of a [`SharedNonceProvider`](crate::client::SharedNonceProvider).
+    Other(Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// The former name of [`CoincheckError`].
+pub type Error = CoincheckError;
+
+/// A result of the operations of this library.
+pub type Result<T, E = CoincheckError> = std::result::Result<T, E>;
+
+impl CoincheckError {
+    /// Create an [`CoincheckError::Other`] error from an error or a message.
+    pub fn other(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        CoincheckError::Other(err.into())
+    }
+
+    /// The status of the response, if it was received.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            CoincheckError::Http(err) => err.status(),
+            CoincheckError::Api { status, .. } => Some(*status),
+            CoincheckError::Auth { source }
+            | CoincheckError::WithdrawNotCancellable { source, .. } => source.status(),
+            CoincheckError::RateLimited { .. } => Some(StatusCode::TOO_MANY_REQUESTS),
+            CoincheckError::Maintenance { .. } => Some(StatusCode::SERVICE_UNAVAILABLE),
+            _ => None,
+        }
+    }
+
+    /// The delay before retrying requested by the server: the `Retry-After` header of a
+    /// `429 Too Many Requests`, or the time until the end of a maintenance.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            CoincheckError::RateLimited { retry_after } => *retry_after,
+            CoincheckError::Maintenance { until: Some(until) } => {
+                Some((*until - Utc::now()).to_std().unwrap_or(Duration::ZERO))
+            }
+            _ => None,
+        }
+    }
+
+    /// Copy the error. The errors of the underlying libraries can't be copied, so they are
+    /// replaced with [`CoincheckError::Other`] with the same message.
+    pub(crate) fn copy(&self) -> CoincheckError {
+        use CoincheckError::*;
+
+        match self {
+            Http(_) | Deserialize(_) | Io(_) | Other(_) => CoincheckError::other(self.to_string()),
+            Api {
+                status,
+                code,
+                message,
+            } => Api {
+                status: *status,
+                code: code.clone(),
+                message: message.clone(),
+            },
+            WebSocket(message) => WebSocket(message.clone()),
+            OrderNotFound(id) => OrderNotFound(*id),
+            ResponseTooLarge(limit) => ResponseTooLarge(*limit),
+            BankAccountNotFound(id) => BankAccountNotFound(*id),
+            Offline => Offline,
+            LimitExceeded { limit, requested } => LimitExceeded {
+                limit: *limit,
+                requested: *requested,
+            },
+            Maintenance { until } => Maintenance { until: *until },
+            WithdrawNotCancellable { id, source } => WithdrawNotCancellable {
+                id: *id,
+                source: Box::new(source.copy()),
+            },
+            InvalidParam(message) => InvalidParam(message.clone()),
+            Auth { source } => Auth {
+                source: Box::new(source.copy()),
+            },
+            RateLimited { retry_after } => RateLimited {
+                retry_after: *retry_after,
+            },
+            Timeout => Timeout,
+        }
+    }
+}
+
+impl fmt::Display for CoincheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoincheckError::Http(err) => write!(f, "{}", err),
+            CoincheckError::Deserialize(err) => write!(f, "{}", err),
+            CoincheckError::Io(err) => write!(f, "{}", err),
+            CoincheckError::WebSocket(message) => write!(f, "{}", message),
+            CoincheckError::OrderNotFound(id) => {
+                write!(f, "order not found in the open orders: {}", id)
+            }
+            CoincheckError::ResponseTooLarge(limit) => {
+                write!(f, "response body exceeds the limit of {} bytes", limit)
+            }
+            CoincheckError::BankAccountNotFound(id) => {
+                write!(f, "bank account not registered: {}", id)
+            }
+            CoincheckError::Api {
+                code: Some(code),
+                message,
+                ..
+            } => write!(f, "API error: {} ({})", message, code),
+            CoincheckError::Api { message, .. } => write!(f, "API error: {}", message),
+            CoincheckError::Offline => write!(f, "the client is offline"),
+            CoincheckError::LimitExceeded { limit, requested } => {
+                write!(f, "{} JPY exceeds the limit of {} JPY", requested, limit)
+            }
+            CoincheckError::Maintenance { until: Some(until) } => {
+                write!(f, "under maintenance until {}", until.to_rfc3339())
+            }
+            CoincheckError::Maintenance { until: None } => write!(f, "under maintenance"),
+            CoincheckError::WithdrawNotCancellable { id, .. } => {
+                write!(
+                    f,
+                    "withdrawal can't be cancelled (already processed?): {}",
+                    id
+                )
+            }
+            CoincheckError::InvalidParam(message) => write!(f, "{}", message),
+            CoincheckError::Auth { .. } => write!(f, "authentication failed"),
+            CoincheckError::RateLimited {
+                retry_after: Some(delay),
+            } => write!(f, "rate limited, retry after {:?}", delay),
+            CoincheckError::RateLimited { retry_after: None } => write!(f, "rate limited"),
+            CoincheckError::Timeout => write!(f, "timed out"),
+            CoincheckError::Other(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for CoincheckError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        // the message is the one of the wrapped error, so skip it
+        match self {
+            CoincheckError::Http(err) => std::error::Error::source(err),
+            CoincheckError::Deserialize(err) => std::error::Error::source(err),
+            CoincheckError::Io(err) => std::error::Error::source(err),
+            CoincheckError::Other(err) => err.source(),
+            CoincheckError::Auth { source }
+            | CoincheckError::WithdrawNotCancellable { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for CoincheckError {
+    /// [`CoincheckError::Timeout`] for a timeout, otherwise [`CoincheckError::Http`].
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            CoincheckError::Timeout
+        } else {
+            CoincheckError::Http(err)
+        }
+    }
+}
+
+impl From<serde_json::Error> for CoincheckError {
+    fn from(err: serde_json::Error) -> Self {
+        CoincheckError::Deserialize(err)
+    }
+}
+
+impl From<std::io::Error> for CoincheckError {
+    fn from(err: std::io::Error) -> Self {
+        CoincheckError::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_and_source() {
+        let err = Error::OrderNotFound(OrderId(42));
+        assert_eq!(err.to_string(), "order not found in the open orders: 42");
+
+        let api = Error::Api {
+            status: StatusCode::UNAUTHORIZED,
+            code: None,
+            message: "invalid signature".to_owned(),
+        };
+        let err = Error::Auth {
+            source: Box::new(api),
+        };
+        assert_eq!(err.to_string(), "authentication failed");
+        assert_eq!(err.status(), Some(StatusCode::UNAUTHORIZED));
+        let source = std::error::Error::source(&err).unwrap();
+        assert_eq!(source.to_string(), "API error: invalid signature");
+
+        // converts into `anyhow::Error` in the applications
+        let err: anyhow::Error = Error::ResponseTooLarge(1024).into();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::ResponseTooLarge(1024))
+        ));
+    }
+
+    #[test]
+    fn copy_error() {
+        let err = Error::WithdrawNotCancellable {
+            id: WithdrawId(1),
+            source: Box::new(Error::Deserialize(
+                serde_json::from_str::<u32>("x").unwrap_err(),
+            )),
+        };
+        match err.copy() {
+            Error::WithdrawNotCancellable { id, source } => {
+                assert_eq!(id, WithdrawId(1));
+                assert!(matches!(*source, Error::Other(_)));
+                assert_eq!(source.to_string(), "expected value at line 1 column 1");
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! Exchange-agnostic traits.
+//!
+//! These traits let you write generic code over multiple exchanges and plug Coincheck in.
+//! The methods return boxed futures so that the traits can be used as trait objects
+//! (e.g. `Box<dyn ExchangePublic>`).
+
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::error::Result;
+use crate::private::account::model::Balance;
+use crate::private::order::model::{CancelResult, OpenOrders, OrderResultGeneral};
+use crate::private::Private;
+use crate::public::model::{OrderBooks, Ticker};
+use crate::public::Public;
+use crate::types::*;
+
+/// A boxed future returned by the exchange traits.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// Public market data of an exchange.
+pub trait ExchangePublic {
+    /// Get the ticker of the pair.
+    fn ticker<'a>(&'a mut self, pair: &'a CoinPair) -> BoxFuture<'a, Ticker>;
+
+    /// Get the order book of the pair.
+    fn order_book<'a>(&'a mut self, pair: &'a CoinPair) -> BoxFuture<'a, OrderBooks>;
+}
+
+/// Private account and order operations of an exchange.
+pub trait ExchangePrivate {
+    /// Get the account balance.
+    fn balance(&mut self) -> BoxFuture<'_, Balance>;
+
+    /// Get the open orders.
+    fn open_orders(&mut self) -> BoxFuture<'_, OpenOrders>;
+
+    /// Place a new limit order.
+    fn new_limit_order<'a>(
+        &'a mut self,
+        side: &'a BaseOrderType,
+        pair: &'a CoinPair,
+        rate: PriceType,
+        amount: PriceType,
+    ) -> BoxFuture<'a, OrderResultGeneral>;
+
+    /// Cancel the order.
+    fn cancel(&mut self, id: OrderId) -> BoxFuture<'_, CancelResult>;
+}
+
+impl ExchangePublic for Public {
+    fn ticker<'a>(&'a mut self, pair: &'a CoinPair) -> BoxFuture<'a, Ticker> {
+        Box::pin(self.ticker_for(pair))
+    }
+
+    fn order_book<'a>(&'a mut self, pair: &'a CoinPair) -> BoxFuture<'a, OrderBooks> {
+        Box::pin(self.order_book_for(pair))
+    }
+}
+
+impl ExchangePrivate for Private {
+    fn balance(&mut self) -> BoxFuture<'_, Balance> {
+        Box::pin(self.account.balance())
+    }
+
+    fn open_orders(&mut self) -> BoxFuture<'_, OpenOrders> {
+        Box::pin(self.order.opens())
+    }
+
+    fn new_limit_order<'a>(
+        &'a mut self,
+        side: &'a BaseOrderType,
+        pair: &'a CoinPair,
+        rate: PriceType,
+        amount: PriceType,
+    ) -> BoxFuture<'a, OrderResultGeneral> {
+        match side {
+            BaseOrderType::Buy => Box::pin(self.order.new_limit_buy(pair, rate, amount)),
+            BaseOrderType::Sell => Box::pin(self.order.new_limit_sell(pair, rate, amount)),
+        }
+    }
+
+    fn cancel(&mut self, id: OrderId) -> BoxFuture<'_, CancelResult> {
+        Box::pin(self.order.cancel(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Coincheck;
+
+    #[test]
+    fn usable_as_trait_objects() {
+        let coincheck = Coincheck::new_with_keys("hoge", "fuga");
+        let _: Box<dyn ExchangePublic> = Box::new(coincheck.public);
+        let _: Box<dyn ExchangePrivate> = Box::new(coincheck.private);
+    }
+}
@@ -54,22 +54,42 @@
 //! }
 //! ```
 
+mod cache;
 pub mod client;
+pub mod error;
+pub mod exchange;
+mod paginate;
 pub mod private;
 pub mod public;
+pub mod rate_limit;
+#[cfg(feature = "redis-nonce")]
+pub mod redis_nonce;
+pub mod retry;
+mod schema_drift;
+mod single_flight;
+pub mod tracker;
 pub mod types;
 pub mod utils;
+mod ws;
 
 use client::SharedClient;
 
-use crate::client::Client;
-use crate::private::account::Account;
-use crate::private::order::Order;
+use crate::client::{Client, ClientConfig};
+use crate::error::{Error, Result};
+use crate::private::account::{self, Account};
+use crate::private::order::{self, Order};
+use crate::private::withdraws_jpy::model::WithdrawalReadiness;
 use crate::private::withdraws_jpy::WithdrawsJpy;
 use crate::private::Private;
 use crate::public::Public;
+use crate::types::*;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::time::Instant;
 
+pub use tokio_util::sync::CancellationToken;
+
 /// A client for the Coincheck REST API.
 pub struct Coincheck {
     pub public: Public,
@@ -77,6 +97,38 @@ pub struct Coincheck {
     client: SharedClient,
 }
 
+/// Balance and open orders at a point in time, see [`Coincheck::risk_snapshot_json`].
+#[derive(Debug, Serialize)]
+pub struct RiskSnapshot {
+    /// Time when the snapshot was taken.
+    pub timestamp: DateTime<Utc>,
+    pub balance: account::model::Balance,
+    /// Sorted by `id`.
+    pub open_orders: Vec<order::model::OpenOrder>,
+}
+
+impl RiskSnapshot {
+    /// Serialize to JSON. Object keys are sorted, so the same snapshot always gives the same string.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(sort_keys(serde_json::to_value(self)?).to_string())
+    }
+}
+
+/// Sort the object keys, including the flattened other balances, whether or not serde_json keeps
+/// the insertion order (`preserve_order`).
+fn sort_keys(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => {
+            let sorted: BTreeMap<_, _> = map.into_iter().map(|(k, v)| (k, sort_keys(v))).collect();
+            serde_json::Value::Object(sorted.into_iter().collect())
+        }
+        serde_json::Value::Array(items) => {
+            serde_json::Value::Array(items.into_iter().map(sort_keys).collect())
+        }
+        other => other,
+    }
+}
+
 /// A client for the Coincheck REST API. Without API keys. Public API only.
 pub struct CoincheckNoAuth {
     pub public: Public,
@@ -89,7 +141,28 @@ impl Coincheck {
 
     /// Create a new instance.
     pub fn new_with_keys(access_key: &str, secret_key: &str) -> Coincheck {
-        let client = Client::shared_new(access_key.to_owned().into(), secret_key.to_owned().into());
+        Coincheck::new_with_config(access_key, secret_key, ClientConfig::default())
+    }
+
+    /// Create a new instance with the client settings.
+    pub fn new_with_config(access_key: &str, secret_key: &str, config: ClientConfig) -> Coincheck {
+        let client = Client::shared_with_config(
+            access_key.to_owned().into(),
+            secret_key.to_owned().into(),
+            config,
+        );
+        Coincheck::from_client(client)
+    }
+
+    /// Create a new instance that never sends a request, e.g. for a backtest.
+    ///
+    /// All API methods fail with [`Error::Offline`](crate::error::Error::Offline), unless a
+    /// canned response is set with [`Client::set_offline_response`].
+    pub fn new_offline() -> Coincheck {
+        Coincheck::from_client(Client::shared_offline())
+    }
+
+    fn from_client(client: SharedClient) -> Coincheck {
         let public = Public::new(client.clone());
 
         let private = Private {
@@ -119,27 +192,88 @@ impl Coincheck {
 
     /// Create a new instance without authentication keys. Only public APIs can be used.
     pub fn new_without_keys() -> CoincheckNoAuth {
-        let client = Client::shared_new(None, None);
+        Coincheck::new_without_keys_with_config(ClientConfig::default())
+    }
+
+    /// Create a new instance without authentication keys, with the client settings.
+    pub fn new_without_keys_with_config(config: ClientConfig) -> CoincheckNoAuth {
+        let client = Client::shared_with_config(None, None, config);
         let public = Public::new(client.clone());
 
         CoincheckNoAuth { public, client }
     }
+
+    /// Get the underlying HTTP client shared by all APIs.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Check whether JPY can be withdrawn: the identity is verified and a bank account exists.
+    ///
+    /// Combines [`Account::info`] and [`WithdrawsJpy::bank_accounts`].
+    pub async fn withdrawal_readiness(&mut self) -> Result<WithdrawalReadiness> {
+        let info = self.private.account.info().await?;
+        let bank_accounts = self.private.withdraws_jpy.bank_accounts().await?;
+        Ok(WithdrawalReadiness {
+            identity_confirmed: info.identity_status == WithdrawalReadiness::IDENTITY_VERIFIED,
+            bank_accounts: bank_accounts.data.len(),
+        })
+    }
+
+    /// Get the balance and the open orders as a [`RiskSnapshot`].
+    ///
+    /// Combines [`Account::balance`] and [`Order::opens`]. The timestamp is taken after both requests.
+    pub async fn risk_snapshot(&mut self) -> Result<RiskSnapshot> {
+        let balance = self.private.account.balance().await?;
+        let mut open_orders = self.private.order.opens().await?.orders;
+        open_orders.sort_by_key(|order| order.id);
+        Ok(RiskSnapshot {
+            timestamp: Utc::now(),
+            balance,
+            open_orders,
+        })
+    }
+
+    /// Get the balance and the open orders as a JSON string. See [`RiskSnapshot::to_json`].
+    pub async fn risk_snapshot_json(&mut self) -> Result<String> {
+        self.risk_snapshot().await?.to_json()
+    }
+
+    /// Get the net exposure to the base currency of `pair`, in the base currency.
+    ///
+    /// This is the spot holding from [`Balance::net_position`](account::model::Balance::net_position):
+    /// the available and reserved balance minus the debt. The exchange API has no leverage
+    /// positions, so there is nothing else to add.
+    pub async fn total_exposure(&mut self, pair: &CoinPair) -> Result<PriceType> {
+        let balance = self.private.account.balance().await?;
+        let currency = pair.base();
+        balance
+            .net_position(&currency)
+            .ok_or_else(|| Error::InvalidParam(format!("{} balance not found", currency.as_str())))
+    }
 }
 
-trait GetLastRequestTime {
+impl CoincheckNoAuth {
+    /// Get the underlying HTTP client shared by all APIs.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+}
+
+pub trait GetLastRequestTime {
     /// Get the last requset time.
     fn last_request_time(&self) -> Instant;
 }
 
 impl GetLastRequestTime for Coincheck {
     fn last_request_time(&self) -> Instant {
-        self.client.borrow().last_request_time
+        self.client.last_request_time()
     }
 }
 
 impl GetLastRequestTime for CoincheckNoAuth {
     fn last_request_time(&self) -> Instant {
-        self.client.borrow().last_request_time
+        self.client.last_request_time()
     }
 }
 
@@ -147,6 +281,71 @@ impl GetLastRequestTime for CoincheckNoAuth {
 mod tests {
     use super::*;
 
+    #[test]
+    fn risk_snapshot_json_is_stable() {
+        use chrono::TimeZone;
+
+        let snapshot = RiskSnapshot {
+            timestamp: Utc.timestamp_opt(0, 0).unwrap(),
+            balance: serde_json::from_value(serde_json::json!({
+                "success": true,
+                "jpy": "1000",
+                "btc": "0.5",
+                "jpy_reserved": "0",
+                "btc_reserved": "0",
+                "jpy_lend_in_use": "0",
+                "btc_lend_in_use": "0",
+                "jpy_lent": "0",
+                "btc_lent": "0",
+                "jpy_debt": "0",
+                "btc_debt": "0",
+                "mona": "3",
+                "etc": "2.5",
+            }))
+            .unwrap(),
+            open_orders: vec![],
+        };
+
+        let json = snapshot.to_json().unwrap();
+        assert_eq!(json, snapshot.to_json().unwrap());
+        assert!(json.starts_with(r#"{"balance":{"btc":"0.5","#));
+        assert!(json.contains(r#""etc":"2.5","jpy":"1000","#));
+        assert!(json.ends_with(r#""open_orders":[],"timestamp":"1970-01-01T00:00:00Z"}"#));
+    }
+
+    #[tokio::test]
+    async fn offline_instance() {
+        let mut coincheck = Coincheck::new_offline();
+        assert!(coincheck.client().is_offline());
+
+        let err = coincheck.private.account.balance().await.unwrap_err();
+        assert!(matches!(err, Error::Offline));
+
+        coincheck.client().set_offline_response(
+            "/api/exchange/orders/opens",
+            r#"{"success":true,"orders":[]}"#,
+        );
+        assert!(coincheck.private.order.opens().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn total_exposure() {
+        let mut coincheck = Coincheck::new_offline();
+        coincheck.client().set_offline_response(
+            "/api/accounts/balance",
+            r#"{"success": true, "jpy": "1000", "btc": "0.5", "jpy_reserved": "0",
+                "btc_reserved": "0.25", "jpy_lend_in_use": "0", "btc_lend_in_use": "0",
+                "jpy_lent": "0", "btc_lent": "0", "jpy_debt": "0", "btc_debt": "1.0",
+                "etc": "2.5", "etc_reserved": "1.5"}"#,
+        );
+
+        let btc = coincheck.total_exposure(&CoinPair::BtcJpy).await.unwrap();
+        assert!((btc - -0.25).abs() < 1e-9);
+        let etc = coincheck.total_exposure(&CoinPair::EtcJpy).await.unwrap();
+        assert!((etc - 4.0).abs() < 1e-9);
+        assert!(coincheck.total_exposure(&CoinPair::MonaJpy).await.is_err());
+    }
+
     #[test]
     fn create_coincheck_instance() {
         let _ = Coincheck::new_with_keys("hoge", "fuga");
@@ -0,0 +1,207 @@
+use std::collections::{HashSet, VecDeque};
+use std::future::Future;
+
+use crate::error::Result;
+use futures_util::stream::{self, BoxStream, StreamExt};
+use tokio_util::sync::CancellationToken;
+
+use crate::types::*;
+
+struct PageState<T, F> {
+    fetch: F,
+    cancel: CancellationToken,
+    cursor: Option<IdType>,
+    buffer: VecDeque<T>,
+    /// The ids of the last page, to skip the records repeated by the next one.
+    previous_page: HashSet<IdType>,
+    done: bool,
+}
+
+/// Stream all records of a paginated endpoint, following the `starting_after` cursor.
+///
+/// Each page is sorted by `id_of` in `order`, so the records are yielded in that order across
+/// the page boundaries. Records already returned by the previous page are skipped, so
+/// overlapping pages don't yield duplicates, and memory use doesn't grow with the stream.
+/// Paging stops when a page is shorter than `limit`, on the first error, or when
+/// `cancel` is cancelled. Cancelling (or dropping the stream) also drops any in-flight request.
+pub(crate) fn paginate<T, F, Fut>(
+    order: SortOrder,
+    limit: i32,
+    cancel: CancellationToken,
+    id_of: fn(&T) -> IdType,
+    fetch: F,
+) -> BoxStream<'static, Result<T>>
+where
+    T: Send + 'static,
+    F: FnMut(Pagination) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<Vec<T>>> + Send,
+{
+    let state = PageState {
+        fetch,
+        cancel,
+        cursor: None,
+        buffer: VecDeque::new(),
+        previous_page: HashSet::new(),
+        done: false,
+    };
+
+    stream::unfold(state, move |mut st| async move {
+        loop {
+            if st.cancel.is_cancelled() {
+                return None;
+            }
+            if let Some(item) = st.buffer.pop_front() {
+                return Some((Ok(item), st));
+            }
+            if st.done {
+                return None;
+            }
+
+            let pagination = Pagination {
+                limit,
+                order,
+                starting_after: st.cursor,
+                ending_before: None,
+            };
+            let page = tokio::select! {
+                _ = st.cancel.cancelled() => return None,
+                page = (st.fetch)(pagination) => page,
+            };
+
+            match page {
+                Ok(mut items) => {
+                    items.sort_by(|a, b| match order {
+                        SortOrder::Asc => id_of(a).cmp(&id_of(b)),
+                        SortOrder::Desc => id_of(b).cmp(&id_of(a)),
+                    });
+                    if (items.len() as i32) < limit {
+                        st.done = true;
+                    }
+                    match items.last() {
+                        Some(last) => st.cursor = Some(id_of(last)),
+                        None => st.done = true,
+                    }
+                    let previous = std::mem::take(&mut st.previous_page);
+                    let current = &mut st.previous_page;
+                    st.buffer.extend(items.into_iter().filter(|item| {
+                        let id = id_of(item);
+                        current.insert(id) && !previous.contains(&id)
+                    }));
+                }
+                Err(err) => {
+                    st.done = true;
+                    return Some((Err(err), st));
+                }
+            }
+        }
+    })
+    .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::TryStreamExt;
+
+    fn fake_pages(
+        ids: Vec<IdType>,
+    ) -> impl FnMut(Pagination) -> std::future::Ready<Result<Vec<IdType>>> {
+        move |pagination| {
+            let start = match pagination.starting_after {
+                Some(cursor) => ids.iter().position(|&id| id == cursor).unwrap() + 1,
+                None => 0,
+            };
+            let page = ids
+                .iter()
+                .skip(start)
+                .take(pagination.limit as usize)
+                .copied()
+                .collect();
+            std::future::ready(Ok(page))
+        }
+    }
+
+    #[tokio::test]
+    async fn follows_cursor_until_short_page() {
+        let ids: Vec<IdType> = (1..=7).collect();
+        let stream = paginate(
+            SortOrder::Asc,
+            3,
+            CancellationToken::new(),
+            |id| *id,
+            fake_pages(ids.clone()),
+        );
+        let items: Vec<IdType> = stream.try_collect().await.unwrap();
+        assert_eq!(items, ids);
+    }
+
+    #[tokio::test]
+    async fn skips_duplicates_across_pages() {
+        // Each page repeats the last record of the previous page.
+        let pages = [vec![1, 2, 3], vec![3, 4, 5], vec![5, 6]];
+        let mut calls = 0;
+        let stream = paginate(
+            SortOrder::Asc,
+            3,
+            CancellationToken::new(),
+            |id: &IdType| *id,
+            move |_| {
+                let page = pages[calls].clone();
+                calls += 1;
+                std::future::ready(Ok(page))
+            },
+        );
+        let items: Vec<IdType> = stream.try_collect().await.unwrap();
+        assert_eq!(items, vec![1, 2, 3, 4, 5, 6]);
+    }
+
+    #[tokio::test]
+    async fn keeps_order_across_pages() {
+        // The pages are returned in the reverse order within each page.
+        let reversed = |ids: Vec<IdType>| {
+            let mut fetch = fake_pages(ids);
+            move |pagination| {
+                let mut page = fetch(pagination).into_inner().unwrap();
+                page.reverse();
+                std::future::ready(Ok(page))
+            }
+        };
+
+        let asc: Vec<IdType> = (1..=7).collect();
+        let stream = paginate(
+            SortOrder::Asc,
+            3,
+            CancellationToken::new(),
+            |id| *id,
+            reversed(asc.clone()),
+        );
+        let items: Vec<IdType> = stream.try_collect().await.unwrap();
+        assert_eq!(items, asc);
+
+        let desc: Vec<IdType> = (1..=7).rev().collect();
+        let stream = paginate(
+            SortOrder::Desc,
+            3,
+            CancellationToken::new(),
+            |id| *id,
+            reversed(desc.clone()),
+        );
+        let items: Vec<IdType> = stream.try_collect().await.unwrap();
+        assert_eq!(items, desc);
+    }
+
+    #[tokio::test]
+    async fn stops_when_cancelled() {
+        let cancel = CancellationToken::new();
+        let mut stream = paginate(
+            SortOrder::Asc,
+            3,
+            cancel.clone(),
+            |id| *id,
+            fake_pages((1..=7).collect()),
+        );
+        assert_eq!(stream.next().await.unwrap().unwrap(), 1);
+        cancel.cancel();
+        assert!(stream.next().await.is_none());
+    }
+}
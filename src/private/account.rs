@@ -1,7 +1,8 @@
 use crate::client::SharedClient;
+use crate::error::{Error, Result};
 use crate::types::*;
-use anyhow::Result;
 use reqwest::Method;
+use std::time::{Duration, Instant};
 
 /// Private API - Account
 ///
@@ -18,6 +19,7 @@ pub mod model {
     use serde::{Deserialize, Serialize};
     use serde_with::{serde_as, DisplayFromStr};
     use std::collections::HashMap;
+    use std::fmt;
 
     /// 残高
     #[serde_as]
@@ -44,6 +46,74 @@ pub mod model {
         pub jpy_debt: PriceType,
         #[serde_as(as = "DisplayFromStr")]
         pub btc_debt: PriceType,
+        /// その他の通貨の残高など
+        #[serde(flatten)]
+        pub others: HashMap<String, serde_json::Value>,
+    }
+
+    impl ApiResponse for Balance {
+        fn success(&self) -> bool {
+            self.success
+        }
+    }
+
+    impl Balance {
+        /// 指定した通貨の利用可能な残高
+        pub fn available(&self, currency: &Currency) -> Option<PriceType> {
+            match currency {
+                Currency::Jpy => Some(self.jpy),
+                Currency::Btc => Some(self.btc),
+                _ => self.other(&currency.as_str().to_lowercase()),
+            }
+        }
+
+        /// 指定した通貨の正味の保有量
+        ///
+        /// 利用可能な残高と注文中の残高の合計から借入中の数量を引いたものです。
+        /// 借入が保有量より多い場合は負の値になります。
+        pub fn net_position(&self, currency: &Currency) -> Option<PriceType> {
+            let (available, reserved, debt) = match currency {
+                Currency::Jpy => (self.jpy, self.jpy_reserved, self.jpy_debt),
+                Currency::Btc => (self.btc, self.btc_reserved, self.btc_debt),
+                _ => {
+                    let key = currency.as_str().to_lowercase();
+                    let field = |suffix: &str| {
+                        self.other(&format!("{}_{}", key, suffix))
+                            .unwrap_or(0 as PriceType)
+                    };
+                    (self.other(&key)?, field("reserved"), field("debt"))
+                }
+            };
+            Some(available + reserved - debt)
+        }
+
+        fn other(&self, key: &str) -> Option<PriceType> {
+            match self.others.get(key)? {
+                serde_json::Value::String(s) => s.parse().ok(),
+                serde_json::Value::Number(n) => n.as_f64().map(|n| n as PriceType),
+                _ => None,
+            }
+        }
+    }
+
+    /// 送金結果
+    #[serde_as]
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct SendMoneyResult {
+        pub success: bool,
+        #[serde_as(as = "DisplayFromStr")]
+        pub id: IdType,
+        pub address: String,
+        #[serde_as(as = "DisplayFromStr")]
+        pub amount: PriceType,
+        #[serde_as(as = "DisplayFromStr")]
+        pub fee: PriceType,
+    }
+
+    impl ApiResponse for SendMoneyResult {
+        fn success(&self) -> bool {
+            self.success
+        }
     }
 
     /// 送金履歴
@@ -53,6 +123,19 @@ pub mod model {
         pub sends: Vec<SendRecord>,
     }
 
+    impl ApiResponse for SendHistory {
+        fn success(&self) -> bool {
+            self.success
+        }
+    }
+
+    impl SendHistory {
+        /// 送金履歴が空か
+        pub fn is_empty(&self) -> bool {
+            self.sends.is_empty()
+        }
+    }
+
     /// 送金履歴のレコード
     #[serde_as]
     #[derive(Debug, Serialize, Deserialize)]
@@ -74,6 +157,19 @@ pub mod model {
         pub deposits: Vec<DepositRecord>,
     }
 
+    impl ApiResponse for DepositHistory {
+        fn success(&self) -> bool {
+            self.success
+        }
+    }
+
+    impl DepositHistory {
+        /// 受け取り履歴が空か
+        pub fn is_empty(&self) -> bool {
+            self.deposits.is_empty()
+        }
+    }
+
     /// 受け取り履歴のレコード
     #[serde_as]
     #[derive(Debug, Serialize, Deserialize)]
@@ -89,8 +185,11 @@ pub mod model {
     }
 
     /// アカウント情報
+    ///
+    /// `Debug` ではメールアドレスとビットコインアドレスを伏せ字にします。
+    /// そのまま出力する場合は [`Account::to_unredacted`] を使います。
     #[serde_as]
-    #[derive(Debug, Serialize, Deserialize)]
+    #[derive(Serialize, Deserialize)]
     pub struct Account {
         pub success: bool,
         pub id: IdType,
@@ -104,9 +203,101 @@ pub mod model {
         pub exchange_fees: HashMap<String, Fee>,
     }
 
+    impl ApiResponse for Account {
+        fn success(&self) -> bool {
+            self.success
+        }
+    }
+
+    impl Account {
+        /// 伏せ字にせずに `Debug` で出力するためのラッパー
+        pub fn to_unredacted(&self) -> Unredacted<'_> {
+            Unredacted(self)
+        }
+
+        fn fmt_debug(&self, f: &mut fmt::Formatter<'_>, redact: bool) -> fmt::Result {
+            let (email, bitcoin_address) = if redact {
+                (
+                    redact_email(&self.email),
+                    redact_text(&self.bitcoin_address),
+                )
+            } else {
+                (self.email.clone(), self.bitcoin_address.clone())
+            };
+            f.debug_struct("Account")
+                .field("success", &self.success)
+                .field("id", &self.id)
+                .field("email", &email)
+                .field("identity_status", &self.identity_status)
+                .field("bitcoin_address", &bitcoin_address)
+                .field("taker_fee", &self.taker_fee)
+                .field("maker_fee", &self.maker_fee)
+                .field("exchange_fees", &self.exchange_fees)
+                .finish()
+        }
+
+        /// 取引ペアの現在の手数料
+        ///
+        /// `exchange_fees` に取引ペアがあればその手数料を、なければアカウント全体の手数料を返します。
+        /// 取引量に応じた手数料の段階は API で提供されていないため、次の段階までの取引量は分かりません。
+        pub fn effective_fee(&self, pair: &CoinPair) -> Fee {
+            match self.exchange_fees.get(pair.as_str()) {
+                Some(fee) => fee.clone(),
+                None => Fee {
+                    taker_fee: self.taker_fee,
+                    maker_fee: self.maker_fee,
+                },
+            }
+        }
+
+        /// 取引ペアごとの手数料の一覧
+        ///
+        /// [`CoinPair`] の順に並べます。未知の取引ペアは含みません。
+        pub fn fee_table(&self) -> Vec<(CoinPair, Fee)> {
+            let mut table: Vec<(CoinPair, Fee)> = self
+                .exchange_fees
+                .iter()
+                .filter_map(|(pair, fee)| Some((pair.parse().ok()?, fee.clone())))
+                .collect();
+            table.sort_by_key(|(pair, _)| *pair);
+            table
+        }
+    }
+
+    impl fmt::Debug for Account {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.fmt_debug(f, true)
+        }
+    }
+
+    /// 伏せ字にしない [`Account`] の `Debug` 出力
+    pub struct Unredacted<'a>(&'a Account);
+
+    impl fmt::Debug for Unredacted<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.0.fmt_debug(f, false)
+        }
+    }
+
+    /// 先頭の 1 文字以外を伏せ字にします。
+    fn redact_text(text: &str) -> String {
+        match text.chars().next() {
+            Some(first) => format!("{}***", first),
+            None => String::new(),
+        }
+    }
+
+    /// ローカル部の先頭の 1 文字以外を伏せ字にします。 (`john@example.com` → `j***@***`)
+    fn redact_email(email: &str) -> String {
+        match email.split_once('@') {
+            Some((local, _)) => format!("{}@***", redact_text(local)),
+            None => redact_text(email),
+        }
+    }
+
     /// 手数料
     #[serde_as]
-    #[derive(Debug, Serialize, Deserialize)]
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
     pub struct Fee {
         #[serde_as(as = "DisplayFromStr")]
         pub taker_fee: PriceType,
@@ -130,13 +321,73 @@ impl Account {
     /// <https://coincheck.com/ja/documents/exchange/api#account-balance>
     pub async fn balance(&mut self) -> Result<model::Balance> {
         self.client
-            .borrow_mut()
             .request_and_get_json(Method::GET, "/api/accounts/balance", None, Self::USE_AUTH)
             .await
     }
 
-    // TODO: implement ビットコインの送金 POST /api/send_money
-    // https://coincheck.com/ja/documents/exchange/api#account-sendmoney
+    /// 残高の反映待ち
+    ///
+    /// 指定した通貨の利用可能な残高が `at_least` 以上になるまで、`interval` ごとに
+    /// [`Account::balance`] を取得します。入金の反映を待つのに使えます。
+    /// 残高を返します。`timeout` 以内に届かなかった場合は [`Error::Timeout`] を返します。
+    pub async fn wait_for_balance(
+        &mut self,
+        currency: &Currency,
+        at_least: PriceType,
+        timeout: Duration,
+        interval: Duration,
+    ) -> Result<PriceType> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            // a currency missing from the response has no balance yet
+            let available = self
+                .balance()
+                .await?
+                .available(currency)
+                .unwrap_or(0 as PriceType);
+            if available >= at_least {
+                return Ok(available);
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(Error::Timeout);
+            }
+            tokio::time::sleep(remaining.min(interval)).await;
+        }
+    }
+
+    /// ビットコインの送金
+    ///
+    /// 指定のアドレスにビットコインを送ります。`amount` が正の数でない場合は、送金せずにエラーを返します。
+    ///
+    /// <https://coincheck.com/ja/documents/exchange/api#account-sendmoney>
+    pub async fn send_money(
+        &mut self,
+        address: &str,
+        amount: PriceType,
+    ) -> Result<model::SendMoneyResult> {
+        if !(amount.is_finite() && amount > 0 as PriceType) {
+            return Err(Error::InvalidParam(format!(
+                "amount must be a positive number: {}",
+                amount
+            )));
+        }
+
+        let mut params = Params::new();
+        let amount = &amount.to_string();
+        params.insert("address", address);
+        params.insert("amount", amount);
+        params.insert("currency", Currency::Btc.as_str());
+        self.client
+            .request_and_get_json(
+                Method::POST,
+                "/api/send_money",
+                Some(&params),
+                Self::USE_AUTH,
+            )
+            .await
+    }
 
     /// 送金履歴
     ///
@@ -147,7 +398,6 @@ impl Account {
         let mut params = Params::new();
         params.insert("currency", Currency::Btc.as_str());
         self.client
-            .borrow_mut()
             .request_and_get_json(
                 Method::GET,
                 "/api/send_money",
@@ -166,7 +416,6 @@ impl Account {
         let mut params = Params::new();
         params.insert("currency", Currency::Btc.as_str());
         self.client
-            .borrow_mut()
             .request_and_get_json(
                 Method::GET,
                 "/api/deposit_money",
@@ -183,7 +432,6 @@ impl Account {
     /// <https://coincheck.com/ja/documents/exchange/api#account-info>
     pub async fn info(&mut self) -> Result<model::Account> {
         self.client
-            .borrow_mut()
             .request_and_get_json(Method::GET, "/api/accounts", None, Self::USE_AUTH)
             .await
     }
@@ -191,8 +439,122 @@ impl Account {
 
 #[cfg(test)]
 mod tests {
+    use crate::error::Error;
+    use crate::types::*;
     use crate::Coincheck;
 
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn account_fee_table() {
+        let account: super::model::Account = serde_json::from_value(serde_json::json!({
+            "success": true,
+            "id": 10000,
+            "email": "test@gmail.com",
+            "identity_status": "identity_verified",
+            "bitcoin_address": "1v6zFvyNPgdRvhUufkRoTtgyiw1xigncc",
+            "taker_fee": "0.15",
+            "maker_fee": "0.0",
+            "exchange_fees": {
+                "mona_jpy": { "maker_fee": "0.05", "taker_fee": "0.1" },
+                "btc_jpy": { "maker_fee": "0.0", "taker_fee": "0.0" },
+                "xxx_jpy": { "maker_fee": "0.1", "taker_fee": "0.2" }
+            }
+        }))
+        .unwrap();
+
+        let table = account.fee_table();
+        let pairs: Vec<CoinPair> = table.iter().map(|(pair, _)| *pair).collect();
+        assert_eq!(pairs, vec![CoinPair::BtcJpy, CoinPair::MonaJpy]);
+        assert_eq!(table[1].1.taker_fee, 0.1 as PriceType);
+
+        assert_eq!(account.effective_fee(&CoinPair::MonaJpy), table[1].1);
+        let fee = account.effective_fee(&CoinPair::EtcJpy);
+        assert_eq!(fee.taker_fee, 0.15 as PriceType);
+        assert_eq!(fee.maker_fee, 0 as PriceType);
+    }
+
+    #[test]
+    fn account_debug_is_redacted() {
+        let account: super::model::Account = serde_json::from_value(serde_json::json!({
+            "success": true,
+            "id": 10000,
+            "email": "test@gmail.com",
+            "identity_status": "identity_verified",
+            "bitcoin_address": "1v6zFvyNPgdRvhUufkRoTtgyiw1xigncc",
+            "taker_fee": "0.15",
+            "maker_fee": "0.0",
+            "exchange_fees": {}
+        }))
+        .unwrap();
+
+        let debug = format!("{:?}", account);
+        assert!(debug.contains(r#"email: "t***@***""#));
+        assert!(debug.contains(r#"bitcoin_address: "1***""#));
+        assert!(!debug.contains("gmail"));
+
+        let debug = format!("{:?}", account.to_unredacted());
+        assert!(debug.contains(r#"email: "test@gmail.com""#));
+        assert!(debug.contains("1v6zFvyNPgdRvhUufkRoTtgyiw1xigncc"));
+    }
+
+    #[tokio::test]
+    async fn wait_for_balance() {
+        let mut coincheck = Coincheck::new_offline();
+        coincheck.client().set_offline_response(
+            "/api/accounts/balance",
+            r#"{"success": true, "jpy": "1000", "btc": "0.1", "jpy_reserved": "0",
+                "btc_reserved": "0", "jpy_lend_in_use": "0", "btc_lend_in_use": "0",
+                "jpy_lent": "0", "btc_lent": "0", "jpy_debt": "0", "btc_debt": "0"}"#,
+        );
+        let api = &mut coincheck.private.account;
+        let timeout = Duration::from_millis(50);
+        let interval = Duration::from_millis(10);
+
+        let jpy = api
+            .wait_for_balance(&Currency::Jpy, 1000 as PriceType, timeout, interval)
+            .await
+            .unwrap();
+        assert_eq!(jpy, 1000 as PriceType);
+
+        for (currency, at_least) in [(Currency::Btc, 0.5), (Currency::Etc, 1.0)] {
+            let started = Instant::now();
+            let err = api
+                .wait_for_balance(&currency, at_least as PriceType, timeout, interval)
+                .await
+                .unwrap_err();
+            assert!(started.elapsed() >= timeout);
+            assert!(matches!(&err, Error::Timeout));
+        }
+    }
+
+    #[tokio::test]
+    async fn send_money() {
+        let mut coincheck = Coincheck::new_offline();
+        coincheck.client().set_offline_response(
+            "/api/send_money",
+            r#"{"success": true, "id": "276", "address": "1v6zFvyNPgdRvhUufkRoTtgyiw1xigncc",
+                "amount": "0.05", "fee": "0.001"}"#,
+        );
+        let api = &mut coincheck.private.account;
+
+        let result = api
+            .send_money("1v6zFvyNPgdRvhUufkRoTtgyiw1xigncc", 0.05)
+            .await
+            .unwrap();
+        assert_eq!(result.id, 276);
+        assert_eq!(result.amount, 0.05 as PriceType);
+        assert_eq!(result.fee, 0.001 as PriceType);
+
+        for amount in [0.0, -0.05, PriceType::NAN] {
+            let err = api
+                .send_money("1v6zFvyNPgdRvhUufkRoTtgyiw1xigncc", amount)
+                .await
+                .unwrap_err();
+            assert!(err.to_string().contains("must be a positive number"));
+        }
+    }
+
     #[tokio::test]
     #[serial_test::serial]
     async fn private_account_api() {
@@ -1,7 +1,15 @@
-use crate::client::SharedClient;
+use crate::client::{BodyReader, Client, SharedClient};
+use crate::error::{Error, Result};
+use crate::paginate::paginate;
+use crate::private::account::{self, Account};
+use crate::public::Public;
 use crate::types::*;
-use anyhow::Result;
+use futures_util::stream::{self, BoxStream, StreamExt, TryStreamExt};
 use reqwest::Method;
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 
 /// Private API - Order
 ///
@@ -24,7 +32,7 @@ pub mod model {
     #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
     pub struct OrderResultGeneral {
         pub success: bool,
-        pub id: IdType,
+        pub id: OrderId,
         #[serde_as(as = "Option<DisplayFromStr>")]
         pub rate: Option<PriceType>,
         #[serde_as(as = "Option<DisplayFromStr>")]
@@ -36,6 +44,12 @@ pub mod model {
         pub created_at: DateTime<Utc>,
     }
 
+    impl ApiResponse for OrderResultGeneral {
+        fn success(&self) -> bool {
+            self.success
+        }
+    }
+
     /// 未決済の注文一覧
     #[derive(Debug, Serialize, Deserialize)]
     pub struct OpenOrders {
@@ -43,11 +57,129 @@ pub mod model {
         pub orders: Vec<OpenOrder>,
     }
 
+    impl ApiResponse for OpenOrders {
+        fn success(&self) -> bool {
+            self.success
+        }
+    }
+
+    impl OpenOrders {
+        /// 未決済の注文がないか
+        pub fn is_empty(&self) -> bool {
+            self.orders.is_empty()
+        }
+
+        /// 取引ペアごとの未約定の数量の合計 (買い, 売り)
+        ///
+        /// 未知の取引ペアや注文方法の注文は集計しません。成行買い注文は数量ではなく
+        /// `pending_market_buy_amount` (決済通貨建て) が未約定なので集計しません。
+        pub fn summary(&self) -> HashMap<CoinPair, (PriceType, PriceType)> {
+            let mut summary: HashMap<CoinPair, (PriceType, PriceType)> = HashMap::new();
+            for order in &self.orders {
+                let (pair, order_type) = match (
+                    order.pair.parse::<CoinPair>(),
+                    order.order_type.parse::<OrderType>(),
+                ) {
+                    (Ok(pair), Ok(order_type)) => (pair, order_type),
+                    _ => continue,
+                };
+
+                match order_type {
+                    OrderType::Limit(BaseOrderType::Buy) => {
+                        summary.entry(pair).or_default().0 += order.pending_amount
+                    }
+                    OrderType::Limit(BaseOrderType::Sell) | OrderType::MarketSell => {
+                        summary.entry(pair).or_default().1 += order.pending_amount
+                    }
+                    OrderType::MarketBuy => {}
+                }
+            }
+            summary
+        }
+
+        /// 新規の指値注文と約定する自分の注文 (自己約定)
+        ///
+        /// 同じ取引ペアの反対側の指値注文のうち、買い注文なら `rate` 以下、売り注文なら `rate`
+        /// 以上のレートの注文の ID を返します。板に載っていない逆指値の注文は対象外です。
+        pub fn self_matches(
+            &self,
+            pair: &CoinPair,
+            side: BaseOrderType,
+            rate: PriceType,
+        ) -> Vec<OrderId> {
+            let opposite = match side {
+                BaseOrderType::Buy => BaseOrderType::Sell,
+                BaseOrderType::Sell => BaseOrderType::Buy,
+            };
+            self.orders
+                .iter()
+                .filter(|order| {
+                    order.stop_loss_rate.is_none()
+                        && order.pair.parse() == Ok(*pair)
+                        && order.order_type.parse() == Ok(OrderType::Limit(opposite))
+                        && match side {
+                            BaseOrderType::Buy => order.rate <= rate,
+                            BaseOrderType::Sell => order.rate >= rate,
+                        }
+                })
+                .map(|order| order.id)
+                .collect()
+        }
+
+        /// 発注したい注文との差分
+        ///
+        /// 取引ペア、売り買い、(呼値の単位に丸めた) レートと逆指値レートが同じで、未約定の数量が
+        /// `amount` 以下の未決済の注文を、発注済みとみなします (部分約定した注文はそのまま残します)。
+        /// 1 つの未決済の注文は 1 つの発注したい注文にだけ対応します。
+        /// 対応しない指値注文 (逆指値付きを含む) はキャンセルする注文になります。
+        ///
+        /// 成行注文は約定したかどうかを未決済の注文から判断できないため、一度だけ発注するものとして
+        /// 扱い、常に新たに発注する注文になります。定期的に呼び出す場合は、発注したい注文に
+        /// 成行注文を含めないでください。未決済の成行注文 (逆指値付き) はキャンセルしません。
+        pub fn reconcile(&self, desired: &[DesiredOrder]) -> ReconcilePlan {
+            let mut remaining: Vec<&OpenOrder> = self
+                .orders
+                .iter()
+                .filter(|order| matches!(order.order_type.parse(), Ok(OrderType::Limit(_))))
+                .collect();
+
+            let mut to_place = Vec::new();
+            for want in desired {
+                if want.order_type != DesiredOrderType::Limit {
+                    to_place.push(want.clone());
+                    continue;
+                }
+                let tick = |rate| round_to_tick(&want.pair, rate);
+                let (rate, stop) = (tick(want.rate), want.stop_loss.map(tick));
+                let found = remaining.iter().position(|order| {
+                    order.pair.parse() == Ok(want.pair)
+                        && order.order_type.parse() == Ok(OrderType::Limit(want.side))
+                        && tick(order.rate) == rate
+                        && order.stop_loss_rate.map(tick) == stop
+                        && order.pending_amount <= want.amount
+                });
+                match found {
+                    Some(i) => {
+                        remaining.swap_remove(i);
+                    }
+                    None => to_place.push(want.clone()),
+                }
+            }
+
+            let mut to_cancel: Vec<OrderId> = remaining.iter().map(|order| order.id).collect();
+            to_cancel.sort();
+            ReconcilePlan {
+                to_cancel,
+                to_place,
+            }
+        }
+    }
+
     /// 未決済の注文
     #[serde_as]
     #[derive(Debug, Serialize, Deserialize)]
     pub struct OpenOrder {
-        pub id: IdType,
+        pub id: OrderId,
         pub order_type: String,
         #[serde_as(as = "DisplayFromStr")]
         pub rate: PriceType,
@@ -61,22 +193,89 @@ pub mod model {
         pub created_at: DateTime<Utc>,
     }
 
+    /// 発注したい注文
+    ///
+    /// [`Order::submit_desired`](super::Order::submit_desired) で発注し、
+    /// [`Order::reconcile`](super::Order::reconcile) で未決済の注文と比較します。
+    /// 設定ファイルなどから読み込めます。`type` と `stop_loss` は省略でき、省略時は指値注文です。
+    ///
+    /// ```json
+    /// { "pair": "btc_jpy", "side": "buy", "type": "limit", "rate": 3000000, "amount": 0.01,
+    ///   "stop_loss": 2900000 }
+    /// ```
+    #[serde_as]
+    #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+    pub struct DesiredOrder {
+        pub pair: CoinPair,
+        #[serde_as(as = "DisplayFromStr")]
+        pub side: BaseOrderType,
+        /// 指値か成行か
+        #[serde(rename = "type", default)]
+        pub order_type: DesiredOrderType,
+        /// レート (成行注文の場合は想定レート)
+        pub rate: PriceType,
+        /// 注文量 (取引通貨建て)
+        pub amount: PriceType,
+        /// 逆指値レート
+        #[serde(default)]
+        pub stop_loss: Option<PriceType>,
+    }
+
+    /// 発注したい注文の種類
+    #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+    #[serde(rename_all = "snake_case")]
+    pub enum DesiredOrderType {
+        /// 指値注文
+        #[default]
+        Limit,
+        /// 成行注文
+        Market,
+    }
+
+    /// 発注したい注文と未決済の注文の差分
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ReconcilePlan {
+        /// キャンセルする未決済の注文
+        pub to_cancel: Vec<OrderId>,
+        /// 新たに発注する注文
+        pub to_place: Vec<DesiredOrder>,
+    }
+
+    impl ReconcilePlan {
+        /// 差分がないか
+        pub fn is_empty(&self) -> bool {
+            self.to_cancel.is_empty() && self.to_place.is_empty()
+        }
+    }
+
     /// キャンセル結果
     #[derive(Debug, Serialize, Deserialize)]
     pub struct CancelResult {
         pub success: bool,
-        pub id: IdType,
+        pub id: OrderId,
+    }
+
+    impl ApiResponse for CancelResult {
+        fn success(&self) -> bool {
+            self.success
+        }
     }
 
     /// キャンセルステータス
     #[derive(Debug, Serialize, Deserialize)]
     pub struct CancelStatus {
         pub success: bool,
-        pub id: IdType,
+        pub id: OrderId,
         pub cancel: bool,
         pub created_at: DateTime<Utc>,
     }
 
+    impl ApiResponse for CancelStatus {
+        fn success(&self) -> bool {
+            self.success
+        }
+    }
+
     /// 取引履歴
     #[derive(Debug, Serialize, Deserialize)]
     pub struct OrderTransactions {
@@ -84,6 +283,19 @@ pub mod model {
         pub transactions: Vec<OrderTransaction>,
     }
 
+    impl ApiResponse for OrderTransactions {
+        fn success(&self) -> bool {
+            self.success
+        }
+    }
+
+    impl OrderTransactions {
+        /// 取引履歴が空か
+        pub fn is_empty(&self) -> bool {
+            self.transactions.is_empty()
+        }
+    }
+
     /// 取引履歴（ページネーション）
     #[derive(Debug, Serialize, Deserialize)]
     pub struct OrderTransactionsPagination {
@@ -92,24 +304,104 @@ pub mod model {
         pub data: Vec<OrderTransaction>,
     }
 
+    impl ApiResponse for OrderTransactionsPagination {
+        fn success(&self) -> bool {
+            self.success
+        }
+    }
+
+    impl OrderTransactionsPagination {
+        /// 取引履歴が空か
+        pub fn is_empty(&self) -> bool {
+            self.data.is_empty()
+        }
+    }
+
     /// 取引情報
     #[serde_as]
     #[derive(Debug, Serialize, Deserialize)]
     pub struct OrderTransaction {
-        pub id: IdType,
-        pub order_id: IdType,
+        pub id: TransactionId,
+        pub order_id: OrderId,
         pub created_at: DateTime<Utc>,
         #[serde_as(as = "HashMap<DisplayFromStr, DisplayFromStr>")]
         pub funds: HashMap<String, PriceType>,
         pub pair: String,
         #[serde_as(as = "DisplayFromStr")]
         pub rate: PriceType,
-        pub fee_currency: Option<String>,
+        #[serde_as(as = "Option<DisplayFromStr>")]
+        pub fee_currency: Option<Currency>,
         #[serde_as(as = "DisplayFromStr")]
         pub fee: PriceType,
         pub liquidity: String,
         pub side: String,
     }
+
+    impl OrderTransaction {
+        /// 手数料込みの約定レート
+        ///
+        /// 手数料を決済通貨に換算し、約定数量 (`funds` の取引通貨) あたりの額をレートに加減します。
+        ///
+        /// - 買い: `rate + 手数料 / 数量`
+        /// - 売り: `rate - 手数料 / 数量`
+        ///
+        /// 手数料が取引通貨建ての場合は `手数料 * rate` で決済通貨に換算します。
+        /// `fee_currency` がない場合は決済通貨建てとみなします。
+        /// マイナスの手数料 (リベート) はレートを有利な方向に動かします。
+        /// 取引ペアや数量が分からない場合は `rate` をそのまま返します。
+        pub fn effective_rate(&self) -> PriceType {
+            let pair: CoinPair = match self.pair.parse() {
+                Ok(pair) => pair,
+                Err(_) => return self.rate,
+            };
+            let amount = self
+                .funds
+                .get(&pair.base().as_str().to_lowercase())
+                .map(|amount| amount.abs())
+                .unwrap_or_default();
+            if amount == 0 as PriceType {
+                return self.rate;
+            }
+
+            let fee = if self.fee_currency == Some(pair.base()) {
+                self.fee * self.rate
+            } else {
+                self.fee
+            };
+            if self.side == BaseOrderType::Sell.as_str() {
+                self.rate - fee / amount
+            } else {
+                self.rate + fee / amount
+            }
+        }
+
+        /// 手数料を差し引いた取引通貨の増減
+        ///
+        /// `funds` の取引通貨の数量 (買いは正、売りは負) です。`funds` は手数料を差し引いた後の
+        /// 増減のため、取引通貨建ての手数料もすでに含まれています。
+        /// 取引ペアが分からない場合は `None` です。
+        pub fn net_base_amount(&self) -> Option<PriceType> {
+            let base = self.pair.parse::<CoinPair>().ok()?.base();
+            let amount = self
+                .funds
+                .get(&base.as_str().to_lowercase())
+                .copied()
+                .unwrap_or_default();
+            Some(amount)
+        }
+    }
+
+    /// 注文で受け取った取引通貨の数量 (手数料を差し引いた後)
+    ///
+    /// `transactions` のうち `order_id` の約定の [`OrderTransaction::net_base_amount`] の合計です。
+    /// 例えば成行買いで実際に増えた BTC の数量が分かります。
+    pub fn net_base_received(transactions: &[OrderTransaction], order_id: OrderId) -> PriceType {
+        transactions
+            .iter()
+            .filter(|tx| tx.order_id == order_id)
+            .filter_map(OrderTransaction::net_base_amount)
+            .sum()
+    }
 }
 
 impl Order {
@@ -127,13 +419,23 @@ impl Order {
     /// **NOTE**
     ///
     /// 取引所の[注文数量制限](https://faq.coincheck.com/s/article/40218)や[指値注文価格制限](https://faq.coincheck.com/s/article/40217)に引っかかった場合はHTTPエラー `400 Bad Request` になるようです。
+    /// 取引所のエラーメッセージは [`Error::Api`] として取得できます。
     ///
     /// ---
     ///
+    /// 注文金額が [`Client::set_max_order_notional`] の上限を超える場合は、注文を送らずに
+    /// [`Error::LimitExceeded`] を返します。逆指値のない成行売りの注文金額は
+    /// [`Public::order_rate_from_amount`](crate::public::Public::order_rate_from_amount) のレートで見積もります。
+    ///
     /// <https://coincheck.com/ja/documents/exchange/api#order-new>
     pub async fn new_any(&mut self, params: &Params<'_>) -> Result<model::OrderResultGeneral> {
+        if let Some(limit) = self.client.config().max_order_notional {
+            let requested = self.notional(params).await?;
+            if requested > limit {
+                return Err(Error::LimitExceeded { limit, requested });
+            }
+        }
         self.client
-            .borrow_mut()
             .request_and_get_json(
                 Method::POST,
                 "/api/exchange/orders",
@@ -143,7 +445,190 @@ impl Order {
             .await
     }
 
+    /// 注文金額 (日本円)
+    async fn notional(&self, params: &Params<'_>) -> Result<PriceType> {
+        let pair: CoinPair = Self::param(params, "pair")?
+            .parse()
+            .map_err(|e: &str| Error::InvalidParam(e.to_owned()))?;
+        let order_type: OrderType = Self::param(params, "order_type")?
+            .parse()
+            .map_err(|e: &str| Error::InvalidParam(e.to_owned()))?;
+
+        match order_type {
+            OrderType::Limit(_) => {
+                Ok(Self::positive_param(params, "rate")? * Self::positive_param(params, "amount")?)
+            }
+            OrderType::MarketBuy => Ok(Self::positive_param(params, "market_buy_amount")?),
+            OrderType::MarketSell => {
+                let amount = Self::positive_param(params, "amount")?;
+                let rate = if params.contains_key("stop_loss_rate") {
+                    Self::positive_param(params, "stop_loss_rate")?
+                } else {
+                    Public::new(self.client.clone())
+                        .order_rate_from_amount(&BaseOrderType::Sell, &pair, amount)
+                        .await?
+                        .rate
+                };
+                Ok(rate * amount)
+            }
+        }
+    }
+
+    /// 新規注文 カスタム (レイテンシ計測)
+    ///
+    /// [`Order::new_any`] と同じですが、注文の受付にかかった時間を合わせて返します。
+    pub async fn new_any_timed(
+        &mut self,
+        params: &Params<'_>,
+    ) -> Result<Timed<model::OrderResultGeneral>> {
+        let started = Instant::now();
+        let value = self.new_any(params).await?;
+        Ok(Timed {
+            value,
+            latency: started.elapsed(),
+        })
+    }
+
+    /// 新規注文の事前確認
+    ///
+    /// 残高を取得した上で、[`Order::validate_params`] の確認を行います。
+    /// [`Order::new_any`] の前に呼び出すことで、問題のある注文をAPIに送る前に検出できます。
+    pub async fn preflight(&mut self, params: &Params<'_>) -> Result<()> {
+        let balance = Account::new(self.client.clone()).balance().await?;
+        Self::validate_params(params, Some(&balance))
+    }
+
+    /// 新規注文のパラメータ確認
+    ///
+    /// 以下を確認し、最初に見つかった問題をエラーとして返します。
+    ///
+    /// - `pair`, `order_type` と注文方法に必要なパラメータがあること
+    /// - 取引ペアで注文方法が利用できること ([`supported_order_types`])
+    /// - レートと数量が正の値で、数量の小数点以下が取引通貨の桁数 ([`precision`]) 以内であること
+    /// - 最小注文数量 ([`CoinPair::min_order_amount`]) と最小注文金額 ([`MIN_ORDER_PRICE_JPY`]) 以上であること
+    /// - `balance` を指定した場合、残高が足りていること
+    ///
+    /// 成行売りはレートが分からないため、注文金額の確認は行いません。
+    pub fn validate_params(
+        params: &Params<'_>,
+        balance: Option<&account::model::Balance>,
+    ) -> Result<()> {
+        let pair: CoinPair = Self::param(params, "pair")?
+            .parse()
+            .map_err(|e: &str| Error::InvalidParam(e.to_owned()))?;
+        let order_type: OrderType = Self::param(params, "order_type")?
+            .parse()
+            .map_err(|e: &str| Error::InvalidParam(e.to_owned()))?;
+        if !supported_order_types(&pair).contains(&order_type) {
+            return Err(Error::InvalidParam(format!(
+                "order_type {} is not supported for {}",
+                order_type.as_str(),
+                pair.as_str()
+            )));
+        }
+
+        let (currency, required) = match order_type {
+            OrderType::Limit(side) => {
+                let rate = Self::positive_param(params, "rate")?;
+                let amount = Self::amount_param(params, "amount", &pair)?;
+                if rate * amount < MIN_ORDER_PRICE_JPY {
+                    return Err(Error::InvalidParam(format!(
+                        "order price {} JPY is below the minimum {} JPY",
+                        rate * amount,
+                        MIN_ORDER_PRICE_JPY
+                    )));
+                }
+                match side {
+                    BaseOrderType::Buy => (pair.quote(), rate * amount),
+                    BaseOrderType::Sell => (pair.base(), amount),
+                }
+            }
+            OrderType::MarketBuy => {
+                let amount_jpy = Self::positive_param(params, "market_buy_amount")?;
+                if amount_jpy < MIN_ORDER_PRICE_JPY {
+                    return Err(Error::InvalidParam(format!(
+                        "market_buy_amount {} JPY is below the minimum {} JPY",
+                        amount_jpy, MIN_ORDER_PRICE_JPY
+                    )));
+                }
+                (pair.quote(), amount_jpy)
+            }
+            OrderType::MarketSell => {
+                let amount = Self::amount_param(params, "amount", &pair)?;
+                (pair.base(), amount)
+            }
+        };
+
+        if params.contains_key("stop_loss_rate") {
+            Self::positive_param(params, "stop_loss_rate")?;
+        }
+
+        if let Some(balance) = balance {
+            let available = balance.available(&currency).ok_or_else(|| {
+                Error::InvalidParam(format!("{} balance not found", currency.as_str()))
+            })?;
+            if available < required {
+                return Err(Error::InvalidParam(format!(
+                    "insufficient {} balance: required {}, available {}",
+                    currency.as_str(),
+                    required,
+                    available
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn param<'a>(params: &Params<'a>, key: &str) -> Result<&'a str> {
+        params
+            .get(key)
+            .copied()
+            .ok_or_else(|| Error::InvalidParam(format!("missing parameter: {}", key)))
+    }
+
+    fn positive_param(params: &Params<'_>, key: &str) -> Result<PriceType> {
+        let value = Self::param(params, key)?;
+        match value.parse::<PriceType>() {
+            Ok(v) if v.is_finite() && v > 0 as PriceType => Ok(v),
+            _ => Err(Error::InvalidParam(format!(
+                "{} must be a positive number: {}",
+                key, value
+            ))),
+        }
+    }
+
+    fn amount_param(params: &Params<'_>, key: &str, pair: &CoinPair) -> Result<PriceType> {
+        let amount = Self::positive_param(params, key)?;
+
+        let value = Self::param(params, key)?;
+        let decimals = value.split_once('.').map_or(0, |(_, frac)| frac.len());
+        let max_decimals = precision(&pair.base());
+        if decimals > max_decimals as usize {
+            return Err(Error::InvalidParam(format!(
+                "{} must have at most {} decimal places: {}",
+                key, max_decimals, value
+            )));
+        }
+
+        if let Some(min) = pair.min_order_amount() {
+            if amount < min {
+                return Err(Error::InvalidParam(format!(
+                    "{} {} is below the minimum order amount {} {}",
+                    key,
+                    amount,
+                    min,
+                    pair.base().as_str()
+                )));
+            }
+        }
+
+        Ok(amount)
+    }
+
     /// 取引所に新規注文を発行します。指値買い (Buy Limit Order) を行います。
+    ///
+    /// レートは呼値の単位に丸めます ([`round_to_tick`])。
     pub async fn new_limit_buy(
         &mut self,
         pair: &CoinPair,
@@ -151,7 +636,7 @@ impl Order {
         amount: PriceType,
     ) -> Result<model::OrderResultGeneral> {
         let mut params = Params::new();
-        let rate = &rate.to_string();
+        let rate = &round_to_tick(pair, rate).to_string();
         let amount = &amount.to_string();
         params.insert("pair", pair.as_str());
         params.insert("order_type", OrderType::LimitBuy.as_str());
@@ -162,6 +647,8 @@ impl Order {
     }
 
     /// 取引所に新規注文を発行します。指値売り (Sell Limit Order) を行います。
+    ///
+    /// レートは呼値の単位に丸めます ([`round_to_tick`])。
     pub async fn new_limit_sell(
         &mut self,
         pair: &CoinPair,
@@ -169,7 +656,7 @@ impl Order {
         amount: PriceType,
     ) -> Result<model::OrderResultGeneral> {
         let mut params = Params::new();
-        let rate = &rate.to_string();
+        let rate = &round_to_tick(pair, rate).to_string();
         let amount = &amount.to_string();
         params.insert("pair", pair.as_str());
         params.insert("order_type", OrderType::LimitSell.as_str());
@@ -180,6 +667,8 @@ impl Order {
     }
 
     /// 取引所に新規注文を発行します。逆指値の指値買い (Buy Stop-Limit Order) を行います。
+    ///
+    /// レートは呼値の単位に丸めます ([`round_to_tick`])。
     pub async fn new_stop_limit_buy(
         &mut self,
         pair: &CoinPair,
@@ -188,9 +677,9 @@ impl Order {
         stop_loss_rate: PriceType,
     ) -> Result<model::OrderResultGeneral> {
         let mut params = Params::new();
-        let rate = &rate.to_string();
+        let rate = &round_to_tick(pair, rate).to_string();
         let amount = &amount.to_string();
-        let stop_loss_rate = &stop_loss_rate.to_string();
+        let stop_loss_rate = &round_to_tick(pair, stop_loss_rate).to_string();
         params.insert("pair", pair.as_str());
         params.insert("order_type", OrderType::LimitBuy.as_str());
         params.insert("rate", rate);
@@ -201,6 +690,8 @@ impl Order {
     }
 
     /// 取引所に新規注文を発行します。逆指値の指値売り (Sell Stop-Limit Order) を行います。
+    ///
+    /// レートは呼値の単位に丸めます ([`round_to_tick`])。
     pub async fn new_stop_limit_sell(
         &mut self,
         pair: &CoinPair,
@@ -209,9 +700,9 @@ impl Order {
         stop_loss_rate: PriceType,
     ) -> Result<model::OrderResultGeneral> {
         let mut params = Params::new();
-        let rate = &rate.to_string();
+        let rate = &round_to_tick(pair, rate).to_string();
         let amount = &amount.to_string();
-        let stop_loss_rate = &stop_loss_rate.to_string();
+        let stop_loss_rate = &round_to_tick(pair, stop_loss_rate).to_string();
         params.insert("pair", pair.as_str());
         params.insert("order_type", OrderType::LimitSell.as_str());
         params.insert("rate", rate);
@@ -252,6 +743,8 @@ impl Order {
     }
 
     /// 取引所に新規注文を発行します。逆指値の成行買い (Buy Stop-Market Order) を行います。
+    ///
+    /// レートは呼値の単位に丸めます ([`round_to_tick`])。
     pub async fn new_stop_market_buy(
         &mut self,
         pair: &CoinPair,
@@ -260,7 +753,7 @@ impl Order {
     ) -> Result<model::OrderResultGeneral> {
         let mut params = Params::new();
         let amount_jpy = &amount_jpy.to_string();
-        let stop_loss_rate = &stop_loss_rate.to_string();
+        let stop_loss_rate = &round_to_tick(pair, stop_loss_rate).to_string();
         params.insert("pair", pair.as_str());
         params.insert("order_type", OrderType::MarketBuy.as_str());
         params.insert("market_buy_amount", amount_jpy);
@@ -270,6 +763,8 @@ impl Order {
     }
 
     /// 取引所に新規注文を発行します。逆指値の成行売り (Sell Stop-Market Order) を行います。
+    ///
+    /// レートは呼値の単位に丸めます ([`round_to_tick`])。
     pub async fn new_stop_market_sell(
         &mut self,
         pair: &CoinPair,
@@ -278,7 +773,7 @@ impl Order {
     ) -> Result<model::OrderResultGeneral> {
         let mut params = Params::new();
         let amount = &amount.to_string();
-        let stop_loss_rate = &stop_loss_rate.to_string();
+        let stop_loss_rate = &round_to_tick(pair, stop_loss_rate).to_string();
         params.insert("pair", pair.as_str());
         params.insert("order_type", OrderType::MarketSell.as_str());
         params.insert("amount", amount);
@@ -287,6 +782,242 @@ impl Order {
         self.new_any(&params).await
     }
 
+    /// 新規注文 + 逆指値 (OCOのような注文)
+    ///
+    /// 指値注文 `entry` を発行し、約定するまで [`Order::opens`] をポーリングしてから、
+    /// 反対売買の逆指値の成行注文を `stop_loss_rate` で発行します。
+    /// 買い注文の場合は `stop_loss_rate` は指値より低く、売り注文の場合は高くなければなりません。
+    ///
+    /// ---
+    /// **NOTE**
+    ///
+    /// 取引所のOCO注文ではなく、クライアント側で順番に注文するだけです。
+    ///
+    /// - 約定から逆指値の発行までの間 (ポーリング間隔 + 通信時間) は逆指値がない状態になります。
+    /// - その間にプロセスが終了した場合、逆指値は発行されません。
+    /// - 新規注文がキャンセルされた場合や、逆指値の発行に失敗した場合はエラーを返します。
+    ///   この場合も新規注文は約定している可能性があります。
+    /// - `entry` が約定するまで (部分約定を含め、未決済の注文一覧から消えるまで) 待ちます。
+    ///   `cancel` がキャンセルされると待つのをやめ、逆指値なしで残らないように新規注文を
+    ///   キャンセルしてエラーを返します。
+    ///
+    /// ---
+    pub async fn place_with_stop(
+        &mut self,
+        entry: &Params<'_>,
+        stop_loss_rate: PriceType,
+        poll_interval: Duration,
+        cancel: CancellationToken,
+    ) -> Result<(model::OrderResultGeneral, model::OrderResultGeneral)> {
+        let order_type: OrderType = Self::param(entry, "order_type")?
+            .parse()
+            .map_err(|e: &str| Error::InvalidParam(e.to_owned()))?;
+        let side = match order_type {
+            OrderType::Limit(side) => side,
+            _ => {
+                return Err(Error::InvalidParam(
+                    "the entry order must be a limit order".to_owned(),
+                ))
+            }
+        };
+        let rate = Self::positive_param(entry, "rate")?;
+        let stop_is_valid = match side {
+            BaseOrderType::Buy => stop_loss_rate < rate,
+            BaseOrderType::Sell => stop_loss_rate > rate,
+        };
+        if !stop_is_valid {
+            return Err(Error::InvalidParam(format!(
+                "stop_loss_rate {} is on the wrong side of the entry rate {}",
+                stop_loss_rate, rate
+            )));
+        }
+
+        let entry = self.new_any(entry).await?;
+        let pair: CoinPair = entry.pair.parse().map_err(Error::other)?;
+        let amount = entry
+            .amount
+            .ok_or_else(|| Error::other(format!("entry order {} has no amount", entry.id)))?;
+
+        if !self
+            .wait_until_closed(entry.id, poll_interval, &cancel)
+            .await?
+        {
+            return match self.cancel_checked(entry.id).await {
+                Ok(_) => Err(Error::other(format!(
+                    "stopped waiting; entry order {} was cancelled",
+                    entry.id
+                ))),
+                Err(Error::OrderNotFound(_)) => Err(Error::other(format!(
+                    "stopped waiting; entry order {} was closed without a stop order",
+                    entry.id
+                ))),
+                Err(err) => Err(err),
+            };
+        }
+        if self.cancel_status(entry.id).await?.cancel {
+            return Err(Error::other(format!(
+                "entry order {} was cancelled",
+                entry.id
+            )));
+        }
+
+        let stop = match side {
+            BaseOrderType::Buy => {
+                self.new_stop_market_sell(&pair, amount, stop_loss_rate)
+                    .await?
+            }
+            BaseOrderType::Sell => {
+                self.new_stop_market_buy(&pair, amount * stop_loss_rate, stop_loss_rate)
+                    .await?
+            }
+        };
+
+        Ok((entry, stop))
+    }
+
+    /// 取引所に新規注文を発行します。指値買いを行い、`ttl` 以内に約定しなければキャンセルします。
+    ///
+    /// 約定するか `ttl` が経過するまで、未決済の注文一覧をポーリングして待ちます。
+    /// キャンセルした場合はキャンセル結果を返します。`None` の場合は `ttl` 以内に約定しています
+    /// (部分約定を含みません)。
+    pub async fn new_limit_buy_with_expiry(
+        &mut self,
+        pair: &CoinPair,
+        rate: PriceType,
+        amount: PriceType,
+        ttl: Duration,
+    ) -> Result<(model::OrderResultGeneral, Option<model::CancelResult>)> {
+        let order = self.new_limit_buy(pair, rate, amount).await?;
+        let cancelled = self.cancel_after(order.id, ttl).await?;
+        Ok((order, cancelled))
+    }
+
+    /// 取引所に新規注文を発行します。指値売りを行い、`ttl` 以内に約定しなければキャンセルします。
+    ///
+    /// [`Order::new_limit_buy_with_expiry`] の売り注文版です。
+    pub async fn new_limit_sell_with_expiry(
+        &mut self,
+        pair: &CoinPair,
+        rate: PriceType,
+        amount: PriceType,
+        ttl: Duration,
+    ) -> Result<(model::OrderResultGeneral, Option<model::CancelResult>)> {
+        let order = self.new_limit_sell(pair, rate, amount).await?;
+        let cancelled = self.cancel_after(order.id, ttl).await?;
+        Ok((order, cancelled))
+    }
+
+    /// Poll interval of [`Order::cancel_after`].
+    const EXPIRY_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// Cancel the order if it's still open after `ttl`. `None` if it was closed before.
+    async fn cancel_after(
+        &mut self,
+        id: OrderId,
+        ttl: Duration,
+    ) -> Result<Option<model::CancelResult>> {
+        let deadline = Instant::now() + ttl;
+        while self.opens().await?.orders.iter().any(|o| o.id == id) {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return match self.cancel_checked(id).await {
+                    Ok(result) => Ok(Some(result)),
+                    Err(Error::OrderNotFound(found)) if found == id => Ok(None),
+                    Err(err) => Err(err),
+                };
+            }
+            tokio::time::sleep(remaining.min(Self::EXPIRY_POLL_INTERVAL)).await;
+        }
+        Ok(None)
+    }
+
+    /// Poll the open orders until the order is no longer open. `false` if `cancel` was cancelled
+    /// before, which also drops any in-flight request.
+    async fn wait_until_closed(
+        &mut self,
+        id: OrderId,
+        poll_interval: Duration,
+        cancel: &CancellationToken,
+    ) -> Result<bool> {
+        loop {
+            let opens = tokio::select! {
+                _ = cancel.cancelled() => return Ok(false),
+                opens = self.opens() => opens?,
+            };
+            if !opens.orders.iter().any(|o| o.id == id) {
+                return Ok(true);
+            }
+            tokio::select! {
+                _ = cancel.cancelled() => return Ok(false),
+                _ = tokio::time::sleep(poll_interval) => {}
+            }
+        }
+    }
+
+    /// 発注したい注文を発注します。
+    ///
+    /// 注文の種類と逆指値の有無に応じて `Order::new_*` を呼びます。
+    /// 成行買いの注文金額 (日本円) は `rate * amount` です。
+    pub async fn submit_desired(
+        &mut self,
+        desired: &model::DesiredOrder,
+    ) -> Result<model::OrderResultGeneral> {
+        use model::DesiredOrderType::{Limit, Market};
+
+        let model::DesiredOrder {
+            pair,
+            side,
+            order_type,
+            rate,
+            amount,
+            stop_loss,
+        } = desired;
+        let (rate, amount) = (*rate, *amount);
+        match (order_type, side, *stop_loss) {
+            (Limit, BaseOrderType::Buy, None) => self.new_limit_buy(pair, rate, amount).await,
+            (Limit, BaseOrderType::Sell, None) => self.new_limit_sell(pair, rate, amount).await,
+            (Limit, BaseOrderType::Buy, Some(stop)) => {
+                self.new_stop_limit_buy(pair, rate, amount, stop).await
+            }
+            (Limit, BaseOrderType::Sell, Some(stop)) => {
+                self.new_stop_limit_sell(pair, rate, amount, stop).await
+            }
+            (Market, BaseOrderType::Buy, None) => self.new_market_buy(pair, rate * amount).await,
+            (Market, BaseOrderType::Sell, None) => self.new_market_sell(pair, amount).await,
+            (Market, BaseOrderType::Buy, Some(stop)) => {
+                self.new_stop_market_buy(pair, rate * amount, stop).await
+            }
+            (Market, BaseOrderType::Sell, Some(stop)) => {
+                self.new_stop_market_sell(pair, amount, stop).await
+            }
+        }
+    }
+
+    /// 未決済の注文と発注したい注文の差分
+    ///
+    /// 未決済の注文一覧を取得し、キャンセルする注文と新たに発注する注文を返します。
+    /// 注文の発行やキャンセルは行いません。詳しくは [`model::OpenOrders::reconcile`] を参照してください。
+    pub async fn reconcile(
+        &mut self,
+        desired: &[model::DesiredOrder],
+    ) -> Result<model::ReconcilePlan> {
+        Ok(self.opens().await?.reconcile(desired))
+    }
+
+    /// 新規の指値注文が自分の注文と約定するか (自己約定)
+    ///
+    /// 未決済の注文一覧を取得して確認します。詳しくは [`model::OpenOrders::self_matches`]
+    /// を参照してください。
+    pub async fn would_self_match(
+        &mut self,
+        pair: &CoinPair,
+        side: BaseOrderType,
+        rate: PriceType,
+    ) -> Result<bool> {
+        let opens = self.opens().await?;
+        Ok(!opens.self_matches(pair, side, rate).is_empty())
+    }
+
     /// 未決済の注文一覧
     ///
     /// アカウントの未決済の注文を一覧で表示します。
@@ -294,7 +1025,6 @@ impl Order {
     /// <https://coincheck.com/ja/documents/exchange/api#order-opens>
     pub async fn opens(&mut self) -> Result<model::OpenOrders> {
         self.client
-            .borrow_mut()
             .request_and_get_json(
                 Method::GET,
                 "/api/exchange/orders/opens",
@@ -309,25 +1039,35 @@ impl Order {
     /// 新規注文または未決済の注文一覧のIDを指定してキャンセルすることができます。
     ///
     /// <https://coincheck.com/ja/documents/exchange/api#order-cancel>
-    pub async fn cancel(&mut self, id: IdType) -> Result<model::CancelResult> {
+    pub async fn cancel(&mut self, id: OrderId) -> Result<model::CancelResult> {
         let url = format!("/api/exchange/orders/{}", id);
         self.client
-            .borrow_mut()
             .request_and_get_json(Method::DELETE, &url, None, Self::USE_AUTH)
             .await
     }
 
+    /// 注文のキャンセル (未決済の注文か確認)
+    ///
+    /// 未決済の注文一覧に `id` があることを確認してからキャンセルします。
+    /// 見つからない場合はキャンセルのリクエストを送らずに [`Error::OrderNotFound`] を返します。
+    pub async fn cancel_checked(&mut self, id: OrderId) -> Result<model::CancelResult> {
+        let opens = self.opens().await?;
+        if !opens.orders.iter().any(|order| order.id == id) {
+            return Err(Error::OrderNotFound(id));
+        }
+        self.cancel(id).await
+    }
+
     /// 注文のキャンセルステータス
     ///
     /// オーダーのキャンセル処理状況を参照出来ます。
     ///
     /// <https://coincheck.com/ja/documents/exchange/api#cancel-status>
-    pub async fn cancel_status(&mut self, id: IdType) -> Result<model::CancelStatus> {
+    pub async fn cancel_status(&mut self, id: OrderId) -> Result<model::CancelStatus> {
         let mut params = Params::new();
         let id: &str = &id.to_string();
         params.insert("id", id);
         self.client
-            .borrow_mut()
             .request_and_get_json(
                 Method::GET,
                 "/api/exchange/orders/cancel_status",
@@ -344,7 +1084,6 @@ impl Order {
     /// <https://coincheck.com/ja/documents/exchange/api#order-transactions>
     pub async fn transactions(&mut self) -> Result<model::OrderTransactions> {
         self.client
-            .borrow_mut()
             .request_and_get_json(
                 Method::GET,
                 "/api/exchange/orders/transactions",
@@ -354,35 +1093,182 @@ impl Order {
             .await
     }
 
+    /// 取引履歴 (1件ずつ処理)
+    ///
+    /// [`Order::transactions_body_stream`] の取引履歴を1件ずつ `f` に渡します。
+    /// レスポンスの本文も取引履歴の `Vec` もまとめて保持しないため、件数が多くてもメモリ使用量が増えません。
+    /// 処理した件数を返します。
+    pub async fn transactions_for_each<F>(&mut self, mut f: F) -> Result<usize>
+    where
+        F: FnMut(model::OrderTransaction),
+    {
+        let mut transactions = self.transactions_body_stream();
+        let mut count = 0;
+        while let Some(transaction) = transactions.try_next().await? {
+            f(transaction);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Number of transactions decoded ahead of the consumer of [`Order::transactions_body_stream`].
+    const DECODE_AHEAD: usize = 64;
+
+    /// 取引履歴の全件取得 (ストリーム、逐次デコード)
+    ///
+    /// [`Order::transactions`] と同じ取引履歴を、レスポンスの本文を受信しながら1件ずつデコードして返します。
+    /// [`Order::transactions_stream`] と同じ型のストリームですが、ページングせず1回のリクエストで取得します。
+    /// 本文も取引履歴の `Vec` もまとめて保持しないため、件数が多くてもメモリ使用量が増えません。
+    /// 本文の上限は [`Client::set_max_response_bytes`] で設定できます。
+    /// ストリームを drop するとリクエストとデコードを中止します。
+    pub fn transactions_body_stream(
+        &mut self,
+    ) -> BoxStream<'static, Result<model::OrderTransaction>> {
+        let client = self.client.clone();
+        let start = async move {
+            let (tx, rx) = mpsc::channel(Self::DECODE_AHEAD);
+            tokio::spawn(Self::decode_transactions(client, tx));
+            stream::unfold(rx, |mut rx| async move {
+                rx.recv().await.map(|transaction| (transaction, rx))
+            })
+        };
+        stream::once(start).flatten().boxed()
+    }
+
+    /// Request the transactions and decode them on a blocking thread, sending each one to `tx`.
+    async fn decode_transactions(
+        client: SharedClient,
+        tx: mpsc::Sender<Result<model::OrderTransaction>>,
+    ) {
+        let request = client.request_and_get_reader(
+            Method::GET,
+            "/api/exchange/orders/transactions",
+            None,
+            Self::USE_AUTH,
+        );
+        let body = tokio::select! {
+            _ = tx.closed() => return,
+            body = request => body,
+        };
+        let body = match body {
+            Ok(body) => body,
+            Err(err) => {
+                let _ = tx.send(Err(err)).await;
+                return;
+            }
+        };
+
+        let sender = tx.clone();
+        let decoded = tokio::task::spawn_blocking(move || {
+            // stops decoding when the stream is dropped
+            Self::parse_transactions(body, |transaction| {
+                sender.blocking_send(Ok(transaction)).is_ok()
+            })
+        })
+        .await;
+        if let Err(err) = decoded.map_err(Error::other).and_then(|result| result) {
+            let _ = tx.send(Err(err)).await;
+        }
+    }
+
+    /// Parse the transactions one by one, until `f` returns `false`.
+    fn parse_transactions<R, F>(reader: R, f: F) -> Result<usize>
+    where
+        R: std::io::Read,
+        F: FnMut(model::OrderTransaction) -> bool,
+    {
+        let mut deserializer = serde_json::Deserializer::from_reader(reader);
+        let count = TransactionsVisitor(f)
+            .deserialize(&mut deserializer)
+            .map_err(BodyReader::parse_error)?;
+        deserializer.end().map_err(BodyReader::parse_error)?;
+        Ok(count)
+    }
+
     /// 取引履歴（ページネーション）
     ///
     /// 自分の最近の取引履歴を参照できます。
+    /// `pagination.limit` は最大 [`Pagination::MAX_LIMIT`] 件に丸められます。
     ///
     /// <https://coincheck.com/ja/documents/exchange/api#order-transactions-pagination>
     pub async fn transactions_pagination(
         &mut self,
         pagination: Pagination,
     ) -> Result<model::OrderTransactionsPagination> {
-        let mut params = Params::new();
-        let limit: &str = &pagination.limit.to_string();
-        let order: &str = &pagination.order.to_string();
-        params.insert("limit", limit);
-        params.insert("order", order);
-
-        let tmp_str; // to create a longer lived value
-        if let Some(r) = pagination.starting_after {
-            tmp_str = r.to_string();
-            params.insert("starting_after", &tmp_str);
-        };
+        Self::fetch_transactions_page(&self.client, &pagination).await
+    }
 
-        let tmp_str; // to create a longer lived value
-        if let Some(r) = pagination.ending_before {
-            tmp_str = r.to_string();
-            params.insert("ending_before", &tmp_str);
-        };
+    /// 取引履歴の全件取得 (ストリーム)
+    ///
+    /// [`Order::transactions_pagination`] を繰り返し呼び出して、取引履歴を1件ずつ返します。
+    /// ストリームを drop するとページングは停止します。
+    pub fn transactions_stream(
+        &mut self,
+        order: SortOrder,
+    ) -> BoxStream<'static, Result<model::OrderTransaction>> {
+        self.transactions_stream_with_cancel(order, CancellationToken::new())
+    }
 
-        self.client
-            .borrow_mut()
+    /// 取引履歴の全件取得 (ストリーム、キャンセル可能)
+    ///
+    /// `cancel` がキャンセルされると、実行中のリクエストを破棄してストリームを終了します。
+    pub fn transactions_stream_with_cancel(
+        &mut self,
+        order: SortOrder,
+        cancel: CancellationToken,
+    ) -> BoxStream<'static, Result<model::OrderTransaction>> {
+        let client = self.client.clone();
+        paginate(
+            order,
+            Pagination::MAX_LIMIT,
+            cancel,
+            |tx: &model::OrderTransaction| tx.id.0,
+            move |pagination| {
+                let client = client.clone();
+                async move {
+                    Self::fetch_transactions_page(&client, &pagination)
+                        .await
+                        .map(|page| page.data)
+                }
+            },
+        )
+    }
+
+    /// 取引履歴のエクスポート
+    ///
+    /// 取引履歴を全件取得します。`cancel` がキャンセルされた場合は、それまでに取得した分を返します。
+    pub async fn export_transactions(
+        &mut self,
+        order: SortOrder,
+        cancel: CancellationToken,
+    ) -> Result<Vec<model::OrderTransaction>> {
+        self.transactions_stream_with_cancel(order, cancel)
+            .try_collect()
+            .await
+    }
+
+    /// 直近の取引履歴
+    ///
+    /// 取引履歴を `order` の順に最大 `max` 件取得します。`max` 件に達したらページングを停止します。
+    pub async fn recent_transactions(
+        &mut self,
+        max: usize,
+        order: SortOrder,
+    ) -> Result<Vec<model::OrderTransaction>> {
+        self.transactions_stream(order)
+            .take(max)
+            .try_collect()
+            .await
+    }
+
+    async fn fetch_transactions_page(
+        client: &Client,
+        pagination: &Pagination,
+    ) -> Result<model::OrderTransactionsPagination> {
+        let params = params_from_serialize(&PaginationParams::from(pagination))?;
+        let params = borrow_params(&params);
+
+        client
             .request_and_get_json(
                 Method::GET,
                 "/api/exchange/orders/transactions_pagination",
@@ -393,10 +1279,961 @@ impl Order {
     }
 }
 
+/// 発注した注文をキャンセルするガード
+///
+/// [`Order::managed_session`] で作成します。ガードを通して発注した注文を記録し、
+/// [`OrderGuard::cancel_all`] で未決済のものをキャンセルします。
+///
+/// ---
+/// **NOTE**
+///
+/// Rust には非同期の `Drop` がないため、[`OrderGuard::cancel_all`] を呼ばずにガードを破棄した場合は、
+/// キャンセルのタスクを tokio ランタイムに spawn するだけです (ベストエフォート)。
+///
+/// - ランタイムが終了する (`main` から戻る、ランタイムを drop するなど) と、タスクは完了しない可能性があります。
+/// - ランタイムの外で破棄した場合や、`panic = "abort"` の場合はキャンセルされません。
+/// - キャンセルの失敗は `tracing` の警告として出力されるだけです。
+/// - 約定済みの数量 (ポジション) は反対売買されません。キャンセルするのは未決済の注文だけです。
+///
+/// 確実にキャンセルするには、終了前に [`OrderGuard::cancel_all`] を `await` してください。
+///
+/// ---
+pub struct OrderGuard {
+    order: Order,
+    placed: Vec<OrderId>,
+}
+
+impl Order {
+    /// 発注した注文をキャンセルするガードを作成します。詳しくは [`OrderGuard`] を参照してください。
+    pub fn managed_session(&self) -> OrderGuard {
+        OrderGuard {
+            order: Order::new(self.client.clone()),
+            placed: Vec::new(),
+        }
+    }
+}
+
+impl OrderGuard {
+    /// 新規注文を発行し、記録します。[`Order::new_any`] と同じです。
+    pub async fn new_any(&mut self, params: &Params<'_>) -> Result<model::OrderResultGeneral> {
+        let result = self.order.new_any(params).await?;
+        self.placed.push(result.id);
+        Ok(result)
+    }
+
+    /// 発注したい注文を発注し、記録します。[`Order::submit_desired`] と同じです。
+    pub async fn submit_desired(
+        &mut self,
+        desired: &model::DesiredOrder,
+    ) -> Result<model::OrderResultGeneral> {
+        let result = self.order.submit_desired(desired).await?;
+        self.placed.push(result.id);
+        Ok(result)
+    }
+
+    /// ガードの外で発注した注文を記録します。
+    pub fn track(&mut self, id: OrderId) {
+        self.placed.push(id);
+    }
+
+    /// 記録している注文 (キャンセルしていないもの)
+    pub fn order_ids(&self) -> &[OrderId] {
+        &self.placed
+    }
+
+    /// 記録している注文のうち、未決済のものをキャンセルします。
+    ///
+    /// 未決済の注文一覧を取得し、残っている注文を 1 つずつキャンセルします。
+    /// 途中で失敗した場合は、キャンセルしていない注文についてガードを破棄した場合と同じ動作になります。
+    pub async fn cancel_all(mut self) -> Result<Vec<model::CancelResult>> {
+        let opens = self.order.opens().await?;
+        self.placed
+            .retain(|id| opens.orders.iter().any(|order| order.id == *id));
+
+        let mut results = Vec::new();
+        while let Some(&id) = self.placed.first() {
+            results.push(self.order.cancel(id).await?);
+            self.placed.remove(0);
+        }
+        Ok(results)
+    }
+}
+
+impl Drop for OrderGuard {
+    fn drop(&mut self) {
+        if self.placed.is_empty() {
+            return;
+        }
+        let ids = std::mem::take(&mut self.placed);
+        let handle = match tokio::runtime::Handle::try_current() {
+            Ok(handle) => handle,
+            Err(_) => {
+                tracing::warn!(?ids, "no tokio runtime to cancel the orders on drop");
+                return;
+            }
+        };
+        let mut order = Order::new(self.order.client.clone());
+        handle.spawn(async move {
+            for id in ids {
+                if let Err(err) = order.cancel(id).await {
+                    tracing::warn!(%id, "failed to cancel the order on drop: {:#}", err);
+                }
+            }
+        });
+    }
+}
+
+/// Deserialize `{"success": .., "transactions": [..]}`, passing each transaction to the callback
+/// instead of collecting them.
+struct TransactionsVisitor<F>(F);
+
+impl<'de, F: FnMut(model::OrderTransaction) -> bool> DeserializeSeed<'de>
+    for TransactionsVisitor<F>
+{
+    type Value = usize;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<usize, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, F: FnMut(model::OrderTransaction) -> bool> Visitor<'de> for TransactionsVisitor<F> {
+    type Value = usize;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("order transactions")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(mut self, mut map: A) -> Result<usize, A::Error> {
+        let mut count = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "success" => {
+                    if !map.next_value::<bool>()? {
+                        return Err(de::Error::custom("success is false"));
+                    }
+                }
+                "transactions" => count = Some(map.next_value_seed(EachSeed(&mut self.0))?),
+                _ => {
+                    map.next_value::<de::IgnoredAny>()?;
+                }
+            }
+        }
+        count.ok_or_else(|| de::Error::missing_field("transactions"))
+    }
+}
+
+/// Deserialize an array, passing each element to the callback until it returns `false`.
+struct EachSeed<'a, F>(&'a mut F);
+
+impl<'de, F: FnMut(model::OrderTransaction) -> bool> DeserializeSeed<'de> for EachSeed<'_, F> {
+    type Value = usize;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<usize, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, F: FnMut(model::OrderTransaction) -> bool> Visitor<'de> for EachSeed<'_, F> {
+    type Value = usize;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("an array of order transactions")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<usize, A::Error> {
+        let mut count = 0;
+        while let Some(tx) = seq.next_element()? {
+            if !(self.0)(tx) {
+                return Err(de::Error::custom("stopped"));
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
+    use super::Order;
+    use crate::error::Error;
+    use crate::private::account::model::Balance;
     use crate::types::*;
     use crate::Coincheck;
+    use tokio_util::sync::CancellationToken;
+
+    /// An open order of `btc_jpy` or another `pair`, as in the response of `opens`.
+    fn open_order(
+        id: IdType,
+        pair: &str,
+        side: &str,
+        rate: &str,
+        amount: &str,
+        stop: Option<&str>,
+    ) -> serde_json::Value {
+        serde_json::json!({
+            "id": id, "order_type": side, "rate": rate, "pair": pair,
+            "pending_amount": amount, "pending_market_buy_amount": null,
+            "stop_loss_rate": stop, "created_at": "2015-01-10T05:55:38.000Z"
+        })
+    }
+
+    fn open_orders(orders: Vec<serde_json::Value>) -> serde_json::Value {
+        serde_json::json!({ "success": true, "orders": orders })
+    }
+
+    /// A transaction of `btc_jpy`, with the `funds` (BTC, JPY) after the fee.
+    pub(crate) fn transaction(
+        id: IdType,
+        order_id: IdType,
+        side: &str,
+        rate: &str,
+        (btc, jpy): (&str, &str),
+        (fee_currency, fee): (&str, &str),
+    ) -> super::model::OrderTransaction {
+        serde_json::from_value(serde_json::json!({
+            "id": id, "order_id": order_id, "created_at": "2015-11-18T07:02:21.000Z",
+            "funds": { "btc": btc, "jpy": jpy }, "pair": "btc_jpy", "rate": rate,
+            "fee_currency": fee_currency, "fee": fee, "liquidity": "T", "side": side
+        }))
+        .unwrap()
+    }
+
+    fn balance(jpy: PriceType, btc: PriceType) -> Balance {
+        serde_json::from_value(serde_json::json!({
+            "success": true,
+            "jpy": jpy.to_string(),
+            "btc": btc.to_string(),
+            "jpy_reserved": "0",
+            "btc_reserved": "0",
+            "jpy_lend_in_use": "0",
+            "btc_lend_in_use": "0",
+            "jpy_lent": "0",
+            "btc_lent": "0",
+            "jpy_debt": "0",
+            "btc_debt": "0",
+            "etc": "2.5",
+        }))
+        .unwrap()
+    }
+
+    fn params<'a>(pairs: &[(&'a str, &'a str)]) -> Params<'a> {
+        pairs.iter().copied().collect()
+    }
+
+    #[test]
+    fn validate_order_params() {
+        let balance = balance(100000 as PriceType, 0.01 as PriceType);
+        let balance = Some(&balance);
+
+        let ok = params(&[
+            ("pair", "btc_jpy"),
+            ("order_type", "buy"),
+            ("rate", "3000000"),
+            ("amount", "0.005"),
+        ]);
+        assert!(Order::validate_params(&ok, balance).is_ok());
+
+        let insufficient = params(&[
+            ("pair", "btc_jpy"),
+            ("order_type", "buy"),
+            ("rate", "3000000"),
+            ("amount", "0.1"),
+        ]);
+        assert!(Order::validate_params(&insufficient, balance).is_err());
+        assert!(Order::validate_params(&insufficient, None).is_ok());
+
+        let below_min = params(&[
+            ("pair", "btc_jpy"),
+            ("order_type", "sell"),
+            ("rate", "3000000"),
+            ("amount", "0.001"),
+        ]);
+        let err = Order::validate_params(&below_min, balance).unwrap_err();
+        assert!(matches!(&err, Error::InvalidParam(_)));
+
+        let bad_precision = params(&[
+            ("pair", "btc_jpy"),
+            ("order_type", "market_sell"),
+            ("amount", "0.005000001"),
+        ]);
+        assert!(Order::validate_params(&bad_precision, balance).is_err());
+
+        let other_currency = params(&[
+            ("pair", "etc_jpy"),
+            ("order_type", "market_sell"),
+            ("amount", "3"),
+        ]);
+        assert!(Order::validate_params(&other_currency, balance).is_err());
+
+        let missing = params(&[("pair", "btc_jpy"), ("order_type", "market_buy")]);
+        assert!(Order::validate_params(&missing, balance).is_err());
+    }
+
+    #[test]
+    fn deserialize_order_transaction() {
+        let tx: super::model::OrderTransaction = serde_json::from_str(
+            r#"{
+                "id": 38,
+                "order_id": 49,
+                "created_at": "2015-11-18T07:02:21.000Z",
+                "funds": { "btc": "0.1", "jpy": "-4096.135" },
+                "pair": "btc_jpy",
+                "rate": "40900.0",
+                "fee_currency": "JPY",
+                "fee": "6.135",
+                "liquidity": "T",
+                "side": "buy"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(tx.fee_currency, Some(Currency::Jpy));
+        assert!((tx.effective_rate() - 40961.35 as PriceType).abs() < 1e-3);
+
+        let mut tx: super::model::OrderTransaction = serde_json::from_str(
+            r#"{
+                "id": 39,
+                "order_id": 50,
+                "created_at": "2015-11-18T07:02:21.000Z",
+                "funds": { "btc": "-0.1", "jpy": "4094.09" },
+                "pair": "btc_jpy",
+                "rate": "40900.0",
+                "fee_currency": null,
+                "fee": "0",
+                "liquidity": "M",
+                "side": "sell"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(tx.fee_currency, None);
+        assert_eq!(tx.effective_rate(), tx.rate);
+
+        tx.fee = 0.0001 as PriceType;
+        tx.fee_currency = Some(Currency::Btc);
+        assert!((tx.effective_rate() - 40859.1 as PriceType).abs() < 1e-3);
+    }
+
+    #[test]
+    fn net_base_received_after_fees() {
+        use super::model::net_base_received;
+
+        // `funds` are after the fee, as in `deserialize_order_transaction`
+        let transactions = vec![
+            transaction(
+                1,
+                49,
+                "buy",
+                "5000000",
+                ("0.00499", "-25000"),
+                ("BTC", "0.00001"),
+            ),
+            transaction(2, 49, "buy", "5000000", ("0.005", "-25010"), ("JPY", "10")),
+        ];
+        let net = transactions[0].net_base_amount().unwrap();
+        assert!((net - 0.00499).abs() < 1e-9);
+        assert_eq!(transactions[1].net_base_amount(), Some(0.005));
+        assert!((net_base_received(&transactions, OrderId(49)) - 0.00999).abs() < 1e-9);
+        assert_eq!(net_base_received(&transactions, OrderId(50)), 0.0);
+    }
+
+    #[test]
+    fn parse_transactions_one_by_one() {
+        let text = r#"{
+            "success": true,
+            "transactions": [
+                { "id": 38, "order_id": 49, "created_at": "2015-11-18T07:02:21.000Z",
+                  "funds": { "btc": "0.1", "jpy": "-4096.135" }, "pair": "btc_jpy",
+                  "rate": "40900.0", "fee_currency": "JPY", "fee": "6.135",
+                  "liquidity": "T", "side": "buy" },
+                { "id": 37, "order_id": 48, "created_at": "2015-11-18T07:02:21.000Z",
+                  "funds": { "btc": "-0.1", "jpy": "4094.09" }, "pair": "btc_jpy",
+                  "rate": "40900.0", "fee_currency": null, "fee": "0",
+                  "liquidity": "M", "side": "sell" }
+            ],
+            "extra": { "ignored": [1, 2] }
+        }"#;
+        let mut ids = Vec::new();
+        let count = Order::parse_transactions(text.as_bytes(), |tx| {
+            ids.push(tx.id);
+            true
+        })
+        .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(ids, vec![TransactionId(38), TransactionId(37)]);
+
+        // stopped by the callback
+        assert!(Order::parse_transactions(text.as_bytes(), |_| false).is_err());
+        assert!(Order::parse_transactions(&br#"{"success": false}"#[..], |_| true).is_err());
+        assert!(Order::parse_transactions(&br#"{"success": true}"#[..], |_| true).is_err());
+    }
+
+    #[tokio::test]
+    async fn stream_transactions_from_body() {
+        use futures_util::TryStreamExt;
+
+        let mut coincheck = Coincheck::new_offline();
+        coincheck.client().set_offline_response(
+            "/api/exchange/orders/transactions",
+            r#"{"success": true, "transactions": [
+                { "id": 38, "order_id": 49, "created_at": "2015-11-18T07:02:21.000Z",
+                  "funds": { "btc": "0.1", "jpy": "-4096.135" }, "pair": "btc_jpy",
+                  "rate": "40900.0", "fee_currency": "JPY", "fee": "6.135",
+                  "liquidity": "T", "side": "buy" },
+                { "id": 37, "order_id": 48, "created_at": "2015-11-18T07:02:21.000Z",
+                  "funds": { "btc": "-0.1", "jpy": "4094.09" }, "pair": "btc_jpy",
+                  "rate": "40900.0", "fee_currency": null, "fee": "0",
+                  "liquidity": "M", "side": "sell" }
+            ]}"#,
+        );
+        let api = &mut coincheck.private.order;
+
+        let transactions: Vec<_> = api.transactions_body_stream().try_collect().await.unwrap();
+        let ids: Vec<_> = transactions.iter().map(|tx| tx.id).collect();
+        assert_eq!(ids, vec![TransactionId(38), TransactionId(37)]);
+
+        let mut count = 0;
+        assert_eq!(api.transactions_for_each(|_| count += 1).await.unwrap(), 2);
+        assert_eq!(count, 2);
+    }
+
+    #[tokio::test]
+    async fn stream_transactions_over_http() {
+        use crate::client::tests::serve_once;
+        use crate::client::ClientConfig;
+
+        const BODY: &str = r#"{"success": true, "transactions": [
+            { "id": 38, "order_id": 49, "created_at": "2015-11-18T07:02:21.000Z",
+              "funds": { "btc": "0.1", "jpy": "-4096.135" }, "pair": "btc_jpy",
+              "rate": "40900.0", "fee_currency": "JPY", "fee": "6.135",
+              "liquidity": "T", "side": "buy" }
+        ]}"#;
+        for max_response_bytes in [None, Some(100)] {
+            let (base_url, server) = serve_once("200 OK", BODY).await;
+            let config = ClientConfig {
+                base_url,
+                rate_limit: None,
+                max_response_bytes,
+                ..Default::default()
+            };
+            let mut coincheck = Coincheck::new_with_config("key", "secret", config);
+            let mut ids = Vec::new();
+            let result = coincheck
+                .private
+                .order
+                .transactions_for_each(|tx| ids.push(tx.id))
+                .await;
+            let (head, _) = server.await.unwrap();
+            assert!(head.starts_with("get /api/exchange/orders/transactions "));
+
+            match max_response_bytes {
+                None => {
+                    assert_eq!(result.unwrap(), 1);
+                    assert_eq!(ids, vec![TransactionId(38)]);
+                }
+                Some(limit) => assert!(matches!(
+                    result.unwrap_err(), Error::ResponseTooLarge(n) if n == limit
+                )),
+            }
+        }
+    }
+
+    #[test]
+    fn empty_open_orders() {
+        let opens: super::model::OpenOrders =
+            serde_json::from_str(r#"{"success": true, "orders": []}"#).unwrap();
+        assert!(opens.is_empty());
+
+        // a missing list is an error, not an empty list
+        assert!(serde_json::from_str::<super::model::OpenOrders>(r#"{"success": true}"#).is_err());
+    }
+
+    #[test]
+    fn summarize_open_orders() {
+        // market buys have the amount in JPY, so they aren't summed
+        let market_buy = |id, pair| {
+            let mut order = open_order(id, pair, "market_buy", "0", "0.7", None);
+            order["pending_market_buy_amount"] = "10000".into();
+            order
+        };
+        let opens: super::model::OpenOrders = serde_json::from_value(open_orders(vec![
+            open_order(1, "btc_jpy", "buy", "3000000", "0.01", None),
+            open_order(2, "btc_jpy", "buy", "2900000", "0.02", None),
+            open_order(3, "btc_jpy", "sell", "3100000", "0.5", None),
+            open_order(4, "etc_jpy", "sell", "2000", "3", None),
+            open_order(5, "xxx_jpy", "sell", "100", "1", None),
+            market_buy(6, "btc_jpy"),
+            market_buy(7, "mona_jpy"),
+        ]))
+        .unwrap();
+
+        let summary = opens.summary();
+        assert_eq!(summary.len(), 2);
+        let (buy, sell) = summary[&CoinPair::BtcJpy];
+        assert!((buy - 0.03 as PriceType).abs() < 1e-6);
+        assert_eq!(sell, 0.5 as PriceType);
+        assert_eq!(summary[&CoinPair::EtcJpy], (0.0, 3.0));
+    }
+
+    #[test]
+    fn reconcile_open_orders() {
+        use super::model::DesiredOrder;
+
+        let opens: super::model::OpenOrders = serde_json::from_value(open_orders(vec![
+            open_order(1, "btc_jpy", "buy", "100", "0.5", None),
+            open_order(2, "btc_jpy", "buy", "99", "1", None),
+            open_order(3, "btc_jpy", "sell", "110", "1", None),
+            open_order(4, "btc_jpy", "sell", "90", "1", Some("95")),
+        ]))
+        .unwrap();
+
+        let desired = |side, rate, amount| DesiredOrder {
+            pair: CoinPair::BtcJpy,
+            side,
+            order_type: Default::default(),
+            rate,
+            amount,
+            stop_loss: None,
+        };
+        let plan = opens.reconcile(&[
+            // partially filled
+            desired(BaseOrderType::Buy, 100.0, 1.0),
+            desired(BaseOrderType::Buy, 98.0, 1.0),
+            desired(BaseOrderType::Sell, 110.2, 1.0),
+            desired(BaseOrderType::Sell, 110.0, 1.0),
+        ]);
+        assert_eq!(plan.to_cancel, vec![OrderId(2), OrderId(4)]);
+        assert_eq!(
+            plan.to_place,
+            vec![
+                desired(BaseOrderType::Buy, 98.0, 1.0),
+                desired(BaseOrderType::Sell, 110.0, 1.0),
+            ]
+        );
+
+        assert!(!plan.is_empty());
+
+        // a stop order only matches the open order with the same stop rate
+        let stop = |rate, stop_loss| DesiredOrder {
+            stop_loss: Some(stop_loss),
+            ..desired(BaseOrderType::Sell, rate, 1.0)
+        };
+        let plan = opens.reconcile(&[stop(90.0, 95.0), stop(110.0, 105.0)]);
+        assert_eq!(plan.to_place, vec![stop(110.0, 105.0)]);
+        assert_eq!(plan.to_cancel, vec![OrderId(1), OrderId(2), OrderId(3)]);
+
+        // market orders are always placed
+        let market = DesiredOrder {
+            order_type: super::model::DesiredOrderType::Market,
+            ..desired(BaseOrderType::Buy, 0.0, 1.0)
+        };
+        let plan = opens.reconcile(std::slice::from_ref(&market));
+        assert_eq!(plan.to_place, vec![market]);
+
+        assert_eq!(
+            opens.reconcile(&[]).to_cancel,
+            vec![OrderId(1), OrderId(2), OrderId(3), OrderId(4)]
+        );
+    }
+
+    #[test]
+    fn reconcile_twice() {
+        use super::model::DesiredOrder;
+
+        let opens = vec![
+            open_order(1, "btc_jpy", "buy", "99", "1", None),
+            open_order(2, "btc_jpy", "sell", "90", "1", Some("95")),
+        ];
+        let desired = [
+            DesiredOrder {
+                pair: CoinPair::BtcJpy,
+                side: BaseOrderType::Buy,
+                order_type: Default::default(),
+                rate: 100.0,
+                amount: 1.0,
+                stop_loss: None,
+            },
+            DesiredOrder {
+                pair: CoinPair::BtcJpy,
+                side: BaseOrderType::Sell,
+                order_type: Default::default(),
+                rate: 90.0,
+                amount: 1.0,
+                stop_loss: Some(96.0),
+            },
+        ];
+
+        let first: super::model::OpenOrders =
+            serde_json::from_value(open_orders(opens.clone())).unwrap();
+        let plan = first.reconcile(&desired);
+        assert_eq!(plan.to_cancel, vec![OrderId(1), OrderId(2)]);
+        assert_eq!(plan.to_place, desired);
+
+        // apply the plan
+        let placed = plan.to_place.iter().enumerate().map(|(i, order)| {
+            let side = if order.side == BaseOrderType::Buy {
+                "buy"
+            } else {
+                "sell"
+            };
+            let stop = order.stop_loss.map(|rate| rate.to_string());
+            open_order(
+                10 + i as IdType,
+                "btc_jpy",
+                side,
+                &order.rate.to_string(),
+                &order.amount.to_string(),
+                stop.as_deref(),
+            )
+        });
+        let second: super::model::OpenOrders =
+            serde_json::from_value(open_orders(placed.collect())).unwrap();
+        assert!(second.reconcile(&desired).is_empty());
+    }
+
+    #[tokio::test]
+    async fn cancel_order_not_open() {
+        let mut coincheck = Coincheck::new_offline();
+        coincheck.client().set_offline_response(
+            "/api/exchange/orders/opens",
+            r#"{"success": true, "orders": []}"#,
+        );
+        let err = coincheck
+            .private
+            .order
+            .cancel_checked(OrderId(0))
+            .await
+            .unwrap_err();
+        assert!(matches!(&err, Error::OrderNotFound(OrderId(0))));
+    }
+
+    #[tokio::test]
+    async fn detect_self_match() {
+        let mut coincheck = Coincheck::new_offline();
+        coincheck.client().set_offline_response(
+            "/api/exchange/orders/opens",
+            &open_orders(vec![
+                open_order(1, "btc_jpy", "buy", "100", "1", None),
+                open_order(2, "btc_jpy", "sell", "110", "1", None),
+                open_order(3, "btc_jpy", "sell", "90", "1", Some("95")),
+                open_order(4, "etc_jpy", "sell", "50", "1", None),
+            ])
+            .to_string(),
+        );
+        let api = &mut coincheck.private.order;
+        let opens = api.opens().await.unwrap();
+        let pair = &CoinPair::BtcJpy;
+
+        assert_eq!(
+            opens.self_matches(pair, BaseOrderType::Buy, 110.0),
+            vec![OrderId(2)]
+        );
+        assert_eq!(
+            opens.self_matches(pair, BaseOrderType::Sell, 100.0),
+            vec![OrderId(1)]
+        );
+        // the stop order isn't on the order book, and the other pair doesn't match
+        assert!(!api
+            .would_self_match(pair, BaseOrderType::Buy, 109.0)
+            .await
+            .unwrap());
+        assert!(!api
+            .would_self_match(pair, BaseOrderType::Sell, 101.0)
+            .await
+            .unwrap());
+        assert!(api
+            .would_self_match(pair, BaseOrderType::Buy, 120.0)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn place_with_stop_rejects_invalid_entry() {
+        let mut coincheck = Coincheck::new_with_keys("hoge", "fuga");
+        let api = &mut coincheck.private.order;
+        let interval = std::time::Duration::from_secs(1);
+
+        let market = params(&[
+            ("pair", "btc_jpy"),
+            ("order_type", "market_buy"),
+            ("market_buy_amount", "1000"),
+        ]);
+        let cancel = CancellationToken::new();
+        assert!(api
+            .place_with_stop(&market, 1.0, interval, cancel.clone())
+            .await
+            .is_err());
+
+        let buy = params(&[
+            ("pair", "btc_jpy"),
+            ("order_type", "buy"),
+            ("rate", "3000000"),
+            ("amount", "0.005"),
+        ]);
+        let stop = 3100000 as PriceType;
+        assert!(api
+            .place_with_stop(&buy, stop, interval, cancel)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn place_with_stop_cancelled() {
+        let mut coincheck = Coincheck::new_offline();
+        let client = coincheck.client();
+        client.set_offline_response(
+            "/api/exchange/orders",
+            r#"{"success": true, "id": 12345, "rate": "3000000.0", "amount": "0.01",
+                "order_type": "buy", "stop_loss_rate": null, "pair": "btc_jpy",
+                "created_at": "2015-01-10T05:55:38.000Z"}"#,
+        );
+        client.set_offline_response(
+            "/api/exchange/orders/opens",
+            &open_orders(vec![open_order(
+                12345, "btc_jpy", "buy", "3000000", "0.01", None,
+            )])
+            .to_string(),
+        );
+        client.set_offline_response(
+            "/api/exchange/orders/12345",
+            r#"{"success": true, "id": 12345}"#,
+        );
+
+        // the entry never fills: stop waiting, and cancel the entry
+        let cancel = CancellationToken::new();
+        let canceller = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            canceller.cancel();
+        });
+        let entry = params(&[
+            ("pair", "btc_jpy"),
+            ("order_type", "buy"),
+            ("rate", "3000000"),
+            ("amount", "0.01"),
+        ]);
+        let api = &mut coincheck.private.order;
+        let interval = std::time::Duration::from_secs(60);
+        let result = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            api.place_with_stop(&entry, 2900000 as PriceType, interval, cancel),
+        )
+        .await
+        .unwrap();
+        let err = result.unwrap_err();
+        assert!(err.to_string().contains("entry order 12345 was cancelled"));
+    }
+
+    #[tokio::test]
+    async fn limit_order_expiry() {
+        let mut coincheck = Coincheck::new_offline();
+        let client = coincheck.client();
+        client.set_offline_response(
+            "/api/exchange/orders",
+            r#"{"success": true, "id": 12345, "rate": "30010.0", "amount": "1.3",
+                "order_type": "buy", "stop_loss_rate": null, "pair": "btc_jpy",
+                "created_at": "2015-01-10T05:55:38.000Z"}"#,
+        );
+        client.set_offline_response(
+            "/api/exchange/orders/opens",
+            &open_orders(vec![open_order(
+                12345, "btc_jpy", "buy", "30010", "1.3", None,
+            )])
+            .to_string(),
+        );
+        client.set_offline_response(
+            "/api/exchange/orders/12345",
+            r#"{"success": true, "id": 12345}"#,
+        );
+
+        let api = &mut coincheck.private.order;
+        let ttl = std::time::Duration::from_millis(10);
+        let (order, cancelled) = api
+            .new_limit_buy_with_expiry(&CoinPair::BtcJpy, 30010.0, 1.3, ttl)
+            .await
+            .unwrap();
+        assert_eq!(cancelled.unwrap().id, order.id);
+
+        // filled before the expiry
+        coincheck.client().set_offline_response(
+            "/api/exchange/orders/opens",
+            r#"{"success": true, "orders": []}"#,
+        );
+        let (_, cancelled) = coincheck
+            .private
+            .order
+            .new_limit_sell_with_expiry(&CoinPair::BtcJpy, 30010.0, 1.3, ttl)
+            .await
+            .unwrap();
+        assert!(cancelled.is_none());
+    }
+
+    #[tokio::test]
+    async fn order_notional_limit() {
+        let mut coincheck = Coincheck::new_offline();
+        coincheck
+            .client()
+            .set_max_order_notional(Some(100_000 as PriceType));
+        let api = &mut coincheck.private.order;
+
+        // blocked without reaching the (offline) API
+        let err = api
+            .new_limit_buy(&CoinPair::BtcJpy, 5_000_000.0, 2.0)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            &err,
+            Error::LimitExceeded {
+                limit: 100_000.0,
+                requested: 10_000_000.0
+            }
+        ));
+        let err = api
+            .new_market_buy(&CoinPair::BtcJpy, 100_001.0)
+            .await
+            .unwrap_err();
+        assert!(matches!(&err, Error::LimitExceeded { .. }));
+
+        // malformed parameters are rejected like in the validation
+        for (key, value) in [("pair", "xxx_jpy"), ("order_type", "hold"), ("rate", "abc")] {
+            let mut params = params(&[
+                ("pair", "btc_jpy"),
+                ("order_type", "buy"),
+                ("rate", "5000000"),
+                ("amount", "0.01"),
+            ]);
+            params.insert(key, value);
+            let err = api.new_any(&params).await.unwrap_err();
+            assert!(matches!(&err, Error::InvalidParam(_)), "{}", key);
+        }
+
+        // market sells are estimated with the rate API
+        coincheck.client().set_offline_response(
+            "/api/exchange/orders/rate",
+            r#"{"success": true, "rate": "5000000", "amount": "0.1", "price": "500000"}"#,
+        );
+        let api = &mut coincheck.private.order;
+        let err = api
+            .new_market_sell(&CoinPair::BtcJpy, 0.1)
+            .await
+            .unwrap_err();
+        assert!(matches!(&err, Error::LimitExceeded { .. }));
+
+        coincheck.client().set_offline_response(
+            "/api/exchange/orders",
+            r#"{"success": true, "id": 12345, "rate": "5000000.0", "amount": "0.01",
+                "order_type": "buy", "stop_loss_rate": null, "pair": "btc_jpy",
+                "created_at": "2015-01-10T05:55:38.000Z"}"#,
+        );
+        assert!(coincheck
+            .private
+            .order
+            .new_limit_buy(&CoinPair::BtcJpy, 5_000_000.0, 0.01)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn submit_desired_orders() {
+        use super::model::{DesiredOrder, DesiredOrderType};
+
+        let desired: Vec<DesiredOrder> = serde_json::from_str(
+            r#"[
+                {"pair": "btc_jpy", "side": "buy", "rate": 5000000, "amount": 0.01},
+                {"pair": "btc_jpy", "side": "sell", "type": "market", "rate": 5000000,
+                 "amount": 0.01, "stop_loss": 4900000},
+                {"pair": "btc_jpy", "side": "buy", "type": "market", "rate": 5000000,
+                 "amount": 0.1}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(desired[0].order_type, DesiredOrderType::Limit);
+        assert_eq!(desired[0].stop_loss, None);
+        assert_eq!(desired[1].order_type, DesiredOrderType::Market);
+        assert_eq!(desired[1].stop_loss, Some(4_900_000.0));
+
+        let mut coincheck = Coincheck::new_offline();
+        coincheck.client().set_offline_response(
+            "/api/exchange/orders",
+            r#"{"success": true, "id": 12345, "rate": "5000000.0", "amount": "0.01",
+                "order_type": "buy", "stop_loss_rate": null, "pair": "btc_jpy",
+                "created_at": "2015-01-10T05:55:38.000Z"}"#,
+        );
+        let api = &mut coincheck.private.order;
+        assert!(api.submit_desired(&desired[0]).await.is_ok());
+        assert!(api.submit_desired(&desired[1]).await.is_ok());
+
+        // the market buy is `rate * amount` JPY
+        coincheck
+            .client()
+            .set_max_order_notional(Some(100_000 as PriceType));
+        let err = coincheck
+            .private
+            .order
+            .submit_desired(&desired[2])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            &err,
+            Error::LimitExceeded {
+                limit: 100_000.0,
+                requested: 500_000.0
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn order_guard() {
+        let coincheck = Coincheck::new_offline();
+        let client = coincheck.client();
+        client.set_offline_response(
+            "/api/exchange/orders",
+            r#"{"success": true, "id": 12345, "rate": "5000000.0", "amount": "0.01",
+                "order_type": "buy", "stop_loss_rate": null, "pair": "btc_jpy",
+                "created_at": "2015-01-10T05:55:38.000Z"}"#,
+        );
+        client.set_offline_response(
+            "/api/exchange/orders/opens",
+            &open_orders(vec![open_order(
+                12345, "btc_jpy", "buy", "5000000", "0.01", None,
+            )])
+            .to_string(),
+        );
+        client.set_offline_response(
+            "/api/exchange/orders/12345",
+            r#"{"success": true, "id": 12345}"#,
+        );
+
+        let mut guard = coincheck.private.order.managed_session();
+        guard
+            .new_any(&params(&[
+                ("pair", "btc_jpy"),
+                ("order_type", "buy"),
+                ("rate", "5000000"),
+                ("amount", "0.01"),
+            ]))
+            .await
+            .unwrap();
+        // already filled
+        guard.track(OrderId(1));
+        assert_eq!(guard.order_ids(), &[OrderId(12345), OrderId(1)]);
+
+        let cancelled = guard.cancel_all().await.unwrap();
+        assert_eq!(cancelled.len(), 1);
+        assert_eq!(cancelled[0].id, OrderId(12345));
+
+        // dropped with a tracked order: the cancellation is spawned
+        let mut guard = coincheck.private.order.managed_session();
+        guard.track(OrderId(12345));
+        drop(guard);
+    }
+
+    #[test]
+    fn order_guard_dropped_outside_runtime() {
+        let coincheck = Coincheck::new_offline();
+        let mut guard = coincheck.private.order.managed_session();
+        guard.track(OrderId(12345));
+        drop(guard);
+    }
 
     // Warn: THE BUY/SELL/CANCEL FUNCTIONS ARE NOT TESTED HERE!
     #[tokio::test]
@@ -417,4 +2254,14 @@ mod tests {
             .await
             .is_ok());
     }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn recent_transactions_api() {
+        let mut coincheck = Coincheck::new_with_env_keys();
+        let api = &mut coincheck.private.order;
+
+        let transactions = api.recent_transactions(5, SortOrder::Desc).await.unwrap();
+        assert!(transactions.len() <= 5);
+    }
 }
@@ -1,6 +1,11 @@
-use crate::client::SharedClient;
-use anyhow::Result;
-use reqwest::Method;
+use crate::client::{Client, SharedClient};
+use crate::error::{Error, Result};
+use crate::paginate::paginate;
+use crate::types::*;
+use chrono::{DateTime, Utc};
+use futures_util::stream::TryStreamExt;
+use reqwest::{Method, StatusCode};
+use tokio_util::sync::CancellationToken;
 
 /// Private API - Withdraws JPY
 ///
@@ -24,10 +29,23 @@ pub mod model {
         pub data: Vec<BankAccount>,
     }
 
+    impl ApiResponse for BankAccounts {
+        fn success(&self) -> bool {
+            self.success
+        }
+    }
+
+    impl BankAccounts {
+        /// 銀行口座が登録されていないか
+        pub fn is_empty(&self) -> bool {
+            self.data.is_empty()
+        }
+    }
+
     /// 銀行口座情報
     #[derive(Debug, Serialize, Deserialize)]
     pub struct BankAccount {
-        pub id: IdType,
+        pub id: BankAccountId,
         pub bank_name: String,
         pub branch_name: String,
         pub bank_account_type: String,
@@ -35,6 +53,67 @@ pub mod model {
         pub name: String,
     }
 
+    /// 銀行口座の登録結果
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct BankAccountResult {
+        pub success: bool,
+        pub data: BankAccount,
+    }
+
+    impl ApiResponse for BankAccountResult {
+        fn success(&self) -> bool {
+            self.success
+        }
+    }
+
+    /// 口座種別
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum BankAccountType {
+        /// 普通預金
+        Futsuu,
+        /// 当座預金
+        Touza,
+    }
+
+    impl BankAccountType {
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                BankAccountType::Futsuu => "futsu",
+                BankAccountType::Touza => "toza",
+            }
+        }
+    }
+
+    impl std::fmt::Display for BankAccountType {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}", self.as_str())
+        }
+    }
+
+    impl std::str::FromStr for BankAccountType {
+        type Err = &'static str;
+
+        fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+            [BankAccountType::Futsuu, BankAccountType::Touza]
+                .iter()
+                .find(|t| t.as_str() == s)
+                .copied()
+                .ok_or("undefined BankAccountType type")
+        }
+    }
+
+    /// 銀行口座の削除結果
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct DeleteBankAccountResult {
+        pub success: bool,
+    }
+
+    impl ApiResponse for DeleteBankAccountResult {
+        fn success(&self) -> bool {
+            self.success
+        }
+    }
+
     /// 出金履歴
     #[derive(Debug, Serialize, Deserialize)]
     pub struct Withdraws {
@@ -43,21 +122,90 @@ pub mod model {
         pub data: Vec<Withdraw>,
     }
 
+    impl ApiResponse for Withdraws {
+        fn success(&self) -> bool {
+            self.success
+        }
+    }
+
+    impl Withdraws {
+        /// 出金履歴が空か
+        pub fn is_empty(&self) -> bool {
+            self.data.is_empty()
+        }
+    }
+
     /// 出金情報
     #[serde_as]
     #[derive(Debug, Serialize, Deserialize)]
     pub struct Withdraw {
-        pub id: IdType,
+        pub id: WithdrawId,
         pub status: String,
         #[serde_as(as = "DisplayFromStr")]
         pub amount: PriceType,
         pub currency: String,
         pub created_at: DateTime<Utc>,
-        pub bank_account_id: IdType,
+        pub bank_account_id: BankAccountId,
         #[serde_as(as = "DisplayFromStr")]
         pub fee: PriceType,
         pub is_fast: bool,
     }
+
+    impl Withdraw {
+        /// キャンセルできる出金申請のステータス (処理待ち)
+        pub const STATUS_PENDING: &'static str = "pending";
+
+        /// キャンセルできるか (処理待ちか)
+        pub fn is_cancellable(&self) -> bool {
+            self.status == Self::STATUS_PENDING
+        }
+    }
+
+    /// 出金申請の作成結果
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct WithdrawResult {
+        pub success: bool,
+        #[serde(flatten)]
+        pub withdraw: Withdraw,
+    }
+
+    impl ApiResponse for WithdrawResult {
+        fn success(&self) -> bool {
+            self.success
+        }
+    }
+
+    /// 出金申請のキャンセル結果
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct CancelResult {
+        pub success: bool,
+        pub id: WithdrawId,
+    }
+
+    impl ApiResponse for CancelResult {
+        fn success(&self) -> bool {
+            self.success
+        }
+    }
+
+    /// 出金の準備状況
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct WithdrawalReadiness {
+        /// 本人確認が完了しているか
+        pub identity_confirmed: bool,
+        /// 登録済みの銀行口座の数
+        pub bank_accounts: usize,
+    }
+
+    impl WithdrawalReadiness {
+        /// アカウント情報の `identity_status` で本人確認済みを表す値
+        pub const IDENTITY_VERIFIED: &'static str = "identity_verified";
+
+        /// 出金できる状態か (本人確認済みで、銀行口座が登録されていること)
+        pub fn is_ready(&self) -> bool {
+            self.identity_confirmed && self.bank_accounts > 0
+        }
+    }
 }
 
 impl WithdrawsJpy {
@@ -67,6 +215,51 @@ impl WithdrawsJpy {
 
     const USE_AUTH: bool = true;
 
+    /// 出金手数料 (円)
+    ///
+    /// <https://coincheck.com/ja/exchange/fee>
+    pub const WITHDRAW_FEE: PriceType = 407 as PriceType;
+
+    /// 即時出金の手数料 (円)
+    pub const FAST_WITHDRAW_FEE: PriceType = 770 as PriceType;
+
+    /// 出金手数料を取得します。
+    pub fn withdraw_fee(is_fast: bool) -> PriceType {
+        if is_fast {
+            Self::FAST_WITHDRAW_FEE
+        } else {
+            Self::WITHDRAW_FEE
+        }
+    }
+
+    /// 出金額の確認
+    ///
+    /// 出金申請の前に、出金額が正の整数で手数料を上回っているかをローカルで確認します。
+    pub fn validate_withdraw(amount: PriceType, is_fast: bool) -> Result<()> {
+        if !amount.is_finite() || amount <= 0 as PriceType {
+            return Err(Error::InvalidParam(format!(
+                "withdraw amount must be positive: {}",
+                amount
+            )));
+        }
+        if amount.fract() != 0 as PriceType {
+            return Err(Error::InvalidParam(format!(
+                "withdraw amount must be in whole yen: {}",
+                amount
+            )));
+        }
+
+        let fee = Self::withdraw_fee(is_fast);
+        if amount <= fee {
+            return Err(Error::InvalidParam(format!(
+                "withdraw amount {} JPY must be greater than the fee {} JPY",
+                amount, fee
+            )));
+        }
+
+        Ok(())
+    }
+
     /// 銀行口座一覧
     ///
     /// お客様の出金用に登録された銀行口座の一覧を返します。
@@ -74,15 +267,73 @@ impl WithdrawsJpy {
     /// <https://coincheck.com/ja/documents/exchange/api#bank-accounts>
     pub async fn bank_accounts(&mut self) -> Result<model::BankAccounts> {
         self.client
-            .borrow_mut()
             .request_and_get_json(Method::GET, "/api/bank_accounts", None, Self::USE_AUTH)
             .await
     }
 
-    // TODO: implement 銀行口座の登録 POST /api/bank_accounts
-    // https://coincheck.com/ja/documents/exchange/api#bank-accounts-create
-    // TODO: implement 銀行口座の削除 DELETE /api/bank_accounts/[id]
-    // https://coincheck.com/ja/documents/exchange/api#bank-accounts-destroy
+    /// 銀行口座の登録
+    ///
+    /// 出金用の銀行口座を登録します。`name` は口座名義 (カタカナ) です。
+    ///
+    /// <https://coincheck.com/ja/documents/exchange/api#bank-accounts-create>
+    pub async fn create_bank_account(
+        &mut self,
+        bank_name: &str,
+        branch_name: &str,
+        account_type: model::BankAccountType,
+        number: &str,
+        name: &str,
+    ) -> Result<model::BankAccount> {
+        let params = Self::bank_account_params(bank_name, branch_name, account_type, number, name);
+        let result: model::BankAccountResult = self
+            .client
+            .request_and_get_json(
+                Method::POST,
+                "/api/bank_accounts",
+                Some(&params),
+                Self::USE_AUTH,
+            )
+            .await?;
+        Ok(result.data)
+    }
+
+    fn bank_account_params<'a>(
+        bank_name: &'a str,
+        branch_name: &'a str,
+        account_type: model::BankAccountType,
+        number: &'a str,
+        name: &'a str,
+    ) -> Params<'a> {
+        let mut params = Params::new();
+        params.insert("bank_name", bank_name);
+        params.insert("branch_name", branch_name);
+        params.insert("bank_account_type", account_type.as_str());
+        params.insert("number", number);
+        params.insert("name", name);
+        params
+    }
+
+    /// 銀行口座の削除
+    ///
+    /// 出金用に登録された銀行口座を削除します。
+    /// `id` の銀行口座が登録されていない (`404 Not Found` または `400 Bad Request` の) 場合は
+    /// [`Error::BankAccountNotFound`] を返します。
+    ///
+    /// <https://coincheck.com/ja/documents/exchange/api#bank-accounts-destroy>
+    pub async fn delete_bank_account(
+        &mut self,
+        id: BankAccountId,
+    ) -> Result<model::DeleteBankAccountResult> {
+        let url = format!("/api/bank_accounts/{}", id);
+        let result = self
+            .client
+            .request_and_get_json(Method::DELETE, &url, None, Self::USE_AUTH)
+            .await;
+        result.map_err(|err| match err.status() {
+            Some(StatusCode::NOT_FOUND | StatusCode::BAD_REQUEST) => Error::BankAccountNotFound(id),
+            _ => err,
+        })
+    }
 
     /// 出金履歴
     ///
@@ -91,21 +342,394 @@ impl WithdrawsJpy {
     /// <https://coincheck.com/ja/documents/exchange/api#withdraws>
     pub async fn withdraws(&mut self) -> Result<model::Withdraws> {
         self.client
-            .borrow_mut()
             .request_and_get_json(Method::GET, "/api/withdraws", None, Self::USE_AUTH)
             .await
     }
 
-    // TODO: implement 出金申請の作成 POST /api/withdraws
-    // https://coincheck.com/ja/documents/exchange/api#withdraws-create
-    // TODO: implement 出金申請のキャンセル DELETE /api/withdraws/[id]
-    // https://coincheck.com/ja/documents/exchange/api#withdraws-destroy
+    /// 出金履歴（ページネーション）
+    ///
+    /// 日本円出金の申請の履歴を表示します。
+    /// `pagination.limit` は最大 [`Pagination::MAX_LIMIT`] 件に丸められます。
+    ///
+    /// <https://coincheck.com/ja/documents/exchange/api#withdraws>
+    pub async fn withdraws_pagination(
+        &mut self,
+        pagination: Pagination,
+    ) -> Result<model::Withdraws> {
+        Self::fetch_withdraws_page(&self.client, &pagination).await
+    }
+
+    /// 出金申請の検索
+    ///
+    /// 出金履歴を新しい順にたどり、`amount` 円で `created_after` 以降に申請された、
+    /// 処理待ち (`pending`) の出金申請を探します。ID が分からない出金申請をキャンセルするときに使います。
+    pub async fn find_withdraw(
+        &mut self,
+        amount: PriceType,
+        created_after: DateTime<Utc>,
+    ) -> Result<Option<model::Withdraw>> {
+        let client = self.client.clone();
+        let mut stream = paginate(
+            SortOrder::Desc,
+            Pagination::MAX_LIMIT,
+            CancellationToken::new(),
+            |withdraw: &model::Withdraw| withdraw.id.0,
+            move |pagination| {
+                let client = client.clone();
+                async move {
+                    Self::fetch_withdraws_page(&client, &pagination)
+                        .await
+                        .map(|page| page.data)
+                }
+            },
+        );
+
+        while let Some(withdraw) = stream.try_next().await? {
+            if withdraw.created_at < created_after {
+                break;
+            }
+            if withdraw.is_cancellable() && withdraw.amount == amount {
+                return Ok(Some(withdraw));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn fetch_withdraws_page(
+        client: &Client,
+        pagination: &Pagination,
+    ) -> Result<model::Withdraws> {
+        let params = params_from_serialize(&PaginationParams::from(pagination))?;
+        let params = borrow_params(&params);
+
+        client
+            .request_and_get_json(Method::GET, "/api/withdraws", Some(&params), Self::USE_AUTH)
+            .await
+    }
+
+    /// 出金申請の作成
+    ///
+    /// 登録済みの銀行口座 `bank_account_id` に日本円を出金します。
+    /// 結果の `fee` は実際に差し引かれる手数料で、即時出金 (`is_fast`) の場合は高くなります。
+    ///
+    /// 出金額が [`WithdrawsJpy::validate_withdraw`] の確認を通らない場合や、
+    /// [`Client::set_max_withdraw_amount`](crate::client::Client::set_max_withdraw_amount)
+    /// の上限を超える場合は、出金申請を送らずにエラー (上限の場合は [`Error::LimitExceeded`]) を返します。
+    ///
+    /// <https://coincheck.com/ja/documents/exchange/api#withdraws-create>
+    pub async fn create_withdraw(
+        &mut self,
+        bank_account_id: BankAccountId,
+        amount: PriceType,
+        is_fast: bool,
+    ) -> Result<model::WithdrawResult> {
+        Self::validate_withdraw(amount, is_fast)?;
+        if let Some(limit) = self.client.config().max_withdraw_amount {
+            if amount > limit {
+                return Err(Error::LimitExceeded {
+                    limit,
+                    requested: amount,
+                });
+            }
+        }
+
+        let mut params = Params::new();
+        let bank_account_id = &bank_account_id.to_string();
+        let amount = &amount.to_string();
+        params.insert("bank_account_id", bank_account_id);
+        params.insert("amount", amount);
+        params.insert("currency", Currency::Jpy.as_str());
+        params.insert("is_fast", if is_fast { "true" } else { "false" });
+        self.client
+            .request_and_get_json(
+                Method::POST,
+                "/api/withdraws",
+                Some(&params),
+                Self::USE_AUTH,
+            )
+            .await
+    }
+
+    /// 出金申請の作成 (出金先と出金額を確認)
+    ///
+    /// [`WithdrawsJpy::validate_withdraw`] で出金額を確認し、[`WithdrawsJpy::bank_accounts`] に
+    /// `bank_account_id` があることを確認してから出金申請を作成します。
+    /// 銀行口座が見つからない場合は出金申請を送らずに [`Error::BankAccountNotFound`] を返します。
+    pub async fn create_withdraw_checked(
+        &mut self,
+        bank_account_id: BankAccountId,
+        amount: PriceType,
+        is_fast: bool,
+    ) -> Result<model::WithdrawResult> {
+        Self::validate_withdraw(amount, is_fast)?;
+        let bank_accounts = self.bank_accounts().await?;
+        if !bank_accounts.data.iter().any(|a| a.id == bank_account_id) {
+            return Err(Error::BankAccountNotFound(bank_account_id));
+        }
+        self.create_withdraw(bank_account_id, amount, is_fast).await
+    }
+
+    /// 出金申請のキャンセル
+    ///
+    /// 処理待ちの出金申請をキャンセルします。キャンセルできるのは、ステータスが処理待ち
+    /// ([`model::Withdraw::is_cancellable`]) の出金申請だけです。
+    ///
+    /// 処理中や処理済みのため取引所に拒否された (`success: false` または `400 Bad Request` の)
+    /// 場合は [`Error::WithdrawNotCancellable`] を返します。
+    ///
+    /// <https://coincheck.com/ja/documents/exchange/api#withdraws-destroy>
+    pub async fn cancel_withdraw(&mut self, id: WithdrawId) -> Result<model::CancelResult> {
+        let url = format!("/api/withdraws/{}", id);
+        let result = self
+            .client
+            .request_and_get_json(Method::DELETE, &url, None, Self::USE_AUTH)
+            .await;
+        result.map_err(|err| match err {
+            // `success: false` or `400 Bad Request`, but not an authentication failure
+            Error::Api {
+                status: StatusCode::OK | StatusCode::BAD_REQUEST,
+                ..
+            } => Error::WithdrawNotCancellable {
+                id,
+                source: Box::new(err),
+            },
+            err => err,
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::WithdrawsJpy;
+    use crate::client::tests::serve_once;
+    use crate::client::ClientConfig;
+    use crate::error::Error;
+    use crate::types::*;
     use crate::Coincheck;
 
+    #[test]
+    fn validate_withdraw_amount() {
+        assert!(WithdrawsJpy::validate_withdraw(10000 as PriceType, false).is_ok());
+        assert!(WithdrawsJpy::validate_withdraw(10000 as PriceType, true).is_ok());
+        assert!(WithdrawsJpy::validate_withdraw(0 as PriceType, false).is_err());
+        assert!(WithdrawsJpy::validate_withdraw(-1 as PriceType, false).is_err());
+        assert!(WithdrawsJpy::validate_withdraw(1000.5 as PriceType, false).is_err());
+        assert!(WithdrawsJpy::validate_withdraw(WithdrawsJpy::WITHDRAW_FEE, false).is_err());
+        assert!(WithdrawsJpy::validate_withdraw(500 as PriceType, false).is_ok());
+        assert!(WithdrawsJpy::validate_withdraw(500 as PriceType, true).is_err());
+    }
+
+    #[test]
+    fn withdrawal_readiness() {
+        use super::model::WithdrawalReadiness;
+
+        let ready = WithdrawalReadiness {
+            identity_confirmed: true,
+            bank_accounts: 1,
+        };
+        assert!(ready.is_ready());
+        assert!(!WithdrawalReadiness {
+            bank_accounts: 0,
+            ..ready
+        }
+        .is_ready());
+        assert!(!WithdrawalReadiness {
+            identity_confirmed: false,
+            ..ready
+        }
+        .is_ready());
+    }
+
+    #[tokio::test]
+    async fn create_bank_account() {
+        use super::model::BankAccountType;
+
+        let params = WithdrawsJpy::bank_account_params(
+            "みずほ",
+            "東京営業部",
+            BankAccountType::Futsuu,
+            "0123456",
+            "タナカ タロウ",
+        );
+        assert_eq!(
+            params,
+            Params::from([
+                ("bank_name", "みずほ"),
+                ("branch_name", "東京営業部"),
+                ("bank_account_type", "futsu"),
+                ("number", "0123456"),
+                ("name", "タナカ タロウ"),
+            ])
+        );
+        assert_eq!("toza".parse(), Ok(BankAccountType::Touza));
+        assert!("touza".parse::<BankAccountType>().is_err());
+
+        let mut coincheck = Coincheck::new_offline();
+        coincheck.client().set_offline_response(
+            "/api/bank_accounts",
+            r#"{"success": true, "data": {"id": 641, "bank_name": "みずほ",
+                "branch_name": "東京営業部", "bank_account_type": "futsu",
+                "number": "0123456", "name": "タナカ タロウ"}}"#,
+        );
+        let account = coincheck
+            .private
+            .withdraws_jpy
+            .create_bank_account(
+                "みずほ",
+                "東京営業部",
+                BankAccountType::Futsuu,
+                "0123456",
+                "タナカ タロウ",
+            )
+            .await
+            .unwrap();
+        assert_eq!(account.id, BankAccountId(641));
+    }
+
+    #[tokio::test]
+    async fn delete_bank_account() {
+        let mut coincheck = Coincheck::new_offline();
+        coincheck
+            .client()
+            .set_offline_response("/api/bank_accounts/243", r#"{"success": true}"#);
+        let result = coincheck
+            .private
+            .withdraws_jpy
+            .delete_bank_account(BankAccountId(243))
+            .await
+            .unwrap();
+        assert!(result.success);
+
+        let (base_url, server) =
+            serve_once("404 Not Found", r#"{"success":false,"error":"not found"}"#).await;
+        let config = ClientConfig {
+            base_url,
+            rate_limit: None,
+            ..Default::default()
+        };
+        let mut coincheck = Coincheck::new_with_config("key", "secret", config);
+        let err = coincheck
+            .private
+            .withdraws_jpy
+            .delete_bank_account(BankAccountId(1))
+            .await
+            .unwrap_err();
+        let (head, _) = server.await.unwrap();
+        assert!(head.starts_with("delete /api/bank_accounts/1 "));
+        assert!(matches!(&err, Error::BankAccountNotFound(BankAccountId(1))));
+        assert_eq!(err.to_string(), "bank account not registered: 1");
+    }
+
+    #[tokio::test]
+    async fn create_withdraw_to_unknown_bank_account() {
+        let mut coincheck = Coincheck::new_offline();
+        coincheck.client().set_offline_response(
+            "/api/bank_accounts",
+            r#"{"success": true, "data": [
+                {"id": 243, "bank_name": "みずほ", "branch_name": "東京営業部",
+                 "bank_account_type": "futsu", "number": "0123456", "name": "タナカ タロウ"}]}"#,
+        );
+        coincheck.client().set_offline_response(
+            "/api/withdraws",
+            r#"{"success": true, "id": 398, "status": "pending", "amount": "10000.0",
+                "currency": "JPY", "created_at": "2015-12-02T05:41:19.000Z",
+                "bank_account_id": 243, "fee": "407.0", "is_fast": false}"#,
+        );
+        let api = &mut coincheck.private.withdraws_jpy;
+
+        let err = api
+            .create_withdraw_checked(BankAccountId(1), 10000.0, false)
+            .await
+            .unwrap_err();
+        assert!(matches!(&err, Error::BankAccountNotFound(BankAccountId(1))));
+
+        let result = api
+            .create_withdraw_checked(BankAccountId(243), 10000.0, false)
+            .await
+            .unwrap();
+        assert_eq!(result.withdraw.id, WithdrawId(398));
+        assert_eq!(result.withdraw.bank_account_id, BankAccountId(243));
+    }
+
+    #[tokio::test]
+    async fn create_withdraw() {
+        let mut coincheck = Coincheck::new_offline();
+        coincheck.client().set_offline_response(
+            "/api/withdraws",
+            r#"{"success": true, "id": 398, "status": "pending", "amount": "10000.0",
+                "currency": "JPY", "created_at": "2015-12-02T05:41:19.000Z",
+                "bank_account_id": 243, "fee": "770.0", "is_fast": true}"#,
+        );
+        let api = &mut coincheck.private.withdraws_jpy;
+
+        let result = api
+            .create_withdraw(BankAccountId(243), 10000.0, true)
+            .await
+            .unwrap();
+        assert_eq!(result.withdraw.fee, 770 as PriceType);
+        assert!(result.withdraw.is_fast);
+
+        // rejected without sending the request
+        for (amount, is_fast) in [(0.0, false), (1000.5, false), (500.0, true)] {
+            let err = api
+                .create_withdraw(BankAccountId(243), amount, is_fast)
+                .await
+                .unwrap_err();
+            assert!(err.to_string().starts_with("withdraw amount"), "{}", err);
+        }
+    }
+
+    #[tokio::test]
+    async fn cancel_withdraw() {
+        let mut coincheck = Coincheck::new_offline();
+        coincheck
+            .client()
+            .set_offline_response("/api/withdraws/398", r#"{"success": true, "id": 398}"#);
+        coincheck.client().set_offline_response(
+            "/api/withdraws/399",
+            r#"{"success": false, "error": "The withdrawal can not be canceled"}"#,
+        );
+        let api = &mut coincheck.private.withdraws_jpy;
+
+        let result = api.cancel_withdraw(WithdrawId(398)).await.unwrap();
+        assert_eq!(result.id, WithdrawId(398));
+
+        let err = api.cancel_withdraw(WithdrawId(399)).await.unwrap_err();
+        match &err {
+            // the API error is kept
+            Error::WithdrawNotCancellable { id, source } => {
+                assert_eq!(*id, WithdrawId(399));
+                assert!(matches!(**source, Error::Api { .. }));
+                assert!(source.to_string().contains("can not be canceled"));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        let err = api.cancel_withdraw(WithdrawId(400)).await.unwrap_err();
+        assert!(matches!(&err, Error::Offline));
+    }
+
+    #[tokio::test]
+    async fn withdraw_amount_limit() {
+        let mut coincheck = Coincheck::new_offline();
+        coincheck
+            .client()
+            .set_max_withdraw_amount(Some(100_000 as PriceType));
+        let err = coincheck
+            .private
+            .withdraws_jpy
+            .create_withdraw(BankAccountId(243), 10_000_000.0, false)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            &err,
+            Error::LimitExceeded {
+                limit: 100_000.0,
+                requested: 10_000_000.0
+            }
+        ));
+    }
+
     #[tokio::test]
     #[serial_test::serial]
     async fn private_withdraw_jpy_api() {
@@ -114,5 +738,11 @@ mod tests {
 
         assert!(api.bank_accounts().await.is_ok());
         assert!(api.withdraws().await.is_ok());
+        assert!(api
+            .find_withdraw(1 as PriceType, chrono::Utc::now())
+            .await
+            .unwrap()
+            .is_none());
+        assert!(coincheck.withdrawal_readiness().await.is_ok());
     }
 }
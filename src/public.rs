@@ -1,7 +1,12 @@
 use crate::client::SharedClient;
+use crate::error::Result;
 use crate::types::*;
-use anyhow::Result;
+use chrono::Utc;
+use futures_util::future::try_join3;
 use reqwest::Method;
+use std::collections::HashMap;
+
+pub mod ws;
 
 /// Public API
 ///
@@ -12,7 +17,7 @@ pub struct Public {
     client: SharedClient,
 }
 
-mod model {
+pub mod model {
     use crate::types::*;
     use chrono::{DateTime, Utc};
     use serde::{Deserialize, Serialize};
@@ -22,16 +27,59 @@ mod model {
     #[serde_as]
     #[derive(Debug, Serialize, Deserialize)]
     pub struct Ticker {
+        /// 最終取引価格
         pub last: PriceType,
+        /// 現在の買い注文の最高価格
         pub bid: PriceType,
+        /// 現在の売り注文の最安価格
         pub ask: PriceType,
+        /// 24時間での最高取引価格
         pub high: PriceType,
+        /// 24時間での最安取引価格
         pub low: PriceType,
+        /// 24時間での取引量 (取引通貨建て)
         pub volume: PriceType,
+        /// 現在の時刻
         #[serde_as(as = "TimestampMilliSeconds")]
         pub timestamp: DateTime<Utc>,
     }
 
+    impl ApiResponse for Ticker {}
+
+    impl Ticker {
+        /// 24時間での売買代金 (円建て、概算)
+        ///
+        /// `volume * last` です。実際の約定価格ではなく最終取引価格で換算するため、概算です。
+        pub fn volume_jpy(&self) -> PriceType {
+            self.volume * self.last
+        }
+
+        /// 売り気配値と買い気配値の差 (ベーシスポイント)
+        ///
+        /// `(ask - bid) / mid * 10000` です (`mid` は気配値の仲値)。
+        /// 気配値が 0 以下 (板が空) の場合は `None` を返します。
+        pub fn spread_bps(&self) -> Option<f64> {
+            if self.bid <= 0.0 || self.ask <= 0.0 {
+                return None;
+            }
+            #[allow(clippy::unnecessary_cast)] // `PriceType` is `f32` with `price_type_f32`
+            let (bid, ask) = (self.bid as f64, self.ask as f64);
+            let mid = (bid + ask) / 2.0;
+            Some((ask - bid) / mid * 10000.0)
+        }
+    }
+
+    /// ティッカーの最終取引価格と気配値
+    ///
+    /// [`Ticker`] のうち、高頻度のポーリングで使うフィールドだけを取り出したものです。
+    /// 他のフィールドは読み飛ばされ、値としてデコードされません。
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+    pub struct TickerQuote {
+        pub last: PriceType,
+        pub bid: PriceType,
+        pub ask: PriceType,
+    }
+
     /// 全取引履歴
     #[derive(Debug, Serialize, Deserialize)]
     pub struct Trades {
@@ -40,6 +88,46 @@ mod model {
         pub data: Vec<Trade>,
     }
 
+    impl ApiResponse for Trades {
+        fn success(&self) -> bool {
+            self.success
+        }
+    }
+
+    impl Trades {
+        /// 取引履歴が空か
+        pub fn is_empty(&self) -> bool {
+            self.data.is_empty()
+        }
+
+        /// 買い注文が約定させた (taker が買い) 取引の数量の合計
+        pub fn buy_volume(&self) -> PriceType {
+            self.volume(BaseOrderType::Buy)
+        }
+
+        /// 売り注文が約定させた (taker が売り) 取引の数量の合計
+        pub fn sell_volume(&self) -> PriceType {
+            self.volume(BaseOrderType::Sell)
+        }
+
+        /// 最新の取引のレート
+        ///
+        /// レートを解析できない場合や取引がない場合は `None` です。
+        pub fn last_price(&self) -> Option<PriceType> {
+            let newest = self.data.iter().max_by_key(|trade| trade.id)?;
+            newest.rate.parse().ok()
+        }
+
+        /// 数量を解析できない取引は無視します。
+        fn volume(&self, side: BaseOrderType) -> PriceType {
+            self.data
+                .iter()
+                .filter(|trade| trade.order_type == side)
+                .filter_map(|trade| trade.amount.parse::<PriceType>().ok())
+                .sum()
+        }
+    }
+
     /// 取引情報
     #[serde_as]
     #[derive(Debug, Serialize, Deserialize)]
@@ -48,7 +136,9 @@ mod model {
         pub amount: String,
         pub rate: String,
         pub pair: String,
-        pub order_type: String,
+        /// 約定させた側 (taker) の売り買い
+        #[serde_as(as = "DisplayFromStr")]
+        pub order_type: BaseOrderType,
         pub created_at: DateTime<Utc>,
     }
 
@@ -59,6 +149,89 @@ mod model {
         pub bids: Vec<OrderBook>,
     }
 
+    impl ApiResponse for OrderBooks {}
+
+    impl OrderBooks {
+        /// 板に注文がないか
+        pub fn is_empty(&self) -> bool {
+            self.asks.is_empty() && self.bids.is_empty()
+        }
+
+        /// 指値注文が反対側の板と交差して即座に約定するか
+        ///
+        /// 買いは `rate` 以下の売り注文、売りは `rate` 以上の買い注文がある場合に `true` です。
+        pub fn would_cross(&self, side: BaseOrderType, rate: PriceType) -> bool {
+            match side {
+                BaseOrderType::Buy => self.asks.iter().any(|book| book.rate <= rate),
+                BaseOrderType::Sell => self.bids.iter().any(|book| book.rate >= rate),
+            }
+        }
+
+        /// 指値注文の板での位置
+        ///
+        /// `rate` に `side` の指値注文を出した場合に、先に約定する注文の
+        /// (価格の段数, 数量の合計) を返します。同じ価格の既存の注文も先に約定するものとして数えます。
+        ///
+        /// 反対側の板と交差して即座に約定する場合は `None` です。
+        pub fn queue_position(
+            &self,
+            side: BaseOrderType,
+            rate: PriceType,
+        ) -> Option<(usize, PriceType)> {
+            let (same, ahead): (_, fn(PriceType, PriceType) -> bool) = match side {
+                BaseOrderType::Buy => (&self.bids, |book, rate| book >= rate),
+                BaseOrderType::Sell => (&self.asks, |book, rate| book <= rate),
+            };
+
+            if self.would_cross(side, rate) {
+                return None;
+            }
+
+            Some(
+                same.iter()
+                    .filter(|book| ahead(book.rate, rate))
+                    .fold((0, 0 as PriceType), |(levels, size), book| {
+                        (levels + 1, size + book.amount)
+                    }),
+            )
+        }
+
+        /// 最良気配から内側に寄せた指値
+        ///
+        /// 買いは最良買い気配から `ticks_inside` 呼値分高く、売りは最良売り気配から低くしたレートを返します。
+        /// 反対側の板と交差しないように、中値 (呼値の単位に自分の側へ丸めたもの) を上限 (売りは下限) とします。
+        ///
+        /// 買いと売りのどちらかの板が空の場合は `None` です。
+        pub fn passive_quote(
+            &self,
+            side: BaseOrderType,
+            ticks_inside: u32,
+            pair: &CoinPair,
+        ) -> Option<PriceType> {
+            let tick = pair.tick_size();
+            let to_ticks = |rate: PriceType| (rate / tick).round();
+            let best_bid = self
+                .bids
+                .iter()
+                .map(|book| book.rate)
+                .reduce(PriceType::max)?;
+            let best_ask = self
+                .asks
+                .iter()
+                .map(|book| book.rate)
+                .reduce(PriceType::min)?;
+            let (bid, ask) = (to_ticks(best_bid), to_ticks(best_ask));
+            let mid = (bid + ask) / 2 as PriceType;
+            let inside = ticks_inside as PriceType;
+
+            let ticks = match side {
+                BaseOrderType::Buy => (bid + inside).min(mid.floor()),
+                BaseOrderType::Sell => (ask - inside).max(mid.ceil()),
+            };
+            Some(round_to_tick(pair, ticks * tick))
+        }
+    }
+
     /// 注文情報
     #[serde_as]
     #[derive(Debug, Serialize, Deserialize)]
@@ -82,6 +255,12 @@ mod model {
         pub amount: PriceType,
     }
 
+    impl ApiResponse for CalculatedRate {
+        fn success(&self) -> bool {
+            self.success
+        }
+    }
+
     /// 販売所レート情報
     #[serde_as]
     #[derive(Debug, Serialize, Deserialize)]
@@ -89,6 +268,28 @@ mod model {
         #[serde_as(as = "DisplayFromStr")]
         pub rate: PriceType,
     }
+
+    impl ApiResponse for ExchangeRate {}
+
+    /// 取引ペア付きのティッカー
+    pub type TaggedTicker = Tagged<Ticker>;
+
+    /// 取引ペア付きの販売所レート情報
+    pub type TaggedRate = Tagged<ExchangeRate>;
+
+    /// 取引ペア付きのレート算出結果
+    pub type TaggedCalculatedRate = Tagged<CalculatedRate>;
+
+    /// 取引ペアの市場情報 (ティッカー、板情報、取引履歴)
+    #[derive(Debug, Serialize)]
+    pub struct MarketSnapshot {
+        pub pair: CoinPair,
+        pub ticker: Ticker,
+        pub order_book: OrderBooks,
+        pub trades: Trades,
+        /// 全てのレスポンスを受け取った時刻
+        pub timestamp: DateTime<Utc>,
+    }
 }
 
 impl Public {
@@ -105,11 +306,67 @@ impl Public {
     /// <https://coincheck.com/ja/documents/exchange/api#ticker>
     pub async fn ticker(&mut self) -> Result<model::Ticker> {
         self.client
-            .borrow_mut()
             .request_and_get_json(Method::GET, "/api/ticker", None, Self::USE_AUTH)
             .await
     }
 
+    /// ティッカー (取引ペア指定)
+    ///
+    /// 指定した取引ペアの各種最新情報を簡易に取得することができます。
+    ///
+    /// <https://coincheck.com/ja/documents/exchange/api#ticker>
+    pub async fn ticker_for(&mut self, pair: &CoinPair) -> Result<model::Ticker> {
+        let mut params = Params::new();
+        params.insert("pair", pair.as_str());
+        self.client
+            .request_and_get_json(Method::GET, "/api/ticker", Some(&params), Self::USE_AUTH)
+            .await
+    }
+
+    /// ティッカー (レスポンスのメタデータ付き)
+    ///
+    /// [`Public::ticker_for`] の結果に、HTTP ステータス、所要時間、サーバーの `Date` ヘッダーを付けて返します。
+    /// キャッシュは使いません。
+    pub async fn ticker_for_with_meta(
+        &mut self,
+        pair: &CoinPair,
+    ) -> Result<WithMeta<model::Ticker>> {
+        let mut params = Params::new();
+        params.insert("pair", pair.as_str());
+        self.client
+            .request_and_get_json_with_meta(
+                Method::GET,
+                "/api/ticker",
+                Some(&params),
+                Self::USE_AUTH,
+            )
+            .await
+    }
+
+    /// ティッカー (取引ペア付き)
+    ///
+    /// [`Public::ticker_for`] の結果に取引ペアを付けて返します。
+    pub async fn ticker_tagged(&mut self, pair: &CoinPair) -> Result<model::TaggedTicker> {
+        let value = self.ticker_for(pair).await?;
+        Ok(Tagged { pair: *pair, value })
+    }
+
+    /// ティッカー (最終取引価格と気配値のみ)
+    ///
+    /// [`Public::ticker_for`] と同じ API を使いますが、`last`, `bid`, `ask` 以外のフィールドをデコードしません。
+    /// Coincheck の API はフィールドの選択に対応していないため、レスポンスのサイズは変わりません。
+    ///
+    /// <https://coincheck.com/ja/documents/exchange/api#ticker>
+    pub async fn ticker_quote(&mut self, pair: &CoinPair) -> Result<model::TickerQuote> {
+        let mut params = Params::new();
+        params.insert("pair", pair.as_str());
+        let text = self
+            .client
+            .request_and_get_text(Method::GET, "/api/ticker", Some(&params), Self::USE_AUTH)
+            .await?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
     /// 全取引履歴
     ///
     /// 最新の取引履歴を取得できます。
@@ -119,11 +376,68 @@ impl Public {
         let mut params = Params::new();
         params.insert("pair", pair.as_str());
         self.client
-            .borrow_mut()
             .request_and_get_json(Method::GET, "/api/trades", Some(&params), Self::USE_AUTH)
             .await
     }
 
+    /// 全取引履歴 (レスポンスのメタデータ付き)
+    ///
+    /// [`Public::trades`] の結果に、HTTP ステータス、所要時間、サーバーの `Date` ヘッダーを付けて返します。
+    /// キャッシュは使いません。
+    pub async fn trades_with_meta(&mut self, pair: &CoinPair) -> Result<WithMeta<model::Trades>> {
+        let mut params = Params::new();
+        params.insert("pair", pair.as_str());
+        self.client
+            .request_and_get_json_with_meta(
+                Method::GET,
+                "/api/trades",
+                Some(&params),
+                Self::USE_AUTH,
+            )
+            .await
+    }
+
+    /// 最終取引価格 (複数の取引ペア)
+    ///
+    /// 取引ペアごとに [`Public::trades`] を呼び出し、最新の取引のレートを返します。
+    /// 取引履歴がない取引ペアは含みません。
+    pub async fn last_prices(
+        &mut self,
+        pairs: &[CoinPair],
+    ) -> Result<HashMap<CoinPair, PriceType>> {
+        let mut prices = HashMap::new();
+        for pair in pairs {
+            let trades = self.trades(pair).await?;
+            if let Some(price) = trades.last_price() {
+                prices.insert(*pair, price);
+            }
+        }
+        Ok(prices)
+    }
+
+    /// 市場情報
+    ///
+    /// [`Public::ticker_for`], [`Public::order_book_for`], [`Public::trades`] を同時に呼び出し、
+    /// 結果をまとめて返します。どれかが失敗した場合はエラーを返します。
+    pub async fn market_snapshot(&mut self, pair: &CoinPair) -> Result<model::MarketSnapshot> {
+        let mut ticker_api = Public::new(self.client.clone());
+        let mut order_book_api = Public::new(self.client.clone());
+        let (ticker, order_book, trades) = try_join3(
+            ticker_api.ticker_for(pair),
+            order_book_api.order_book_for(pair),
+            self.trades(pair),
+        )
+        .await?;
+
+        Ok(model::MarketSnapshot {
+            pair: *pair,
+            ticker,
+            order_book,
+            trades,
+            timestamp: Utc::now(),
+        })
+    }
+
     /// 板情報
     ///
     /// 板情報を取得できます。
@@ -131,11 +445,61 @@ impl Public {
     /// <https://coincheck.com/ja/documents/exchange/api#order-book>
     pub async fn order_book(&mut self) -> Result<model::OrderBooks> {
         self.client
-            .borrow_mut()
             .request_and_get_json(Method::GET, "/api/order_books", None, Self::USE_AUTH)
             .await
     }
 
+    /// 板情報 (取引ペア指定)
+    ///
+    /// 指定した取引ペアの板情報を取得できます。
+    ///
+    /// <https://coincheck.com/ja/documents/exchange/api#order-book>
+    pub async fn order_book_for(&mut self, pair: &CoinPair) -> Result<model::OrderBooks> {
+        let mut params = Params::new();
+        params.insert("pair", pair.as_str());
+        self.client
+            .request_and_get_json(
+                Method::GET,
+                "/api/order_books",
+                Some(&params),
+                Self::USE_AUTH,
+            )
+            .await
+    }
+
+    /// 板情報 (取引ペア指定、レスポンスのメタデータ付き)
+    ///
+    /// [`Public::order_book_for`] の結果に、HTTP ステータス、所要時間、サーバーの `Date` ヘッダーを付けて返します。
+    /// キャッシュは使いません。
+    pub async fn order_book_for_with_meta(
+        &mut self,
+        pair: &CoinPair,
+    ) -> Result<WithMeta<model::OrderBooks>> {
+        let mut params = Params::new();
+        params.insert("pair", pair.as_str());
+        self.client
+            .request_and_get_json_with_meta(
+                Method::GET,
+                "/api/order_books",
+                Some(&params),
+                Self::USE_AUTH,
+            )
+            .await
+    }
+
+    /// 指値注文が即座に約定するか
+    ///
+    /// [`Public::order_book_for`] で板情報を取得し、`rate` の指値注文が反対側の板と交差するかを返します。
+    /// 板情報の取得から発注までに板が動く可能性があるため、結果は目安です。
+    pub async fn would_cross(
+        &mut self,
+        pair: &CoinPair,
+        side: BaseOrderType,
+        rate: PriceType,
+    ) -> Result<bool> {
+        Ok(self.order_book_for(pair).await?.would_cross(side, rate))
+    }
+
     /// レート取得
     ///
     /// 取引所の注文を元にレートを算出します。注文量を使用します。
@@ -153,7 +517,6 @@ impl Public {
         params.insert("pair", pair.as_str());
         params.insert("amount", &amount);
         self.client
-            .borrow_mut()
             .request_and_get_json(
                 Method::GET,
                 "/api/exchange/orders/rate",
@@ -163,6 +526,21 @@ impl Public {
             .await
     }
 
+    /// レート取得 (取引ペア付き)
+    ///
+    /// [`Public::order_rate_from_amount`] の結果に取引ペアを付けて返します。
+    pub async fn order_rate_from_amount_tagged(
+        &mut self,
+        order_type: &BaseOrderType,
+        pair: &CoinPair,
+        amount: PriceType,
+    ) -> Result<model::TaggedCalculatedRate> {
+        let value = self
+            .order_rate_from_amount(order_type, pair, amount)
+            .await?;
+        Ok(Tagged { pair: *pair, value })
+    }
+
     /// レート取得
     ///
     /// 取引所の注文を元にレートを算出します。注文金額を使用します。
@@ -180,7 +558,6 @@ impl Public {
         params.insert("pair", pair.as_str());
         params.insert("price", &price);
         self.client
-            .borrow_mut()
             .request_and_get_json(
                 Method::GET,
                 "/api/exchange/orders/rate",
@@ -190,28 +567,223 @@ impl Public {
             .await
     }
 
+    /// レート取得 (取引ペア付き)
+    ///
+    /// [`Public::order_rate_from_price`] の結果に取引ペアを付けて返します。
+    pub async fn order_rate_from_price_tagged(
+        &mut self,
+        order_type: &BaseOrderType,
+        pair: &CoinPair,
+        price: PriceType,
+    ) -> Result<model::TaggedCalculatedRate> {
+        let value = self.order_rate_from_price(order_type, pair, price).await?;
+        Ok(Tagged { pair: *pair, value })
+    }
+
     /// 販売レート取得
     ///
     /// 販売所のレートを取得します。
     ///
     /// <https://coincheck.com/ja/documents/exchange/api#buy-rate>
-    pub async fn marketplace_buy_rate<'a>(
-        &mut self,
-        pair: &CoinPair,
-    ) -> Result<model::ExchangeRate> {
+    pub async fn marketplace_buy_rate(&mut self, pair: &CoinPair) -> Result<model::ExchangeRate> {
         let url = format!("/api/rate/{}", pair.as_str());
         self.client
-            .borrow_mut()
             .request_and_get_json(Method::GET, &url, None, Self::USE_AUTH)
             .await
     }
+
+    /// 販売レート取得 (取引ペア付き)
+    ///
+    /// [`Public::marketplace_buy_rate`] の結果に取引ペアを付けて返します。
+    pub async fn marketplace_buy_rate_tagged(
+        &mut self,
+        pair: &CoinPair,
+    ) -> Result<model::TaggedRate> {
+        let value = self.marketplace_buy_rate(pair).await?;
+        Ok(Tagged { pair: *pair, value })
+    }
+
+    /// 販売所の売買レート取得
+    ///
+    /// 販売所の (購入レート, 売却レート) を取得します。
+    ///
+    /// 現在の API は売却レートを提供していないため、売却レートは常に `None` です。
+    /// API が対応した場合に呼び出し側を変更せずに済むよう、戻り値の形を先に用意しています。
+    ///
+    /// <https://coincheck.com/ja/documents/exchange/api#buy-rate>
+    pub async fn marketplace_rates(
+        &mut self,
+        pair: &CoinPair,
+    ) -> Result<(model::ExchangeRate, Option<model::ExchangeRate>)> {
+        let buy = self.marketplace_buy_rate(pair).await?;
+        Ok((buy, None))
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::model::OrderBooks;
     use crate::types::*;
     use crate::Coincheck;
 
+    #[test]
+    fn ticker_volume_jpy() {
+        let ticker: super::model::Ticker = serde_json::from_value(serde_json::json!({
+            "last": 4000000.0,
+            "bid": 3999000.0,
+            "ask": 4001000.0,
+            "high": 4100000.0,
+            "low": 3900000.0,
+            "volume": 1.5,
+            "timestamp": 1609459200000u64,
+        }))
+        .unwrap();
+        assert_eq!(ticker.volume_jpy(), 6000000.0);
+        assert!((ticker.spread_bps().unwrap() - 5.0).abs() < 1e-9);
+
+        let empty = super::model::Ticker { bid: 0.0, ..ticker };
+        assert_eq!(empty.spread_bps(), None);
+
+        let quote: super::model::TickerQuote = serde_json::from_str(
+            r#"{"last":4000000.0,"bid":3999000.0,"ask":4001000.0,"high":4100000.0,
+                "low":3900000.0,"volume":1.5,"timestamp":1609459200000}"#,
+        )
+        .unwrap();
+        assert_eq!(quote.last, ticker.last);
+        assert_eq!(quote.bid, ticker.bid);
+        assert_eq!(quote.ask, ticker.ask);
+    }
+
+    #[test]
+    fn trades_volume_by_side() {
+        let trades: super::model::Trades = serde_json::from_value(serde_json::json!({
+            "success": true,
+            "pagination": { "limit": 3, "order": "desc", "starting_after": null, "ending_before": null },
+            "data": [
+                { "id": 3, "amount": "0.5", "rate": "100", "pair": "btc_jpy",
+                  "order_type": "sell", "created_at": "2021-01-01T00:00:02.000Z" },
+                { "id": 2, "amount": "0.25", "rate": "101", "pair": "btc_jpy",
+                  "order_type": "buy", "created_at": "2021-01-01T00:00:01.000Z" },
+                { "id": 1, "amount": "1", "rate": "100", "pair": "btc_jpy",
+                  "order_type": "buy", "created_at": "2021-01-01T00:00:00.000Z" }
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(trades.data[0].order_type, BaseOrderType::Sell);
+        assert_eq!(trades.buy_volume(), 1.25);
+        assert_eq!(trades.sell_volume(), 0.5);
+        assert_eq!(trades.last_price(), Some(100.0));
+    }
+
+    #[test]
+    fn order_book_queue_position() {
+        let books: OrderBooks = serde_json::from_value(serde_json::json!({
+            "asks": [["102", "0.5"], ["101", "0.25"]],
+            "bids": [["99", "1"], ["98", "2"], ["97", "4"]],
+        }))
+        .unwrap();
+
+        let buy = BaseOrderType::Buy;
+        assert_eq!(books.queue_position(buy, 100 as PriceType), Some((0, 0.0)));
+        assert_eq!(books.queue_position(buy, 98 as PriceType), Some((2, 3.0)));
+        assert_eq!(books.queue_position(buy, 101 as PriceType), None);
+
+        let sell = BaseOrderType::Sell;
+        assert_eq!(
+            books.queue_position(sell, 102 as PriceType),
+            Some((2, 0.75))
+        );
+        assert_eq!(books.queue_position(sell, 99 as PriceType), None);
+
+        assert!(books.would_cross(buy, 101 as PriceType));
+        assert!(!books.would_cross(buy, 100 as PriceType));
+        assert!(books.would_cross(sell, 99 as PriceType));
+        assert!(!books.would_cross(sell, 100 as PriceType));
+    }
+
+    #[test]
+    fn order_book_passive_quote() {
+        let books: OrderBooks = serde_json::from_value(serde_json::json!({
+            "asks": [["111", "0.5"], ["110", "0.25"]],
+            "bids": [["99", "1"], ["100", "2"]],
+        }))
+        .unwrap();
+
+        let (buy, sell) = (BaseOrderType::Buy, BaseOrderType::Sell);
+        let pair = CoinPair::BtcJpy;
+        assert_eq!(books.passive_quote(buy, 1, &pair), Some(101.0));
+        assert_eq!(books.passive_quote(sell, 1, &pair), Some(109.0));
+        assert_eq!(books.passive_quote(buy, 0, &pair), Some(100.0));
+        // capped at the mid price
+        assert_eq!(books.passive_quote(buy, 10, &pair), Some(105.0));
+        assert_eq!(books.passive_quote(sell, 10, &pair), Some(105.0));
+
+        let books: OrderBooks = serde_json::from_value(serde_json::json!({
+            "asks": [["0.102", "1"]],
+            "bids": [["0.1", "1"]],
+        }))
+        .unwrap();
+        let pair = CoinPair::MonaJpy;
+        assert_eq!(books.passive_quote(buy, 5, &pair), Some(0.101));
+        assert_eq!(books.passive_quote(sell, 5, &pair), Some(0.101));
+
+        let empty = OrderBooks {
+            asks: vec![],
+            bids: vec![],
+        };
+        assert_eq!(empty.passive_quote(buy, 1, &pair), None);
+    }
+
+    #[tokio::test]
+    async fn market_snapshot() {
+        let mut coincheck = Coincheck::new_offline();
+        let client = coincheck.client();
+        client.set_offline_response(
+            "/api/ticker",
+            r#"{"last":100.0,"bid":99.0,"ask":101.0,"high":110.0,"low":90.0,
+                "volume":1.5,"timestamp":1609459200000}"#,
+        );
+        client.set_offline_response(
+            "/api/order_books",
+            r#"{"asks":[["101","0.5"]],"bids":[["99","1"]]}"#,
+        );
+        client.set_offline_response(
+            "/api/trades",
+            r#"{"success":true,
+                "pagination":{"limit":1,"order":"desc","starting_after":null,"ending_before":null},
+                "data":[{"id":1,"amount":"0.1","rate":"100","pair":"btc_jpy",
+                         "order_type":"buy","created_at":"2021-01-01T00:00:00.000Z"}]}"#,
+        );
+
+        let snapshot = coincheck
+            .public
+            .market_snapshot(&CoinPair::BtcJpy)
+            .await
+            .unwrap();
+        assert_eq!(snapshot.pair, CoinPair::BtcJpy);
+        assert_eq!(snapshot.ticker.last, 100.0);
+        assert_eq!(snapshot.order_book.asks.len(), 1);
+        assert_eq!(snapshot.trades.last_price(), Some(100.0));
+
+        let ticker = coincheck
+            .public
+            .ticker_for_with_meta(&CoinPair::BtcJpy)
+            .await
+            .unwrap();
+        assert_eq!(ticker.value.last, 100.0);
+        assert_eq!(ticker.status, reqwest::StatusCode::OK);
+
+        coincheck
+            .client()
+            .set_offline_response("/api/trades", "not json");
+        assert!(coincheck
+            .public
+            .market_snapshot(&CoinPair::BtcJpy)
+            .await
+            .is_err());
+    }
+
     #[tokio::test]
     #[serial_test::serial]
     async fn public_api() {
@@ -222,8 +794,17 @@ mod tests {
         let otype = &BaseOrderType::Buy;
 
         assert!(api.ticker().await.is_ok());
+        assert!(api.ticker_for(pair).await.is_ok());
+        assert!(api.ticker_for_with_meta(pair).await.is_ok());
+        assert!(api.ticker_quote(pair).await.is_ok());
+        assert_eq!(api.ticker_tagged(pair).await.unwrap().pair, *pair);
         assert!(api.trades(pair).await.is_ok());
+        assert!(api
+            .last_prices(&[CoinPair::BtcJpy, CoinPair::EtcJpy])
+            .await
+            .is_ok());
         assert!(api.order_book().await.is_ok());
+        assert!(api.order_book_for(pair).await.is_ok());
         assert!(api
             .order_rate_from_amount(otype, pair, 0.1 as PriceType)
             .await
@@ -233,5 +814,10 @@ mod tests {
             .await
             .is_ok());
         assert!(api.marketplace_buy_rate(pair).await.is_ok());
+        assert!(api.marketplace_rates(pair).await.is_ok());
+        assert_eq!(
+            api.marketplace_buy_rate_tagged(pair).await.unwrap().pair,
+            *pair
+        );
     }
 }
@@ -0,0 +1,1190 @@
+//! WebSocket API
+//!
+//! 公開チャンネルをリアルタイムに購読することができます。
+//!
+//! <https://coincheck.com/ja/documents/exchange/api#websocket>
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::future::Future;
+use std::io::Write;
+use std::path::Path;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use chrono::{DateTime, TimeZone, Utc};
+use futures_util::stream::{self, BoxStream, Stream, StreamExt};
+use serde::Deserialize;
+use serde_json::Value;
+use serde_with::{serde_as, DisplayFromStr};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::mpsc;
+
+use crate::client::{Client, SharedClient};
+use crate::error::{Error, Result};
+use crate::public::{model, Public};
+use crate::retry::Backoff;
+use crate::types::*;
+use crate::ws::{Message, WsConnection, WS_URL};
+
+/// 取引履歴チャンネルの約定
+#[derive(Debug, Clone, PartialEq)]
+pub struct TradeUpdate {
+    pub pair: CoinPair,
+    pub trade_id: IdType,
+    pub rate: PriceType,
+    pub amount: PriceType,
+    /// 約定させた側 (taker) の売り買い
+    pub order_type: BaseOrderType,
+    /// 約定時刻 (旧形式のメッセージでは受信時刻)
+    pub timestamp: DateTime<Utc>,
+}
+
+impl TradeUpdate {
+    /// 取引履歴チャンネルのメッセージを解析します。
+    ///
+    /// 現在の形式 `[[timestamp, id, pair, rate, amount, side, taker_id, maker_id], ...]` と
+    /// 旧形式 `[id, pair, rate, amount, side]` の両方に対応しています。
+    pub fn parse_message(message: &str) -> Result<Vec<TradeUpdate>> {
+        let value: Value = serde_json::from_str(message)?;
+        let items = value
+            .as_array()
+            .ok_or_else(|| Error::WebSocket(format!("unexpected trades message: {}", message)))?;
+        if items.iter().all(Value::is_array) {
+            items
+                .iter()
+                .map(|item| TradeUpdate::from_fields(item.as_array().unwrap(), true))
+                .collect()
+        } else {
+            Ok(vec![TradeUpdate::from_fields(items, false)?])
+        }
+    }
+
+    fn from_fields(fields: &[Value], with_timestamp: bool) -> Result<TradeUpdate> {
+        let offset = with_timestamp as usize;
+        let field = |i: usize| -> Result<String> {
+            match fields.get(i + offset) {
+                Some(Value::String(s)) => Ok(s.clone()),
+                Some(Value::Number(n)) => Ok(n.to_string()),
+                _ => Err(Error::WebSocket(format!(
+                    "missing trade field {} in {:?}",
+                    i + offset,
+                    fields
+                ))),
+            }
+        };
+        let timestamp = if with_timestamp {
+            let secs = match &fields[0] {
+                Value::String(s) => s.parse().ok(),
+                Value::Number(n) => n.as_i64(),
+                _ => None,
+            };
+            secs.and_then(|secs| Utc.timestamp_opt(secs, 0).single())
+                .ok_or_else(|| Error::WebSocket(format!("bad trade timestamp: {}", fields[0])))?
+        } else {
+            Utc::now()
+        };
+
+        fn parse<T: FromStr>(name: &str, value: String) -> Result<T> {
+            value
+                .parse()
+                .map_err(|_| Error::WebSocket(format!("bad trade {}: {}", name, value)))
+        }
+        Ok(TradeUpdate {
+            trade_id: parse("id", field(0)?)?,
+            pair: parse("pair", field(1)?)?,
+            rate: parse("rate", field(2)?)?,
+            amount: parse("amount", field(3)?)?,
+            order_type: parse("side", field(4)?)?,
+            timestamp,
+        })
+    }
+}
+
+/// [`TickerStream`] の要素
+///
+/// Coincheck の WebSocket API にはティッカーのチャンネルがないため、取引履歴チャンネルの約定
+/// ([`TradeUpdate`]) から最終取引価格を得ます。
+pub type TickerUpdate = TradeUpdate;
+
+/// 取引履歴チャンネル (`<pair>-trades`) の約定のストリーム
+///
+/// 最終取引価格の更新として、約定を [`TickerUpdate`] として返します。
+/// 接続が切れると [`Backoff`] の間隔で再接続し、購読し直します。
+/// 接続エラーや解析できないメッセージは `Err` として返し、ストリームは続きます。
+/// ストリームを破棄すると接続を閉じ、購読を終了します。
+pub struct TickerStream {
+    recorder: Recorder,
+    inner: BoxStream<'static, Result<TickerUpdate>>,
+}
+
+impl TickerStream {
+    pub fn new(pair: CoinPair) -> Self {
+        Self::with_backoff(pair, Backoff::default())
+    }
+
+    /// 再接続の間隔を指定します。
+    pub fn with_backoff(pair: CoinPair, backoff: Backoff) -> Self {
+        let subscriptions = Subscriptions::fixed(format!("{}-trades", pair));
+        let recorder = subscriptions.recorder.clone();
+        let inner = subscribe(
+            subscriptions,
+            backoff,
+            || WsConnection::connect(WS_URL),
+            TradeUpdate::parse_message,
+        );
+        TickerStream { recorder, inner }
+    }
+
+    /// 以降に受信したメッセージを `path` のファイルに追記します。[`WsRecording`] で読み込めます。
+    pub fn record_to(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.recorder.start(path.as_ref())
+    }
+}
+
+impl Stream for TickerStream {
+    type Item = Result<TickerUpdate>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+/// 複数の取引ペアの取引履歴チャンネルの約定のストリーム
+///
+/// 1つの接続で複数の取引ペアを購読し、[`TradesStream::subscribe`] と
+/// [`TradesStream::unsubscribe`] で購読する取引ペアを変更できます。
+/// 購読を解除した取引ペアの約定は、解除の要求が処理される前に届いたものも返しません。
+/// その他は [`TickerStream`] と同じです。
+pub struct TradesStream {
+    recorder: Recorder,
+    channels: Arc<Mutex<BTreeSet<String>>>,
+    commands: mpsc::UnboundedSender<String>,
+    inner: BoxStream<'static, Result<TradeUpdate>>,
+}
+
+impl TradesStream {
+    pub fn new(pairs: &[CoinPair]) -> Self {
+        Self::with_backoff(pairs, Backoff::default())
+    }
+
+    /// 再接続の間隔を指定します。
+    pub fn with_backoff(pairs: &[CoinPair], backoff: Backoff) -> Self {
+        Self::with_connector(pairs, backoff, || WsConnection::connect(WS_URL))
+    }
+
+    fn with_connector<C, Fut, S>(pairs: &[CoinPair], backoff: Backoff, connect: C) -> Self
+    where
+        C: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<WsConnection<S>>> + Send,
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let channels: BTreeSet<_> = pairs.iter().map(TradesStream::channel).collect();
+        let channels = Arc::new(Mutex::new(channels));
+        let (commands, receiver) = mpsc::unbounded_channel();
+        let subscriptions = Subscriptions {
+            channels: channels.clone(),
+            commands: Some(receiver),
+            recorder: Recorder::default(),
+        };
+        let recorder = subscriptions.recorder.clone();
+
+        let subscribed = channels.clone();
+        let inner = subscribe(subscriptions, backoff, connect, move |message| {
+            let updates = TradeUpdate::parse_message(message)?;
+            let subscribed = subscribed.lock().unwrap();
+            Ok(updates
+                .into_iter()
+                .filter(|update| subscribed.contains(&TradesStream::channel(&update.pair)))
+                .collect())
+        });
+        TradesStream {
+            recorder,
+            channels,
+            commands,
+            inner,
+        }
+    }
+
+    fn channel(pair: &CoinPair) -> String {
+        format!("{}-trades", pair)
+    }
+
+    /// 取引ペアの購読を追加します。
+    pub fn subscribe(&self, pair: CoinPair) {
+        let channel = TradesStream::channel(&pair);
+        if self.channels.lock().unwrap().insert(channel.clone()) {
+            // the receiver lives as long as the stream
+            let _ = self.commands.send(request("subscribe", &channel));
+        }
+    }
+
+    /// 取引ペアの購読を解除します。
+    pub fn unsubscribe(&self, pair: CoinPair) {
+        let channel = TradesStream::channel(&pair);
+        if self.channels.lock().unwrap().remove(&channel) {
+            let _ = self.commands.send(request("unsubscribe", &channel));
+        }
+    }
+
+    /// 以降に受信したメッセージを `path` のファイルに追記します。[`WsRecording`] で読み込めます。
+    pub fn record_to(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.recorder.start(path.as_ref())
+    }
+
+    /// 購読している取引ペア
+    pub fn pairs(&self) -> Vec<CoinPair> {
+        let channels = self.channels.lock().unwrap();
+        CoinPair::ALL
+            .iter()
+            .filter(|pair| channels.contains(&TradesStream::channel(pair)))
+            .copied()
+            .collect()
+    }
+}
+
+impl Stream for TradesStream {
+    type Item = Result<TradeUpdate>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+/// 板情報チャンネルのメッセージで更新する板
+///
+/// 価格の段ごとに並べて保持するため、最良気配は板全体を並べ替えずに取得できます。
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LocalOrderBook {
+    asks: BTreeMap<Level, PriceType>,
+    bids: BTreeMap<Level, PriceType>,
+    last_update_at: Option<DateTime<Utc>>,
+}
+
+/// A price level, ordered by `total_cmp` so that it can be a map key.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Level(PriceType);
+
+impl Eq for Level {}
+
+impl PartialOrd for Level {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Level {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl LocalOrderBook {
+    /// REST API の板情報から作成します。
+    pub fn from_snapshot(snapshot: &model::OrderBooks) -> Self {
+        let mut book = LocalOrderBook::default();
+        for level in &snapshot.asks {
+            book.set(BaseOrderType::Sell, level.rate, level.amount);
+        }
+        for level in &snapshot.bids {
+            book.set(BaseOrderType::Buy, level.rate, level.amount);
+        }
+        book
+    }
+
+    /// 板情報チャンネルのメッセージ `[pair, {"bids": [[rate, amount], ...], "asks": [...],
+    /// "last_update_at": timestamp}]` の差分を適用します。
+    ///
+    /// 数量が `"0"` の価格の段は削除します。
+    pub fn apply_message(&mut self, message: &str) -> Result<()> {
+        let (_, diff): (String, OrderBookDiff) = serde_json::from_str(message)?;
+        for (rate, amount) in diff.asks {
+            self.set(BaseOrderType::Sell, rate, amount);
+        }
+        for (rate, amount) in diff.bids {
+            self.set(BaseOrderType::Buy, rate, amount);
+        }
+        if let Some(secs) = diff.last_update_at {
+            self.last_update_at = Utc.timestamp_opt(secs, 0).single();
+        }
+        Ok(())
+    }
+
+    fn set(&mut self, side: BaseOrderType, rate: PriceType, amount: PriceType) {
+        let levels = match side {
+            BaseOrderType::Buy => &mut self.bids,
+            BaseOrderType::Sell => &mut self.asks,
+        };
+        if amount == 0 as PriceType {
+            levels.remove(&Level(rate));
+        } else {
+            levels.insert(Level(rate), amount);
+        }
+    }
+
+    /// 最良買い気配
+    pub fn best_bid(&self) -> Option<model::OrderBook> {
+        self.bids
+            .iter()
+            .next_back()
+            .map(|(rate, amount)| model::OrderBook {
+                rate: rate.0,
+                amount: *amount,
+            })
+    }
+
+    /// 最良売り気配
+    pub fn best_ask(&self) -> Option<model::OrderBook> {
+        self.asks
+            .iter()
+            .next()
+            .map(|(rate, amount)| model::OrderBook {
+                rate: rate.0,
+                amount: *amount,
+            })
+    }
+
+    /// 最後に受信した差分の更新時刻
+    pub fn last_update_at(&self) -> Option<DateTime<Utc>> {
+        self.last_update_at
+    }
+
+    /// REST API と同じ並び (売りは安い順、買いは高い順) の板情報
+    pub fn to_order_books(&self) -> model::OrderBooks {
+        let level = |(rate, amount): (&Level, &PriceType)| model::OrderBook {
+            rate: rate.0,
+            amount: *amount,
+        };
+        model::OrderBooks {
+            asks: self.asks.iter().map(level).collect(),
+            bids: self.bids.iter().rev().map(level).collect(),
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Deserialize)]
+struct OrderBookDiff {
+    #[serde_as(as = "Vec<(DisplayFromStr, DisplayFromStr)>")]
+    #[serde(default)]
+    asks: Vec<(PriceType, PriceType)>,
+    #[serde_as(as = "Vec<(DisplayFromStr, DisplayFromStr)>")]
+    #[serde(default)]
+    bids: Vec<(PriceType, PriceType)>,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    last_update_at: Option<i64>,
+}
+
+/// 板情報の更新で返す最良気配
+///
+/// 板全体は [`OrderBookStream::order_books`] で取得できます。
+#[derive(Debug)]
+pub struct OrderBookTop {
+    /// 最良買い気配
+    pub best_bid: Option<model::OrderBook>,
+    /// 最良売り気配
+    pub best_ask: Option<model::OrderBook>,
+    /// 最後に受信した差分の更新時刻
+    pub last_update_at: Option<DateTime<Utc>>,
+}
+
+/// 板情報チャンネル (`<pair>-orderbook`) のストリーム
+///
+/// 差分を受信するたびに [`LocalOrderBook`] に適用し、更新後の最良気配を返します。
+/// 板全体は [`OrderBookStream::order_books`] で取得できます。
+///
+/// チャンネルは差分のみを配信するため、接続のたびにチャンネルを購読してから REST API の板情報を
+/// 取得し直し、取得中に受信した差分をその上に適用します (これらの差分では最良気配を返しません)。
+/// [`OrderBookStream::with_snapshot`] では最初の接続に限り、指定した板情報から始めます。板情報の取得に失敗した場合は、接続の失敗と同じく `Err` を返して再接続します。
+/// その他は [`TickerStream`] と同じく、エラーは `Err` として返し、破棄すると購読を終了します。
+pub struct OrderBookStream {
+    recorder: Recorder,
+    book: Arc<Mutex<LocalOrderBook>>,
+    inner: BoxStream<'static, Result<OrderBookTop>>,
+}
+
+impl OrderBookStream {
+    pub fn new(pair: CoinPair) -> Self {
+        Self::with_client(pair, Client::shared_new(None, None), Backoff::default())
+    }
+
+    /// 板情報を `client` で取得し、再接続の間隔を指定します。
+    pub fn with_client(pair: CoinPair, client: SharedClient, backoff: Backoff) -> Self {
+        Self::with_connector(pair, client, None, backoff, || {
+            WsConnection::connect(WS_URL)
+        })
+    }
+
+    /// 板情報 `book` から始め、再接続の間隔を指定します。再接続時の板情報は `client` で取得します。
+    pub fn with_snapshot(
+        pair: CoinPair,
+        client: SharedClient,
+        book: LocalOrderBook,
+        backoff: Backoff,
+    ) -> Self {
+        Self::with_connector(pair, client, Some(book), backoff, || {
+            WsConnection::connect(WS_URL)
+        })
+    }
+
+    fn with_connector<C, Fut, S>(
+        pair: CoinPair,
+        client: SharedClient,
+        book: Option<LocalOrderBook>,
+        backoff: Backoff,
+        mut connect: C,
+    ) -> Self
+    where
+        C: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<WsConnection<S>>> + Send,
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let mut fresh = book.is_some();
+        let book = Arc::new(Mutex::new(book.unwrap_or_default()));
+        let shared = book.clone();
+        let channel = format!("{}-orderbook", pair);
+        let subscriptions = Subscriptions::manual();
+        // The diffs sent while disconnected are lost, so the book is replaced with a snapshot on
+        // every connection except the first one of a book given by the caller. The channel is
+        // subscribed before the snapshot is fetched, so that no diff is missed in between.
+        let reset = book.clone();
+        let recorder = subscriptions.recorder.clone();
+        let connect = move || {
+            let snapshot = if std::mem::replace(&mut fresh, false) {
+                None
+            } else {
+                Some((Public::new(client.clone()), reset.clone(), recorder.clone()))
+            };
+            let conn = connect();
+            let channel = channel.clone();
+            async move {
+                let mut conn = conn.await?;
+                conn.send_text(&request("subscribe", &channel)).await?;
+                if let Some((public, book, recorder)) = snapshot {
+                    let snapshot = sync_order_book(&mut conn, public, pair, &recorder).await?;
+                    *book.lock().unwrap() = snapshot;
+                }
+                Ok(conn)
+            }
+        };
+        let recorder = subscriptions.recorder.clone();
+        let inner = subscribe(subscriptions, backoff, connect, move |message| {
+            let mut book = shared.lock().unwrap();
+            book.apply_message(message)?;
+            Ok(vec![OrderBookTop {
+                best_bid: book.best_bid(),
+                best_ask: book.best_ask(),
+                last_update_at: book.last_update_at(),
+            }])
+        });
+        OrderBookStream {
+            recorder,
+            book,
+            inner,
+        }
+    }
+
+    /// 最良買い気配
+    pub fn best_bid(&self) -> Option<model::OrderBook> {
+        self.book.lock().unwrap().best_bid()
+    }
+
+    /// 最良売り気配
+    pub fn best_ask(&self) -> Option<model::OrderBook> {
+        self.book.lock().unwrap().best_ask()
+    }
+
+    /// 現在の板情報
+    ///
+    /// 板全体を複製するため、最良気配のみが必要な場合はストリームの [`OrderBookTop`] を使ってください。
+    pub fn order_books(&self) -> model::OrderBooks {
+        self.book.lock().unwrap().to_order_books()
+    }
+
+    /// 以降に受信したメッセージを `path` のファイルに追記します。[`WsRecording`] で読み込めます。
+    pub fn record_to(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.recorder.start(path.as_ref())
+    }
+}
+
+impl Stream for OrderBookStream {
+    type Item = Result<OrderBookTop>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+/// Fetch the order book snapshot on a subscribed connection, and apply the diffs received
+/// meanwhile on top of it.
+async fn sync_order_book<S>(
+    conn: &mut WsConnection<S>,
+    mut public: Public,
+    pair: CoinPair,
+    recorder: &Recorder,
+) -> Result<LocalOrderBook>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let fetch = public.order_book_for(&pair);
+    tokio::pin!(fetch);
+    let mut diffs = Vec::new();
+    let snapshot = loop {
+        // `recv` is cancel safe, so the diffs not received yet are left on the connection.
+        tokio::select! {
+            snapshot = &mut fetch => break snapshot?,
+            message = conn.recv() => match message? {
+                Message::Text(message) => diffs.push(message),
+                Message::Ping(payload) => conn.send_pong(&payload).await?,
+                Message::Close(_) => {
+                    return Err(Error::WebSocket(
+                        "WebSocket connection closed while fetching the order book".to_owned(),
+                    ))
+                }
+            },
+        }
+    };
+
+    let mut book = LocalOrderBook::from_snapshot(&snapshot);
+    for message in &diffs {
+        recorder.write(message)?;
+        book.apply_message(message)?;
+    }
+    Ok(book)
+}
+
+/// WebSocket で受信したメッセージの記録
+///
+/// ストリームの `record_to` で記録したファイルを読み込み、板情報や約定の解析を再現できます。
+/// ファイルは1行に1つのメッセージを JSON の文字列として保存します。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WsRecording {
+    pub messages: Vec<String>,
+}
+
+impl WsRecording {
+    /// ファイルから読み込みます。
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let messages = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(WsRecording { messages })
+    }
+
+    /// ファイルに保存します。
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut file = File::create(path)?;
+        for message in &self.messages {
+            writeln!(file, "{}", serde_json::to_string(message)?)?;
+        }
+        Ok(())
+    }
+
+    /// 板情報チャンネルの記録を `book` に順に適用します。
+    pub fn replay_order_book(&self, mut book: LocalOrderBook) -> Result<LocalOrderBook> {
+        for message in &self.messages {
+            book.apply_message(message)?;
+        }
+        Ok(book)
+    }
+
+    /// 取引履歴チャンネルの記録を解析します。
+    pub fn replay_trades(&self) -> Result<Vec<TradeUpdate>> {
+        let mut updates = Vec::new();
+        for message in &self.messages {
+            updates.extend(TradeUpdate::parse_message(message)?);
+        }
+        Ok(updates)
+    }
+}
+
+/// The channels of a connection, changed by the stream owner through `commands`.
+pub(crate) struct Subscriptions {
+    /// Subscribed on every (re)connection.
+    channels: Arc<Mutex<BTreeSet<String>>>,
+    /// `subscribe`/`unsubscribe` requests to send on the current connection.
+    commands: Option<mpsc::UnboundedReceiver<String>>,
+    recorder: Recorder,
+}
+
+impl Subscriptions {
+    /// A single channel that never changes.
+    fn fixed(channel: String) -> Self {
+        Subscriptions {
+            channels: Arc::new(Mutex::new(BTreeSet::from([channel]))),
+            commands: None,
+            recorder: Recorder::default(),
+        }
+    }
+
+    /// No channels, for a connector that subscribes by itself.
+    fn manual() -> Self {
+        Subscriptions {
+            channels: Arc::default(),
+            commands: None,
+            recorder: Recorder::default(),
+        }
+    }
+}
+
+/// Appends the received messages to a file in the [`WsRecording`] format, once started.
+#[derive(Clone, Default)]
+struct Recorder(Arc<Mutex<Option<File>>>);
+
+impl Recorder {
+    fn start(&self, path: &Path) -> Result<()> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        *self.0.lock().unwrap() = Some(file);
+        Ok(())
+    }
+
+    fn write(&self, message: &str) -> Result<()> {
+        if let Some(file) = self.0.lock().unwrap().as_mut() {
+            writeln!(file, "{}", serde_json::to_string(message)?)?;
+        }
+        Ok(())
+    }
+}
+
+fn request(kind: &str, channel: &str) -> String {
+    serde_json::json!({ "type": kind, "channel": channel }).to_string()
+}
+
+struct SubscribeState<C, S, P, T> {
+    subscriptions: Subscriptions,
+    backoff: Backoff,
+    connect: C,
+    parse: P,
+    conn: Option<WsConnection<S>>,
+    pending: VecDeque<Result<T>>,
+    failures: u32,
+}
+
+enum Event {
+    Command(Option<String>),
+    Message(Result<Message>),
+}
+
+/// Subscribe to the channels, reconnecting with `backoff` whenever the connection fails or is
+/// closed. Every message is turned into items by `parse`.
+pub(crate) fn subscribe<C, Fut, S, P, T>(
+    subscriptions: Subscriptions,
+    backoff: Backoff,
+    connect: C,
+    parse: P,
+) -> BoxStream<'static, Result<T>>
+where
+    C: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<WsConnection<S>>> + Send,
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    P: FnMut(&str) -> Result<Vec<T>> + Send + 'static,
+    T: Send + 'static,
+{
+    let state = SubscribeState {
+        subscriptions,
+        backoff,
+        connect,
+        parse,
+        conn: None,
+        pending: VecDeque::new(),
+        failures: 0,
+    };
+
+    stream::unfold(state, |mut st| async move {
+        loop {
+            if let Some(item) = st.pending.pop_front() {
+                return Some((item, st));
+            }
+
+            let conn = match st.conn.as_mut() {
+                Some(conn) => conn,
+                None => {
+                    if st.failures > 0 {
+                        tokio::time::sleep(st.backoff.delay(st.failures)).await;
+                    }
+                    match open(&mut st.connect, &st.subscriptions.channels).await {
+                        Ok(conn) => st.conn.insert(conn),
+                        Err(e) => {
+                            st.failures += 1;
+                            return Some((Err(e), st));
+                        }
+                    }
+                }
+            };
+
+            // `recv` is cancel safe, so a command can interrupt it.
+            let commands = &mut st.subscriptions.commands;
+            let event = tokio::select! {
+                command = async { commands.as_mut().unwrap().recv().await }, if commands.is_some() => {
+                    Event::Command(command)
+                }
+                message = conn.recv() => Event::Message(message),
+            };
+
+            let sent = match event {
+                Event::Command(Some(command)) => conn.send_text(&command).await,
+                Event::Command(None) => {
+                    st.subscriptions.commands = None;
+                    Ok(())
+                }
+                Event::Message(Ok(Message::Text(message))) => {
+                    st.failures = 0;
+                    if let Err(e) = st.subscriptions.recorder.write(&message) {
+                        return Some((Err(e), st));
+                    }
+                    match (st.parse)(&message) {
+                        Ok(items) => st.pending.extend(items.into_iter().map(Ok)),
+                        Err(e) => return Some((Err(e), st)),
+                    }
+                    Ok(())
+                }
+                Event::Message(Ok(Message::Ping(payload))) => conn.send_pong(&payload).await,
+                Event::Message(Ok(Message::Close(payload))) => {
+                    // best effort, the server closes the connection anyway
+                    let _ = conn.send_close(&payload).await;
+                    st.conn = None;
+                    st.failures += 1;
+                    Ok(())
+                }
+                Event::Message(Err(e)) => Err(e),
+            };
+            if let Err(e) = sent {
+                st.conn = None;
+                st.failures += 1;
+                return Some((Err(e), st));
+            }
+        }
+    })
+    .boxed()
+}
+
+async fn open<C, Fut, S>(
+    connect: &mut C,
+    channels: &Mutex<BTreeSet<String>>,
+) -> Result<WsConnection<S>>
+where
+    C: FnMut() -> Fut,
+    Fut: Future<Output = Result<WsConnection<S>>>,
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut conn = connect().await?;
+    let channels = channels.lock().unwrap().clone();
+    for channel in &channels {
+        conn.send_text(&request("subscribe", channel)).await?;
+    }
+    Ok(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ws::tests::{accept, read_text, write_frame};
+    use std::time::Duration;
+
+    #[test]
+    fn parse_trades_message() {
+        let message = r#"[["1663318663","2357062","btc_jpy","2820896.0","5.0","sell","1193401","2078767"],
+                          ["1663318664","2357063","btc_jpy","2820897.0","0.5","buy","1193402","2078768"]]"#;
+        let updates = TradeUpdate::parse_message(message).unwrap();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[0].trade_id, 2357062);
+        assert_eq!(updates[0].pair, CoinPair::BtcJpy);
+        assert!((updates[0].rate - 2820896.0).abs() < 1e-6);
+        assert!((updates[0].amount - 5.0).abs() < 1e-6);
+        assert_eq!(updates[0].order_type, BaseOrderType::Sell);
+        assert_eq!(updates[0].timestamp.timestamp(), 1663318663);
+        assert_eq!(updates[1].order_type, BaseOrderType::Buy);
+
+        let legacy = r#"[2357062,"btc_jpy","2820896.0","5.0","sell"]"#;
+        let updates = TradeUpdate::parse_message(legacy).unwrap();
+        assert_eq!(updates[0].trade_id, 2357062);
+
+        assert!(TradeUpdate::parse_message(r#"{"type":"error"}"#).is_err());
+        assert!(TradeUpdate::parse_message(r#"[["1","2","xxx_jpy","1","1","buy"]]"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn reconnect_and_resubscribe() {
+        let subscriptions = Arc::new(Mutex::new(Vec::new()));
+        let received = subscriptions.clone();
+        let mut connections = 0;
+        let connect = move || {
+            connections += 1;
+            let n = connections;
+            let received = received.clone();
+            async move {
+                let (client, server) = tokio::io::duplex(1 << 16);
+                tokio::spawn(async move {
+                    let mut server = accept(server).await;
+                    let request = read_text(&mut server).await;
+                    received.lock().unwrap().push(request);
+
+                    let message = format!(r#"[{},"btc_jpy","100.0","1.0","buy"]"#, n);
+                    let io = server.get_mut();
+                    write_frame(io, true, 0x1, b"not json").await;
+                    write_frame(io, true, 0x1, message.as_bytes()).await;
+                    // closing the connection makes the stream reconnect
+                    write_frame(io, true, 0x8, b"").await;
+                });
+                WsConnection::handshake(client, "localhost", "/").await
+            }
+        };
+        let backoff = Backoff {
+            initial: Duration::from_millis(1),
+            max: Duration::from_millis(1),
+            multiplier: 1.0,
+            jitter: 0.0,
+        };
+        let mut stream = subscribe(
+            Subscriptions::fixed("btc_jpy-trades".to_owned()),
+            backoff,
+            connect,
+            TradeUpdate::parse_message,
+        );
+
+        assert!(stream.next().await.unwrap().is_err());
+        assert_eq!(stream.next().await.unwrap().unwrap().trade_id, 1);
+        assert!(stream.next().await.unwrap().is_err());
+        assert_eq!(stream.next().await.unwrap().unwrap().trade_id, 2);
+        drop(stream);
+
+        let subscriptions = subscriptions.lock().unwrap();
+        assert_eq!(subscriptions.len(), 2);
+        assert_eq!(
+            subscriptions[0],
+            r#"{"channel":"btc_jpy-trades","type":"subscribe"}"#
+        );
+    }
+
+    #[test]
+    fn apply_order_book_diffs() {
+        let snapshot: model::OrderBooks = serde_json::from_str(
+            r#"{"asks": [["27330.0", "2.25"], ["27340.0", "0.45"]],
+                "bids": [["27240.0", "1.1543"], ["26800.0", "1.2"]]}"#,
+        )
+        .unwrap();
+        let mut book = LocalOrderBook::from_snapshot(&snapshot);
+        assert_eq!(book.best_ask().unwrap().rate, 27330.0);
+        assert_eq!(book.best_bid().unwrap().rate, 27240.0);
+
+        book.apply_message(
+            r#"["btc_jpy",{"bids":[["27250.0","0.5"],["27240.0","0"]],
+                "asks":[["27330.0","0"],["27335.0","1.0"],["27340.0","0.9"]],
+                "last_update_at":"1659321701"}]"#,
+        )
+        .unwrap();
+        let best_bid = book.best_bid().unwrap();
+        assert_eq!((best_bid.rate, best_bid.amount), (27250.0, 0.5));
+        let best_ask = book.best_ask().unwrap();
+        assert_eq!((best_ask.rate, best_ask.amount), (27335.0, 1.0));
+        assert_eq!(book.last_update_at().unwrap().timestamp(), 1659321701);
+
+        let books = book.to_order_books();
+        let rates = |levels: &[model::OrderBook]| levels.iter().map(|l| l.rate).collect::<Vec<_>>();
+        assert_eq!(rates(&books.asks), vec![27335.0, 27340.0]);
+        assert_eq!(rates(&books.bids), vec![27250.0, 26800.0]);
+        assert_eq!(books.asks[1].amount, 0.9);
+
+        assert!(book.apply_message(r#"{"bids":[]}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn order_book_stream() {
+        let connect = || async {
+            let (client, server) = tokio::io::duplex(1 << 16);
+            tokio::spawn(async move {
+                let mut server = accept(server).await;
+                read_text(&mut server).await;
+                let io = server.get_mut();
+                write_frame(
+                    io,
+                    true,
+                    0x1,
+                    br#"["btc_jpy",{"bids":[["100.0","1.0"]],"asks":[["101.0","2.0"]]}]"#,
+                )
+                .await;
+                write_frame(
+                    io,
+                    true,
+                    0x1,
+                    br#"["btc_jpy",{"bids":[["100.0","0"]],"asks":[]}]"#,
+                )
+                .await;
+                // keep the connection open
+                std::future::pending::<()>().await;
+            });
+            WsConnection::handshake(client, "localhost", "/").await
+        };
+        let mut stream = OrderBookStream::with_connector(
+            CoinPair::BtcJpy,
+            Client::shared_offline(),
+            Some(LocalOrderBook::default()),
+            Backoff::default(),
+            connect,
+        );
+        let path = std::env::temp_dir().join(format!(
+            "coinchecker-orderbook-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        stream.record_to(&path).unwrap();
+
+        let top = stream.next().await.unwrap().unwrap();
+        assert_eq!(top.best_bid.unwrap().rate, 100.0);
+        assert_eq!(stream.best_ask().unwrap().rate, 101.0);
+        let top = stream.next().await.unwrap().unwrap();
+        assert!(top.best_bid.is_none());
+        assert_eq!(top.best_ask.unwrap().rate, 101.0);
+        assert!(stream.best_bid().is_none());
+
+        // the recorded session rebuilds the same book
+        let recording = WsRecording::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(recording.messages.len(), 2);
+        let replayed = recording
+            .replay_order_book(LocalOrderBook::default())
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(replayed.to_order_books()).unwrap(),
+            serde_json::to_value(stream.order_books()).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn order_book_stream_reconnect() {
+        let mut connections = 0;
+        let connect = move || {
+            connections += 1;
+            let first = connections == 1;
+            async move {
+                let (client, server) = tokio::io::duplex(1 << 16);
+                tokio::spawn(async move {
+                    let mut server = accept(server).await;
+                    read_text(&mut server).await;
+                    let io = server.get_mut();
+                    if first {
+                        write_frame(
+                            io,
+                            true,
+                            0x1,
+                            br#"["btc_jpy",{"bids":[["100.0","1.0"]],"asks":[["101.0","2.0"]]}]"#,
+                        )
+                        .await;
+                        write_frame(io, true, 0x8, b"").await;
+                    } else {
+                        write_frame(
+                            io,
+                            true,
+                            0x1,
+                            br#"["btc_jpy",{"bids":[],"asks":[["102.0","1.0"]]}]"#,
+                        )
+                        .await;
+                        std::future::pending::<()>().await;
+                    }
+                });
+                WsConnection::handshake(client, "localhost", "/").await
+            }
+        };
+        let client = Client::shared_offline();
+        client.set_offline_response(
+            "/api/order_books",
+            r#"{"asks": [["103.0", "1.0"]], "bids": [["99.0", "1.0"]]}"#,
+        );
+        let backoff = Backoff {
+            initial: Duration::from_millis(1),
+            max: Duration::from_millis(1),
+            multiplier: 1.0,
+            jitter: 0.0,
+        };
+        let mut stream = OrderBookStream::with_connector(
+            CoinPair::BtcJpy,
+            client,
+            Some(LocalOrderBook::default()),
+            backoff,
+            connect,
+        );
+
+        let top = stream.next().await.unwrap().unwrap();
+        assert_eq!(top.best_bid.unwrap().rate, 100.0);
+        assert_eq!(top.best_ask.unwrap().rate, 101.0);
+
+        // the levels from before the reconnection are replaced with the snapshot
+        let top = stream.next().await.unwrap().unwrap();
+        assert_eq!(top.best_bid.unwrap().rate, 99.0);
+        assert_eq!(top.best_ask.unwrap().rate, 102.0);
+        let rates = |levels: &[model::OrderBook]| levels.iter().map(|l| l.rate).collect::<Vec<_>>();
+        let books = stream.order_books();
+        assert_eq!(rates(&books.asks), vec![102.0, 103.0]);
+        assert_eq!(rates(&books.bids), vec![99.0]);
+    }
+
+    #[tokio::test]
+    async fn order_book_stream_diff_during_snapshot() {
+        use crate::client::ClientConfig;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::sync::oneshot;
+
+        let (diff_sent, mut diff_received) = oneshot::channel();
+        let (send_more, more) = oneshot::channel::<()>();
+        let mut sides = Some((diff_sent, more));
+        let connect = move || {
+            let (diff_sent, more) = sides.take().unwrap();
+            async move {
+                let (client, server) = tokio::io::duplex(1 << 16);
+                tokio::spawn(async move {
+                    let mut server = accept(server).await;
+                    read_text(&mut server).await;
+                    let io = server.get_mut();
+                    write_frame(
+                        io,
+                        true,
+                        0x1,
+                        br#"["btc_jpy",{"bids":[["100.0","1.0"]],"asks":[["103.0","0"]]}]"#,
+                    )
+                    .await;
+                    diff_sent.send(()).unwrap();
+                    more.await.unwrap();
+                    write_frame(
+                        io,
+                        true,
+                        0x1,
+                        br#"["btc_jpy",{"bids":[],"asks":[["102.0","1.0"]]}]"#,
+                    )
+                    .await;
+                    std::future::pending::<()>().await;
+                });
+                WsConnection::handshake(client, "localhost", "/").await
+            }
+        };
+
+        // answers the snapshot request only after the diff is sent
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut head = Vec::new();
+            while !head.ends_with(b"\r\n\r\n") {
+                head.push(conn.read_u8().await.unwrap());
+            }
+            (&mut diff_received).await.unwrap();
+            let body =
+                r#"{"asks": [["103.0", "1.0"], ["104.0", "1.0"]], "bids": [["99.0", "1.0"]]}"#;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            conn.write_all(response.as_bytes()).await.unwrap();
+        });
+        let config = ClientConfig {
+            base_url: format!("http://{}", addr),
+            rate_limit: None,
+            ..Default::default()
+        };
+        let client = Client::shared_with_config(None, None, config);
+        let mut stream = OrderBookStream::with_connector(
+            CoinPair::BtcJpy,
+            client,
+            None,
+            Backoff::default(),
+            connect,
+        );
+
+        let synced = async {
+            while stream.best_ask().is_none() {
+                let _ = tokio::time::timeout(Duration::from_millis(10), stream.next()).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), synced)
+            .await
+            .unwrap();
+        // the diff received during the fetch is applied on top of the snapshot
+        let rates = |levels: &[model::OrderBook]| levels.iter().map(|l| l.rate).collect::<Vec<_>>();
+        let books = stream.order_books();
+        assert_eq!(rates(&books.asks), vec![104.0]);
+        assert_eq!(rates(&books.bids), vec![100.0, 99.0]);
+
+        send_more.send(()).unwrap();
+        let top = stream.next().await.unwrap().unwrap();
+        assert_eq!(top.best_ask.unwrap().rate, 102.0);
+        assert_eq!(top.best_bid.unwrap().rate, 100.0);
+    }
+
+    #[test]
+    fn recording_round_trip() {
+        let recording = WsRecording {
+            messages: vec![
+                r#"[2357062,"btc_jpy","2820896.0","5.0","sell"]"#.to_owned(),
+                "[2357063,\n\"btc_jpy\",\"2820897.0\",\"0.5\",\"buy\"]".to_owned(),
+            ],
+        };
+        let path =
+            std::env::temp_dir().join(format!("coinchecker-trades-{}.jsonl", std::process::id()));
+        recording.save(&path).unwrap();
+        let loaded = WsRecording::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, recording);
+        let trades = loaded.replay_trades().unwrap();
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[1].order_type, BaseOrderType::Buy);
+    }
+
+    #[tokio::test]
+    async fn trades_stream_changes_pairs() {
+        let (requests, mut received) = mpsc::unbounded_channel();
+        let connect = move || {
+            let requests = requests.clone();
+            async move {
+                let (client, server) = tokio::io::duplex(1 << 16);
+                tokio::spawn(async move {
+                    let mut server = accept(server).await;
+                    for _ in 0..2 {
+                        requests.send(read_text(&mut server).await).unwrap();
+                    }
+                    let trades = br#"[["1663318663","1","btc_jpy","100.0","1.0","buy","1","2"],
+                                      ["1663318663","2","etc_jpy","10.0","1.0","sell","3","4"]]"#;
+                    write_frame(server.get_mut(), true, 0x1, trades).await;
+
+                    requests.send(read_text(&mut server).await).unwrap();
+                    // sent before the unsubscribe is processed
+                    write_frame(server.get_mut(), true, 0x1, trades).await;
+                    std::future::pending::<()>().await;
+                });
+                WsConnection::handshake(client, "localhost", "/").await
+            }
+        };
+        let mut stream = TradesStream::with_connector(
+            &[CoinPair::BtcJpy, CoinPair::EtcJpy],
+            Backoff::default(),
+            connect,
+        );
+
+        assert_eq!(stream.next().await.unwrap().unwrap().pair, CoinPair::BtcJpy);
+        assert_eq!(stream.next().await.unwrap().unwrap().pair, CoinPair::EtcJpy);
+        assert_eq!(
+            received.recv().await.unwrap(),
+            r#"{"channel":"btc_jpy-trades","type":"subscribe"}"#
+        );
+        assert_eq!(
+            received.recv().await.unwrap(),
+            r#"{"channel":"etc_jpy-trades","type":"subscribe"}"#
+        );
+
+        stream.unsubscribe(CoinPair::EtcJpy);
+        assert_eq!(stream.pairs(), vec![CoinPair::BtcJpy]);
+        let update = stream.next().await.unwrap().unwrap();
+        assert_eq!((update.pair, update.trade_id), (CoinPair::BtcJpy, 1));
+        assert_eq!(
+            received.recv().await.unwrap(),
+            r#"{"channel":"etc_jpy-trades","type":"unsubscribe"}"#
+        );
+    }
+}
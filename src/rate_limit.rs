@@ -0,0 +1,112 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DurationMilliSeconds};
+
+/// Limit the rate of the requests sent by a [`Client`](crate::client::Client).
+///
+/// A token bucket: a request can be sent every `interval`, and up to `burst` requests at once
+/// after being idle. Requests over the limit wait for their turn, in the order they were made.
+/// Set with [`Client::set_rate_limit`](crate::client::Client::set_rate_limit).
+#[serde_as]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RateLimit {
+    #[serde_as(as = "DurationMilliSeconds<u64>")]
+    pub interval: Duration,
+    pub burst: u32,
+}
+
+impl Default for RateLimit {
+    /// 5 requests per second with bursts of 5, well below the about 10 requests per second
+    /// allowed by Coincheck.
+    fn default() -> Self {
+        RateLimit {
+            interval: Duration::from_millis(200),
+            burst: 5,
+        }
+    }
+}
+
+/// The state of the [`RateLimit`], shared by all the requests of a client.
+#[derive(Debug, Default)]
+pub(crate) struct RateLimiter {
+    /// When the bucket would be full again if no other request came (GCRA's "theoretical
+    /// arrival time").
+    full_at: Mutex<Option<Instant>>,
+    /// The number of requests which had to wait.
+    throttled: AtomicU64,
+}
+
+impl RateLimiter {
+    /// Reserve a slot and wait for it. The lock isn't held while waiting.
+    pub(crate) async fn acquire(&self, limit: &RateLimit) {
+        let wait = self.reserve(limit, Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Reserve the next slot at `now`, and get how long to wait for it.
+    fn reserve(&self, limit: &RateLimit, now: Instant) -> Duration {
+        let tolerance = limit.interval * limit.burst.saturating_sub(1);
+        let mut full_at = self.full_at.lock().unwrap();
+        let start = full_at.map_or(now, |t| t.max(now));
+        *full_at = Some(start + limit.interval);
+        let wait = start
+            .checked_sub(tolerance)
+            .map_or(Duration::ZERO, |t| t.saturating_duration_since(now));
+        if !wait.is_zero() {
+            let count = self.throttled.fetch_add(1, Ordering::Relaxed) + 1;
+            tracing::debug!(?wait, count, "throttled by the rate limit");
+        }
+        wait
+    }
+
+    /// The number of requests which had to wait for their slot.
+    pub(crate) fn throttled_count(&self) -> u64 {
+        self.throttled.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_bucket() {
+        let limit = RateLimit {
+            interval: Duration::from_millis(100),
+            burst: 3,
+        };
+        let limiter = RateLimiter::default();
+        let now = Instant::now();
+        let ms = Duration::from_millis;
+
+        // the burst goes through, then one request every interval
+        let waits: Vec<_> = (0..5).map(|_| limiter.reserve(&limit, now)).collect();
+        assert_eq!(waits, vec![ms(0), ms(0), ms(0), ms(100), ms(200)]);
+        assert_eq!(limiter.throttled_count(), 2);
+
+        // refilled after being idle
+        let later = now + ms(1000);
+        let waits: Vec<_> = (0..4).map(|_| limiter.reserve(&limit, later)).collect();
+        assert_eq!(waits, vec![ms(0), ms(0), ms(0), ms(100)]);
+        assert_eq!(limiter.throttled_count(), 3);
+    }
+
+    #[tokio::test]
+    async fn acquire_waits() {
+        let limit = RateLimit {
+            interval: Duration::from_millis(20),
+            burst: 1,
+        };
+        let limiter = RateLimiter::default();
+        let started = Instant::now();
+        for _ in 0..4 {
+            limiter.acquire(&limit).await;
+        }
+        assert!(started.elapsed() >= Duration::from_millis(60));
+    }
+}
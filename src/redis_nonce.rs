@@ -0,0 +1,155 @@
+//! A [`SharedNonceProvider`] backed by Redis. Requires the `redis-nonce` feature.
+
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use redis::aio::MultiplexedConnection;
+use redis::Script;
+
+use crate::client::SharedNonceProvider;
+use crate::error::{Error, Result};
+use crate::exchange::BoxFuture;
+
+/// Take the greater of the current time and the last nonce + 1, atomically. The nonces are below
+/// 2^53, so they are exact as Lua numbers.
+const NEXT_NONCE: &str = r"
+local now = tonumber(ARGV[1])
+local last = tonumber(redis.call('GET', KEYS[1]) or '0')
+if last < now then
+    redis.call('SET', KEYS[1], ARGV[1])
+    return now
+end
+return redis.call('INCR', KEYS[1])
+";
+
+/// Nonces from a counter in Redis, shared by all the replicas using the same API key.
+///
+/// Each nonce is the current time in microseconds, like the nonces of a [`Client`] without a
+/// provider, but always greater than the last one given to any replica. The counter is updated
+/// by a Lua script, so two replicas never get the same nonce. The counter doesn't need to be
+/// initialized, and a key already used without the provider keeps working.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use coinchecker::redis_nonce::RedisNonce;
+/// use coinchecker::Coincheck;
+///
+/// # fn main() -> coinchecker::error::Result<()> {
+/// let coincheck = Coincheck::new_with_env_keys();
+/// let nonce = RedisNonce::open("redis://127.0.0.1/", "coinchecker:nonce")?;
+/// coincheck.client().set_nonce_provider(Box::new(nonce));
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Client`]: crate::client::Client
+pub struct RedisNonce {
+    client: redis::Client,
+    key: String,
+    script: Script,
+    /// Reused by the requests, and dropped on an error to reconnect.
+    conn: Mutex<Option<MultiplexedConnection>>,
+}
+
+impl RedisNonce {
+    /// Use the counter `key` of `client`.
+    pub fn new(client: redis::Client, key: impl Into<String>) -> Self {
+        RedisNonce {
+            client,
+            key: key.into(),
+            script: Script::new(NEXT_NONCE),
+            conn: Mutex::new(None),
+        }
+    }
+
+    /// Use the counter `key` of the Redis server at `url`, e.g. `redis://127.0.0.1/`.
+    ///
+    /// Only the URL is checked here; the connection is made by the first nonce.
+    pub fn open(url: &str, key: impl Into<String>) -> Result<Self> {
+        let client = redis::Client::open(url)
+            .map_err(|err| Error::InvalidParam(format!("invalid Redis URL: {}", err)))?;
+        Ok(RedisNonce::new(client, key))
+    }
+
+    async fn connection(&self) -> Result<MultiplexedConnection> {
+        if let Some(conn) = self.conn.lock().unwrap().clone() {
+            return Ok(conn);
+        }
+        let conn = self
+            .client
+            .get_multiplexed_async_connection()
+            .await
+            .map_err(Error::other)?;
+        *self.conn.lock().unwrap() = Some(conn.clone());
+        Ok(conn)
+    }
+
+    async fn next(&self) -> Result<u128> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_err(Error::other)?
+            .as_micros();
+        let mut conn = self.connection().await?;
+        let nonce: redis::RedisResult<u64> = self
+            .script
+            .key(&self.key)
+            .arg(now.to_string())
+            .invoke_async(&mut conn)
+            .await;
+        match nonce {
+            Ok(nonce) => Ok(nonce.into()),
+            Err(err) => {
+                *self.conn.lock().unwrap() = None;
+                Err(Error::other(err))
+            }
+        }
+    }
+}
+
+impl SharedNonceProvider for RedisNonce {
+    fn next_nonce(&self) -> BoxFuture<'_, u128> {
+        Box::pin(self.next())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    use super::*;
+
+    /// Needs a Redis server at `REDIS_URL` (default: `redis://127.0.0.1/`).
+    #[tokio::test]
+    async fn redis_nonce_api() {
+        let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1/".to_owned());
+        let key = format!("coinchecker:test:{}", std::process::id());
+
+        // two replicas sharing the key
+        let replicas = [
+            Arc::new(RedisNonce::open(&url, key.as_str()).unwrap()),
+            Arc::new(RedisNonce::open(&url, key.as_str()).unwrap()),
+        ];
+        let tasks: Vec<_> = (0..8)
+            .map(|i| {
+                let nonce = replicas[i % 2].clone();
+                tokio::spawn(async move {
+                    let mut nonces = Vec::new();
+                    for _ in 0..50 {
+                        nonces.push(nonce.next_nonce().await.unwrap());
+                    }
+                    nonces
+                })
+            })
+            .collect();
+
+        let mut all = HashSet::new();
+        for task in tasks {
+            let nonces = task.await.unwrap();
+            assert!(nonces.windows(2).all(|w| w[0] < w[1]));
+            all.extend(nonces);
+        }
+        assert_eq!(all.len(), 8 * 50);
+    }
+}
@@ -0,0 +1,398 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DurationMilliSeconds};
+
+use crate::error::Error;
+
+/// Decide whether a failed request is sent again.
+///
+/// Set a policy with [`Client::set_retry_policy`](crate::client::Client::set_retry_policy).
+/// Private requests are signed again with a fresh nonce on every attempt.
+pub trait RetryPolicy: Send + Sync {
+    /// Get the delay before retrying, or `None` to give up and return the `error`.
+    ///
+    /// `path` doesn't include the query parameters. `attempt` is the number of failed attempts
+    /// so far (starting from 1).
+    fn retry_after(
+        &self,
+        method: &Method,
+        path: &str,
+        error: &Error,
+        attempt: u32,
+    ) -> Option<Duration>;
+}
+
+/// Never retry. (default)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoRetry;
+
+impl RetryPolicy for NoRetry {
+    fn retry_after(&self, _: &Method, _: &str, _: &Error, _: u32) -> Option<Duration> {
+        None
+    }
+}
+
+/// Exponential backoff between the retries.
+///
+/// The delay starts from `initial` and is multiplied by `multiplier` on every attempt, up to
+/// `max`. `jitter` (0.0 to 1.0) randomly shortens each delay by up to that fraction, so that
+/// clients failing together don't retry at the same time.
+///
+/// A negative or NaN delay (e.g. from a negative `multiplier`) is taken as no delay, and a NaN
+/// `jitter` as no jitter.
+#[serde_as]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Backoff {
+    #[serde_as(as = "DurationMilliSeconds<u64>")]
+    pub initial: Duration,
+    #[serde_as(as = "DurationMilliSeconds<u64>")]
+    pub max: Duration,
+    pub multiplier: f64,
+    pub jitter: f64,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            initial: Duration::from_millis(500),
+            max: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl Backoff {
+    /// Get the delay before the retry after `attempt` failed attempts (starting from 1).
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = self.initial.as_secs_f64() * self.multiplier.powi(exponent);
+        // `max` also turns NaN into 0
+        let delay = delay.max(0.0).min(self.max.as_secs_f64());
+        let jitter = if self.jitter.is_nan() {
+            0.0
+        } else {
+            self.jitter.clamp(0.0, 1.0) * Backoff::random_unit(attempt)
+        };
+        Duration::from_secs_f64(delay * (1.0 - jitter))
+    }
+
+    /// A random number in `0.0..1.0`, from the randomly seeded std hasher.
+    fn random_unit(seed: u32) -> f64 {
+        let hash = RandomState::new().hash_one(seed);
+        (hash >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Retry the `GET` requests, which don't change anything, up to `max_retries` times.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RetryGets {
+    pub max_retries: u32,
+    pub backoff: Backoff,
+}
+
+impl RetryPolicy for RetryGets {
+    fn retry_after(
+        &self,
+        method: &Method,
+        _: &str,
+        error: &Error,
+        attempt: u32,
+    ) -> Option<Duration> {
+        if *method == Method::GET && attempt <= self.max_retries {
+            Some(
+                error
+                    .retry_after()
+                    .unwrap_or_else(|| self.backoff.delay(attempt)),
+            )
+        } else {
+            None
+        }
+    }
+}
+
+/// Retry the transient failures: the statuses listed (`429` and `5xx` by default), timeouts and
+/// connection errors.
+///
+/// Only `GET` requests are retried, unless `non_idempotent` is set: retrying a `POST` or a
+/// `DELETE` whose response was lost can place an order twice. The delay requested by the server
+/// ([`Error::retry_after`]) is used instead of the backoff when present.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryTransient {
+    pub max_retries: u32,
+    pub backoff: Backoff,
+    pub statuses: Vec<u16>,
+    pub timeouts: bool,
+    pub connect_errors: bool,
+    /// Also retry `POST` and `DELETE` requests.
+    pub non_idempotent: bool,
+}
+
+impl Default for RetryTransient {
+    fn default() -> Self {
+        RetryTransient {
+            max_retries: 3,
+            backoff: Backoff::default(),
+            statuses: vec![429, 500, 502, 503, 504],
+            timeouts: true,
+            connect_errors: true,
+            non_idempotent: false,
+        }
+    }
+}
+
+impl RetryTransient {
+    /// Whether the error is one of the transient failures to retry.
+    pub fn is_transient(&self, error: &Error) -> bool {
+        match error {
+            Error::Http(err) => match err.status() {
+                Some(status) => self.statuses.contains(&status.as_u16()),
+                None => self.connect_errors && err.is_connect(),
+            },
+            Error::Timeout => self.timeouts,
+            Error::Api { status, .. } if !status.is_success() => {
+                self.statuses.contains(&status.as_u16())
+            }
+            Error::RateLimited { .. } => self.statuses.contains(&429),
+            Error::Maintenance { .. } => self.statuses.contains(&503),
+            _ => false,
+        }
+    }
+}
+
+impl RetryPolicy for RetryTransient {
+    fn retry_after(
+        &self,
+        method: &Method,
+        _: &str,
+        error: &Error,
+        attempt: u32,
+    ) -> Option<Duration> {
+        let idempotent = *method == Method::GET;
+        if (idempotent || self.non_idempotent)
+            && attempt <= self.max_retries
+            && self.is_transient(error)
+        {
+            Some(
+                error
+                    .retry_after()
+                    .unwrap_or_else(|| self.backoff.delay(attempt)),
+            )
+        } else {
+            None
+        }
+    }
+}
+
+/// Parse a `Retry-After` header value: seconds or an HTTP date.
+pub(crate) fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+
+    /// Retry all `GET`s, and `POST`s to the paths listed.
+    struct RetrySafePosts(Vec<&'static str>);
+
+    impl RetryPolicy for RetrySafePosts {
+        fn retry_after(
+            &self,
+            method: &Method,
+            path: &str,
+            _: &Error,
+            attempt: u32,
+        ) -> Option<Duration> {
+            let safe =
+                *method == Method::GET || (*method == Method::POST && self.0.contains(&path));
+            if safe && attempt == 1 {
+                Some(Duration::ZERO)
+            } else {
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn backoff_delay() {
+        let backoff = Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_millis(1000),
+            multiplier: 2.0,
+            jitter: 0.0,
+        };
+        let delays: Vec<_> = (1..=6).map(|attempt| backoff.delay(attempt)).collect();
+        let millis: Vec<_> = delays.iter().map(|d| d.as_millis()).collect();
+        assert_eq!(millis, vec![100, 200, 400, 800, 1000, 1000]);
+
+        let backoff = Backoff {
+            jitter: 0.5,
+            ..backoff
+        };
+        for attempt in 1..=20 {
+            let delay = backoff.delay(attempt);
+            assert!(delay <= Duration::from_millis(1000));
+            assert!(delay >= Duration::from_millis(50));
+        }
+
+        // invalid settings don't panic
+        let zero = Duration::ZERO;
+        let negative = Backoff {
+            multiplier: -2.0,
+            jitter: 0.0,
+            ..backoff
+        };
+        assert_eq!(negative.delay(1), Duration::from_millis(100));
+        assert_eq!(negative.delay(2), zero);
+        let nan = Backoff {
+            multiplier: f64::NAN,
+            ..backoff
+        };
+        assert_eq!(nan.delay(2), zero);
+        let infinite = Backoff {
+            initial: zero,
+            multiplier: f64::INFINITY,
+            ..backoff
+        };
+        assert_eq!(infinite.delay(2), zero);
+        let nan_jitter = Backoff {
+            jitter: f64::NAN,
+            ..backoff
+        };
+        assert_eq!(nan_jitter.delay(1), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn retry_decision() {
+        let err = Error::Timeout;
+        let backoff = Backoff {
+            jitter: 0.0,
+            ..Backoff::default()
+        };
+
+        assert_eq!(NoRetry.retry_after(&Method::GET, "/", &err, 1), None);
+
+        let gets = RetryGets {
+            max_retries: 2,
+            backoff,
+        };
+        assert_eq!(
+            gets.retry_after(&Method::GET, "/", &err, 2),
+            Some(backoff.delay(2))
+        );
+        assert_eq!(gets.retry_after(&Method::GET, "/", &err, 3), None);
+        assert_eq!(gets.retry_after(&Method::POST, "/", &err, 1), None);
+
+        let custom = RetrySafePosts(vec!["/api/safe"]);
+        let policy: &dyn RetryPolicy = &custom;
+        assert!(policy
+            .retry_after(&Method::POST, "/api/safe", &err, 1)
+            .is_some());
+        assert!(policy
+            .retry_after(&Method::POST, "/api/exchange/orders", &err, 1)
+            .is_none());
+        assert!(policy.retry_after(&Method::GET, "/", &err, 1).is_some());
+    }
+
+    #[test]
+    fn retry_transient() {
+        let policy = RetryTransient {
+            backoff: Backoff {
+                jitter: 0.0,
+                ..Backoff::default()
+            },
+            ..RetryTransient::default()
+        };
+        let maintenance = Error::Maintenance { until: None };
+        let other = Error::other("parse error");
+
+        assert_eq!(
+            policy.retry_after(&Method::GET, "/", &maintenance, 1),
+            Some(policy.backoff.delay(1))
+        );
+        assert_eq!(policy.retry_after(&Method::GET, "/", &other, 1), None);
+        assert_eq!(policy.retry_after(&Method::GET, "/", &maintenance, 4), None);
+        assert_eq!(
+            policy.retry_after(&Method::POST, "/", &maintenance, 1),
+            None
+        );
+
+        let policy = RetryTransient {
+            non_idempotent: true,
+            ..policy
+        };
+        assert!(policy
+            .retry_after(&Method::POST, "/", &maintenance, 1)
+            .is_some());
+
+        // the Retry-After hint wins over the backoff
+        let hinted = Error::RateLimited {
+            retry_after: Some(Duration::from_secs(7)),
+        };
+        assert_eq!(
+            policy.retry_after(&Method::GET, "/", &hinted, 1),
+            Some(Duration::from_secs(7))
+        );
+        let until = Utc::now() + chrono::Duration::hours(1);
+        let delay = policy
+            .retry_after(
+                &Method::GET,
+                "/",
+                &Error::Maintenance { until: Some(until) },
+                1,
+            )
+            .unwrap();
+        assert!(delay > Duration::from_secs(3590) && delay <= Duration::from_secs(3600));
+
+        // an error body with a listed status
+        let api_error = |status| Error::Api {
+            status,
+            code: None,
+            message: "error".to_owned(),
+        };
+        assert!(policy.is_transient(&api_error(StatusCode::BAD_GATEWAY)));
+        assert!(!policy.is_transient(&api_error(StatusCode::OK)));
+        assert!(policy.is_transient(&Error::Timeout));
+        let auth = Error::Auth {
+            source: Box::new(api_error(StatusCode::UNAUTHORIZED)),
+        };
+        assert!(!policy.is_transient(&auth));
+    }
+
+    #[test]
+    fn parse_retry_after() {
+        use chrono::TimeZone;
+
+        let now = Utc.with_ymd_and_hms(2015, 10, 21, 7, 28, 0).unwrap();
+        assert_eq!(
+            super::parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            super::parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            super::parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(super::parse_retry_after("soon", now), None);
+    }
+}
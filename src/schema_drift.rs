@@ -0,0 +1,304 @@
+use std::cell::RefCell;
+use std::fmt;
+
+use serde::de::value::BorrowedStrDeserializer;
+use serde::de::Visitor;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, Deserializer, MapAccess, SeqAccess};
+use serde_json::{Error, Value};
+
+/// A difference between a JSON response and the model it was parsed into.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum SchemaDrift {
+    /// The key is in the response, but not in the model.
+    Unexpected(String),
+    /// The key is in the model, but not in the response.
+    Missing(String),
+}
+
+impl SchemaDrift {
+    pub(crate) fn key(&self) -> &str {
+        match self {
+            SchemaDrift::Unexpected(key) | SchemaDrift::Missing(key) => key,
+        }
+    }
+
+    /// Compare the keys of the raw response with the fields of the model `T`, recursively.
+    ///
+    /// The model is deserialized again from `raw`, recording the fields each struct declares, so
+    /// it doesn't need to implement `Serialize`. The keys are JSON paths like `data[].amount`.
+    /// Objects deserialized as maps, including the structs with a `#[serde(flatten)]` catch-all
+    /// field, are not compared. A missing key can only be an optional or defaulted field, since
+    /// the response wouldn't parse otherwise.
+    pub(crate) fn diff<T: DeserializeOwned>(raw: &Value) -> Vec<SchemaDrift> {
+        let drifts = RefCell::new(Vec::new());
+        let tracked = Tracked {
+            value: raw,
+            path: String::new(),
+            drifts: &drifts,
+        };
+        // the response has been parsed already, so an error only stops the comparison
+        let _ = T::deserialize(tracked);
+
+        let mut drifts = drifts.into_inner();
+        drifts.sort();
+        drifts.dedup();
+        drifts
+    }
+
+    fn join(path: &str, key: &str) -> String {
+        match path {
+            "" => key.to_owned(),
+            _ => format!("{}.{}", path, key),
+        }
+    }
+}
+
+impl fmt::Display for SchemaDrift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaDrift::Unexpected(key) => write!(f, "unexpected key in the response: {}", key),
+            SchemaDrift::Missing(key) => write!(f, "missing key in the response: {}", key),
+        }
+    }
+}
+
+/// A deserializer of a [`Value`] that compares each object deserialized as a struct with the
+/// fields of the struct.
+struct Tracked<'a> {
+    value: &'a Value,
+    path: String,
+    drifts: &'a RefCell<Vec<SchemaDrift>>,
+}
+
+impl<'a> Tracked<'a> {
+    fn child(&self, value: &'a Value, path: String) -> Tracked<'a> {
+        Tracked {
+            value,
+            path,
+            drifts: self.drifts,
+        }
+    }
+}
+
+macro_rules! forward_to_value {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            fn $method<V: Visitor<'a>>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, Error> {
+                self.value.$method($($arg,)* visitor)
+            }
+        )*
+    };
+}
+
+impl<'a> Deserializer<'a> for Tracked<'a> {
+    type Error = Error;
+
+    forward_to_value! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_i128();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_u128();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_identifier();
+        deserialize_ignored_any();
+    }
+
+    fn deserialize_option<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'a>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Value::Array(items) => visitor.visit_seq(TrackedSeq {
+                items: items.iter(),
+                path: format!("{}[]", self.path),
+                parent: self,
+            }),
+            _ => self.value.deserialize_seq(visitor),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'a>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let object = match self.value {
+            Value::Object(object) => object,
+            _ => return self.value.deserialize_struct(name, fields, visitor),
+        };
+
+        {
+            let mut drifts = self.drifts.borrow_mut();
+            let unexpected = object
+                .keys()
+                .filter(|key| !fields.contains(&key.as_str()))
+                .map(|key| SchemaDrift::Unexpected(SchemaDrift::join(&self.path, key)));
+            drifts.extend(unexpected);
+            let missing = fields
+                .iter()
+                .filter(|field| !object.contains_key(**field))
+                .map(|field| SchemaDrift::Missing(SchemaDrift::join(&self.path, field)));
+            drifts.extend(missing);
+        }
+        visitor.visit_map(TrackedMap {
+            entries: object.iter(),
+            value: None,
+            parent: self,
+        })
+    }
+}
+
+struct TrackedMap<'a> {
+    entries: serde_json::map::Iter<'a>,
+    /// The value of the last key, with its path.
+    value: Option<(&'a Value, String)>,
+    parent: Tracked<'a>,
+}
+
+impl<'a> MapAccess<'a> for TrackedMap<'a> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'a>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some((value, SchemaDrift::join(&self.parent.path, key)));
+                let key = BorrowedStrDeserializer::<Error>::new(key);
+                seed.deserialize(key).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'a>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let (value, path) = self
+            .value
+            .take()
+            .ok_or_else(|| <Error as de::Error>::custom("value is missing"))?;
+        seed.deserialize(self.parent.child(value, path))
+    }
+}
+
+struct TrackedSeq<'a> {
+    items: std::slice::Iter<'a, Value>,
+    path: String,
+    parent: Tracked<'a>,
+}
+
+impl<'a> SeqAccess<'a> for TrackedSeq<'a> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'a>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        match self.items.next() {
+            Some(item) => seed
+                .deserialize(self.parent.child(item, self.path.clone()))
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use serde::Deserialize;
+    use serde_with::{serde_as, DisplayFromStr};
+
+    #[test]
+    fn detect_schema_drift() {
+        #[derive(Deserialize)]
+        #[allow(dead_code)]
+        struct Page {
+            success: bool,
+            data: Vec<Item>,
+        }
+
+        #[serde_as]
+        #[derive(Deserialize)]
+        #[allow(dead_code)]
+        struct Item {
+            id: u64,
+            #[serde_as(as = "DisplayFromStr")]
+            rate: f64,
+            #[serde(default)]
+            fee: String,
+            memo: Option<String>,
+        }
+
+        let raw = serde_json::json!({
+            "success": true,
+            "data": [
+                {"id": 1, "rate": "100", "memo": null, "new_field": 1},
+                {"id": 2, "rate": "100", "memo": "a", "new_field": 2},
+            ],
+        });
+        assert_eq!(
+            SchemaDrift::diff::<Page>(&raw),
+            vec![
+                SchemaDrift::Unexpected("data[].new_field".to_owned()),
+                SchemaDrift::Missing("data[].fee".to_owned()),
+            ]
+        );
+
+        let raw = serde_json::json!({
+            "success": true,
+            "data": [{"id": 1, "rate": "100", "fee": "0", "memo": null}],
+        });
+        assert!(SchemaDrift::diff::<Page>(&raw).is_empty());
+    }
+
+    #[test]
+    fn catch_all_is_not_compared() {
+        #[derive(Deserialize)]
+        #[allow(dead_code)]
+        struct Balance {
+            jpy: String,
+            #[serde(flatten)]
+            other: HashMap<String, String>,
+        }
+
+        let raw = serde_json::json!({"jpy": "100", "etc": "1"});
+        assert!(SchemaDrift::diff::<Balance>(&raw).is_empty());
+    }
+}
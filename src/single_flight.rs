@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::watch;
+
+use crate::error::{Error, Result};
+
+/// The result of a request, shared with the requests waiting for it.
+type Outcome = Option<Result<String, Arc<Error>>>;
+
+/// The requests in flight by key, so that identical concurrent requests share one response.
+#[derive(Default)]
+pub(crate) struct SingleFlight {
+    in_flight: Mutex<HashMap<String, watch::Receiver<Outcome>>>,
+}
+
+enum Role {
+    Leader(watch::Sender<Outcome>),
+    Follower(watch::Receiver<Outcome>),
+}
+
+/// Removes the key when the leader is done, or cancelled.
+struct Leave<'a> {
+    flight: &'a SingleFlight,
+    key: &'a str,
+}
+
+impl Drop for Leave<'_> {
+    fn drop(&mut self) {
+        self.flight.in_flight.lock().unwrap().remove(self.key);
+    }
+}
+
+impl SingleFlight {
+    /// Run `request`, or wait for the result of the identical request already in flight.
+    ///
+    /// The waiting requests get a copy of the [`Error`] of a failed request, with only the message
+    /// of the errors that can't be copied (e.g. [`Error::Http`]). If the request in flight is
+    /// cancelled, one of the waiting requests runs instead.
+    pub async fn run<F>(&self, key: &str, request: F) -> Result<String>
+    where
+        F: Future<Output = Result<String>>,
+    {
+        loop {
+            let role = {
+                let mut in_flight = self.in_flight.lock().unwrap();
+                match in_flight.get(key) {
+                    Some(rx) => Role::Follower(rx.clone()),
+                    None => {
+                        let (tx, rx) = watch::channel(None);
+                        in_flight.insert(key.to_owned(), rx);
+                        Role::Leader(tx)
+                    }
+                }
+            };
+
+            match role {
+                Role::Leader(tx) => {
+                    let leave = Leave { flight: self, key };
+                    let result = request.await;
+                    drop(leave);
+                    let outcome = match &result {
+                        Ok(body) => Ok(body.clone()),
+                        Err(err) => Err(Arc::new(err.copy())),
+                    };
+                    let _ = tx.send(Some(outcome));
+                    return result;
+                }
+                Role::Follower(mut rx) => {
+                    // the sender is dropped without a result if the leader is cancelled
+                    if rx.changed().await.is_ok() {
+                        if let Some(outcome) = &*rx.borrow() {
+                            return outcome.clone().map_err(|err| err.copy());
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn share_in_flight_request() {
+        let flight = SingleFlight::default();
+        let count = AtomicUsize::new(0);
+        let request = || async {
+            count.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok("body".to_owned())
+        };
+
+        let results = futures_util::future::join_all((0..10).map(|_| flight.run("a", request())));
+        for result in results.await {
+            assert_eq!(result.unwrap(), "body");
+        }
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        // done, so the next one is sent again
+        flight.run("a", request()).await.unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+
+        // a different key isn't shared
+        let (a, b) = tokio::join!(flight.run("a", request()), flight.run("b", request()));
+        assert!(a.is_ok() && b.is_ok());
+        assert_eq!(count.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn share_error() {
+        let flight = SingleFlight::default();
+        let request = || async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Err(Error::Offline)
+        };
+        let (a, b) = tokio::join!(flight.run("a", request()), flight.run("a", request()));
+        assert!(matches!(a.unwrap_err(), Error::Offline));
+        assert!(matches!(b.unwrap_err(), Error::Offline));
+    }
+
+    #[tokio::test]
+    async fn leader_cancelled() {
+        let flight = SingleFlight::default();
+        let leader = flight.run("a", std::future::pending());
+        let follower = flight.run("a", async { Ok("body".to_owned()) });
+        tokio::pin!(follower);
+
+        // the follower waits for the leader, then runs its own request when it's cancelled
+        tokio::select! {
+            biased;
+            _ = leader => unreachable!(),
+            _ = &mut follower => unreachable!(),
+            _ = tokio::time::sleep(Duration::from_millis(50)) => {}
+        }
+        assert_eq!(follower.await.unwrap(), "body");
+    }
+}
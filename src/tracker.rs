@@ -0,0 +1,412 @@
+//! Rolling statistics over market data.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::public::model::{Ticker, Trade};
+use crate::types::*;
+
+fn to_chrono(duration: Duration) -> chrono::Duration {
+    chrono::Duration::from_std(duration).unwrap_or(chrono::Duration::MAX)
+}
+
+/// Time-weighted average spread over a rolling window.
+///
+/// Each spread (`ask - bid`) is treated as valid until the next sample. The window ends at the
+/// latest sample time, so the result only depends on the fed tickers, not on the local clock.
+///
+/// # Examples
+///
+/// ```rust
+/// # use coinchecker::Coincheck;
+/// # use coinchecker::types::CoinPair;
+/// # use coinchecker::tracker::SpreadTracker;
+/// # use std::time::Duration;
+/// #
+/// #[tokio::main]
+/// async fn main() {
+///     let mut coincheck = Coincheck::new_without_keys();
+///     let mut tracker = SpreadTracker::new(Duration::from_secs(60));
+///     for _ in 0..3 {
+///         if let Ok(ticker) = coincheck.public.ticker_for(&CoinPair::BtcJpy).await {
+///             tracker.update(&ticker);
+///         }
+///     }
+///     println!("{:?} {:?}", tracker.average_spread(), tracker.max_spread());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SpreadTracker {
+    window: chrono::Duration,
+    samples: VecDeque<(DateTime<Utc>, PriceType)>,
+}
+
+impl SpreadTracker {
+    /// Create a new tracker with the window length.
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window: to_chrono(window),
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Add the spread of the ticker.
+    pub fn update(&mut self, ticker: &Ticker) {
+        self.push(ticker.timestamp, ticker.ask - ticker.bid);
+    }
+
+    /// Add a spread sample. Samples older than the latest one are ignored.
+    pub fn push(&mut self, time: DateTime<Utc>, spread: PriceType) {
+        if let Some(&(last, _)) = self.samples.back() {
+            if time < last {
+                return;
+            }
+        }
+        self.samples.push_back((time, spread));
+
+        // keep the last sample before the window start, it covers the beginning of the window
+        let start = time - self.window;
+        while self.samples.len() > 1 && self.samples[1].0 <= start {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Get the time-weighted average spread over the window.
+    pub fn average_spread(&self) -> Option<PriceType> {
+        let &(end, last_spread) = self.samples.back()?;
+        let start = end - self.window;
+
+        let mut weighted = 0 as PriceType;
+        let mut total_ms = 0i64;
+        for (i, &(time, spread)) in self.samples.iter().enumerate() {
+            let from = time.max(start);
+            let to = self.samples.get(i + 1).map_or(end, |&(next, _)| next);
+            let ms = (to - from).num_milliseconds();
+            if ms > 0 {
+                weighted += spread * ms as PriceType;
+                total_ms += ms;
+            }
+        }
+
+        if total_ms == 0 {
+            return Some(last_spread);
+        }
+        Some(weighted / total_ms as PriceType)
+    }
+
+    /// Get the max spread over the window.
+    pub fn max_spread(&self) -> Option<PriceType> {
+        self.samples
+            .iter()
+            .map(|&(_, spread)| spread)
+            .fold(None, |max, spread| match max {
+                Some(max) if max >= spread => Some(max),
+                _ => Some(spread),
+            })
+    }
+}
+
+/// Short-term history of sampled tickers, in a ring buffer.
+///
+/// Coincheck has no historical ticker endpoint, so the history is built from the polled tickers.
+/// The oldest ticker is dropped when the buffer is full.
+///
+/// # Examples
+///
+/// ```rust
+/// # use coinchecker::Coincheck;
+/// # use coinchecker::types::CoinPair;
+/// # use coinchecker::tracker::TickerHistory;
+/// # use std::time::Duration;
+/// #
+/// #[tokio::main]
+/// async fn main() {
+///     let mut coincheck = Coincheck::new_without_keys();
+///     let mut history = TickerHistory::new(300);
+///     for _ in 0..3 {
+///         if let Ok(ticker) = coincheck.public.ticker_for(&CoinPair::BtcJpy).await {
+///             history.push(ticker);
+///         }
+///     }
+///     let five_minutes_ago = chrono::Utc::now() - chrono::Duration::minutes(5);
+///     println!("{:?}", history.since(five_minutes_ago));
+/// }
+/// ```
+#[derive(Debug)]
+pub struct TickerHistory {
+    capacity: usize,
+    tickers: VecDeque<Ticker>,
+}
+
+impl TickerHistory {
+    /// Create a new history keeping up to `capacity` tickers.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tickers: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Add a ticker. Tickers older than the latest one are ignored.
+    pub fn push(&mut self, ticker: Ticker) {
+        if self.capacity == 0 {
+            return;
+        }
+        if let Some(last) = self.tickers.back() {
+            if ticker.timestamp < last.timestamp {
+                return;
+            }
+        }
+        if self.tickers.len() == self.capacity {
+            self.tickers.pop_front();
+        }
+        self.tickers.push_back(ticker);
+    }
+
+    pub fn len(&self) -> usize {
+        self.tickers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tickers.is_empty()
+    }
+
+    /// Get the latest `n` tickers, oldest first.
+    pub fn last_n(&self, n: usize) -> Vec<&Ticker> {
+        let skip = self.tickers.len().saturating_sub(n);
+        self.tickers.iter().skip(skip).collect()
+    }
+
+    /// Get the tickers with a `timestamp` at or after `time`, oldest first.
+    pub fn since(&self, time: DateTime<Utc>) -> Vec<&Ticker> {
+        let start = self.tickers.partition_point(|t| t.timestamp < time);
+        self.tickers.iter().skip(start).collect()
+    }
+}
+
+/// Order flow statistics over the window of an [`OrderFlowTracker`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderFlowSnapshot {
+    /// Time of the latest trade (end of the window).
+    pub time: DateTime<Utc>,
+    /// Total amount of the trades taken by buyers.
+    pub buy_volume: PriceType,
+    /// Total amount of the trades taken by sellers.
+    pub sell_volume: PriceType,
+    pub trade_count: usize,
+    pub average_trade_size: PriceType,
+}
+
+/// Rolling buy/sell volume of trades over a window.
+///
+/// The window ends at the latest trade time. Trades are identified by `id`, so the same trades
+/// can be fed again when polling [`Public::trades`](crate::public::Public::trades).
+///
+/// # Examples
+///
+/// ```rust
+/// # use coinchecker::Coincheck;
+/// # use coinchecker::types::CoinPair;
+/// # use coinchecker::tracker::OrderFlowTracker;
+/// # use std::time::Duration;
+/// #
+/// #[tokio::main]
+/// async fn main() {
+///     let mut coincheck = Coincheck::new_without_keys();
+///     let mut tracker = OrderFlowTracker::new(Duration::from_secs(300));
+///     for _ in 0..3 {
+///         if let Ok(trades) = coincheck.public.trades(&CoinPair::BtcJpy).await {
+///             tracker.extend(&trades.data);
+///             println!("{:?}", tracker.snapshot());
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct OrderFlowTracker {
+    window: chrono::Duration,
+    trades: VecDeque<(DateTime<Utc>, BaseOrderType, PriceType)>,
+    last_id: Option<IdType>,
+}
+
+impl OrderFlowTracker {
+    /// Create a new tracker with the window length.
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window: to_chrono(window),
+            trades: VecDeque::new(),
+            last_id: None,
+        }
+    }
+
+    /// Add the trades, in any order. Trades already added (or older) and trades whose amount
+    /// can't be parsed are ignored.
+    pub fn extend(&mut self, trades: &[Trade]) {
+        let mut trades: Vec<&Trade> = trades.iter().collect();
+        trades.sort_by_key(|trade| trade.id);
+        for trade in trades {
+            self.update(trade);
+        }
+    }
+
+    /// Add a trade. Trades with an `id` not greater than the last one are ignored.
+    pub fn update(&mut self, trade: &Trade) {
+        if self.last_id.is_some_and(|last_id| trade.id <= last_id) {
+            return;
+        }
+        if let Ok(amount) = trade.amount.parse() {
+            self.last_id = Some(trade.id);
+            self.push(trade.created_at, trade.order_type, amount);
+        }
+    }
+
+    /// Add a trade sample. Samples older than the latest one are ignored.
+    pub fn push(&mut self, time: DateTime<Utc>, side: BaseOrderType, amount: PriceType) {
+        if let Some(&(last, _, _)) = self.trades.back() {
+            if time < last {
+                return;
+            }
+        }
+        self.trades.push_back((time, side, amount));
+
+        let start = time - self.window;
+        while self.trades.front().is_some_and(|&(t, _, _)| t <= start) {
+            self.trades.pop_front();
+        }
+    }
+
+    /// Get the statistics over the window. `None` if no trade has been added.
+    pub fn snapshot(&self) -> Option<OrderFlowSnapshot> {
+        let &(time, _, _) = self.trades.back()?;
+        let volume = |side: BaseOrderType| {
+            self.trades
+                .iter()
+                .filter(|&&(_, s, _)| s == side)
+                .map(|&(_, _, amount)| amount)
+                .sum::<PriceType>()
+        };
+        let buy_volume = volume(BaseOrderType::Buy);
+        let sell_volume = volume(BaseOrderType::Sell);
+        let trade_count = self.trades.len();
+
+        Some(OrderFlowSnapshot {
+            time,
+            buy_volume,
+            sell_volume,
+            trade_count,
+            average_trade_size: (buy_volume + sell_volume) / trade_count as PriceType,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(secs, 0).unwrap()
+    }
+
+    #[test]
+    fn time_weighted_spread() {
+        let mut tracker = SpreadTracker::new(Duration::from_secs(60));
+        assert_eq!(tracker.average_spread(), None);
+
+        tracker.push(at(0), 10 as PriceType);
+        assert_eq!(tracker.average_spread(), Some(10 as PriceType));
+
+        // 10 for 30s, then 20 for 10s
+        tracker.push(at(30), 20 as PriceType);
+        tracker.push(at(40), 5 as PriceType);
+        assert_eq!(tracker.average_spread(), Some(12.5 as PriceType));
+        assert_eq!(tracker.max_spread(), Some(20 as PriceType));
+
+        // window is [40, 100]: 5 for 60s
+        tracker.push(at(100), 1 as PriceType);
+        assert_eq!(tracker.average_spread(), Some(5 as PriceType));
+        assert_eq!(tracker.max_spread(), Some(5 as PriceType));
+
+        // out of order sample is ignored
+        tracker.push(at(50), 1000 as PriceType);
+        assert_eq!(tracker.max_spread(), Some(5 as PriceType));
+    }
+
+    fn ticker(secs: i64, last: PriceType) -> Ticker {
+        Ticker {
+            last,
+            bid: last,
+            ask: last,
+            high: last,
+            low: last,
+            volume: 0 as PriceType,
+            timestamp: at(secs),
+        }
+    }
+
+    #[test]
+    fn ticker_history_ring_buffer() {
+        let mut history = TickerHistory::new(3);
+        assert!(history.is_empty());
+        assert!(history.last_n(2).is_empty());
+
+        for secs in 0..5 {
+            history.push(ticker(secs * 10, secs as PriceType));
+        }
+        // out of order sample is ignored
+        history.push(ticker(15, 100 as PriceType));
+        assert_eq!(history.len(), 3);
+
+        let lasts =
+            |tickers: Vec<&Ticker>| -> Vec<PriceType> { tickers.iter().map(|t| t.last).collect() };
+        assert_eq!(lasts(history.last_n(2)), vec![3.0, 4.0]);
+        assert_eq!(lasts(history.last_n(10)), vec![2.0, 3.0, 4.0]);
+        assert_eq!(lasts(history.since(at(30))), vec![3.0, 4.0]);
+        assert_eq!(lasts(history.since(at(25))), vec![3.0, 4.0]);
+        assert!(history.since(at(41)).is_empty());
+    }
+
+    fn trade(id: IdType, secs: i64, side: BaseOrderType, amount: &str) -> Trade {
+        Trade {
+            id,
+            amount: amount.to_owned(),
+            rate: "100".to_owned(),
+            pair: CoinPair::BtcJpy.as_str().to_owned(),
+            order_type: side,
+            created_at: at(secs),
+        }
+    }
+
+    #[test]
+    fn rolling_order_flow() {
+        use BaseOrderType::{Buy, Sell};
+
+        let mut tracker = OrderFlowTracker::new(Duration::from_secs(60));
+        assert_eq!(tracker.snapshot(), None);
+
+        // newest first, like the API
+        let page = vec![
+            trade(3, 30, Sell, "0.5"),
+            trade(2, 20, Buy, "0.25"),
+            trade(1, 0, Buy, "1"),
+        ];
+        tracker.extend(&page);
+        tracker.extend(&page);
+        let snapshot = tracker.snapshot().unwrap();
+        assert_eq!(snapshot.time, at(30));
+        assert_eq!(snapshot.buy_volume, 1.25);
+        assert_eq!(snapshot.sell_volume, 0.5);
+        assert_eq!(snapshot.trade_count, 3);
+
+        // trade 1 leaves the window [20, 80]
+        tracker.extend(&[trade(5, 80, Sell, "x"), trade(4, 60, Sell, "0.25")]);
+        let snapshot = tracker.snapshot().unwrap();
+        assert_eq!(snapshot.time, at(60));
+        assert_eq!(snapshot.buy_volume, 0.25);
+        assert_eq!(snapshot.sell_volume, 0.75);
+        assert_eq!(snapshot.trade_count, 3);
+        assert_eq!(snapshot.average_trade_size, 1.0 / 3.0);
+    }
+}
@@ -1,24 +1,137 @@
 use std::collections::HashMap;
 use std::fmt;
+use std::time::Duration;
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use serde_with::{serde_as, DisplayFromStr};
+use serde_with::{serde_as, DeserializeFromStr, DisplayFromStr, SerializeDisplay};
+
+use crate::error::{Error, Result};
 
 pub type Params<'a> = HashMap<&'a str, &'a str>;
 
+/// Request parameters that own their keys and values.
+pub type OwnedParams = HashMap<String, String>;
+
+/// Serialize a struct into request parameters.
+///
+/// The struct must serialize to a flat object. `null` fields are omitted, strings are used as-is
+/// and the other values are formatted as JSON (e.g. numbers and booleans).
+pub fn params_from_serialize<T: Serialize>(value: &T) -> Result<OwnedParams> {
+    let fields = match serde_json::to_value(value)? {
+        serde_json::Value::Object(fields) => fields,
+        other => {
+            return Err(Error::InvalidParam(format!(
+                "params must be an object: {}",
+                other
+            )))
+        }
+    };
+
+    let mut params = OwnedParams::new();
+    for (key, value) in fields {
+        let value = match value {
+            serde_json::Value::Null => continue,
+            serde_json::Value::String(s) => s,
+            serde_json::Value::Array(_) | serde_json::Value::Object(_) => {
+                return Err(Error::InvalidParam(format!(
+                    "param `{}` must be a scalar: {}",
+                    key, value
+                )))
+            }
+            other => other.to_string(),
+        };
+        params.insert(key, value);
+    }
+    Ok(params)
+}
+
+/// Borrow [`OwnedParams`] as [`Params`].
+pub fn borrow_params(params: &OwnedParams) -> Params<'_> {
+    params
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect()
+}
+
 /// ID value type (default: [u64])
 pub type IdType = u64;
 
+macro_rules! id_newtype {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+        #[serde(transparent)]
+        pub struct $name(pub IdType);
+
+        impl From<IdType> for $name {
+            fn from(id: IdType) -> Self {
+                $name(id)
+            }
+        }
+
+        impl From<$name> for IdType {
+            fn from(id: $name) -> Self {
+                id.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+    };
+}
+
+id_newtype!(
+    /// 注文ID
+    ///
+    /// 他の種類のIDと取り違えないように、IDごとに別の型になっています。
+    ///
+    /// ```compile_fail
+    /// # use coinchecker::Coincheck;
+    /// # use coinchecker::types::BankAccountId;
+    /// # async fn cancel(coincheck: &mut Coincheck) {
+    /// coincheck.private.order.cancel(BankAccountId(5)).await;
+    /// # }
+    /// ```
+    OrderId
+);
+id_newtype!(
+    /// 取引ID
+    TransactionId
+);
+id_newtype!(
+    /// 銀行口座ID
+    BankAccountId
+);
+id_newtype!(
+    /// 出金ID
+    WithdrawId
+);
+
 /// Price value type (default: [f64])
 #[cfg(not(feature = "price_type_f32"))]
 pub type PriceType = f64;
 #[cfg(feature = "price_type_f32")]
 pub type PriceType = f32;
 
+/// 最小注文金額 (円相当額)
+///
+/// <https://faq.coincheck.com/s/article/40218>
+pub const MIN_ORDER_PRICE_JPY: PriceType = 500 as PriceType;
+
 /// 通貨
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Currency {
     Jpy,
     Btc,
+    Etc,
+    Fct,
+    Mona,
+    Plt,
+    Xrp,
 }
 
 impl Currency {
@@ -26,11 +139,185 @@ impl Currency {
         match self {
             Currency::Jpy => "JPY",
             Currency::Btc => "BTC",
+            Currency::Etc => "ETC",
+            Currency::Fct => "FCT",
+            Currency::Mona => "MONA",
+            Currency::Plt => "PLT",
+            Currency::Xrp => "XRP",
         }
     }
+
+    /// 全ての通貨
+    pub const ALL: [Currency; 7] = [
+        Currency::Jpy,
+        Currency::Btc,
+        Currency::Etc,
+        Currency::Fct,
+        Currency::Mona,
+        Currency::Plt,
+        Currency::Xrp,
+    ];
+}
+
+/// 数量の小数点以下の最大桁数
+pub const fn precision(currency: &Currency) -> u32 {
+    match currency {
+        Currency::Jpy => 0,
+        Currency::Xrp => 6,
+        Currency::Btc | Currency::Etc | Currency::Fct | Currency::Mona | Currency::Plt => 8,
+    }
+}
+
+/// 数量を通貨の桁数 ([`precision`]) に丸めます。
+///
+/// 残高や注文数量を超えないように、0 方向に切り捨てます。
+///
+/// `0.29` のように浮動小数点で正確に表せない数量が 1 単位少なく切り捨てられないよう、
+/// 最小単位の整数に十分近い場合はその整数とみなします。計算は `f64` で行います。
+pub fn round_amount(currency: &Currency, amount: PriceType) -> PriceType {
+    const SNAP: f64 = 1e-6;
+
+    let scale = 10f64.powi(precision(currency) as i32);
+    #[allow(clippy::unnecessary_cast)] // `PriceType` is `f32` with `price_type_f32`
+    let scaled = amount as f64 * scale;
+    let nearest = scaled.round();
+    let units = if (scaled - nearest).abs() < SNAP {
+        nearest
+    } else {
+        scaled.trunc()
+    };
+    (units / scale) as PriceType
+}
+
+/// レートを取引ペアの呼値の単位 ([`CoinPair::tick_size`]) の最も近い倍数に丸めます。
+///
+/// ちょうど中間の場合は 0 から遠い方へ丸めます。
+///
+/// `price_type_f32` でも BTC の価格帯で桁が落ちないように、計算は `f64` で行います。
+pub fn round_to_tick(pair: &CoinPair, rate: PriceType) -> PriceType {
+    #[allow(clippy::unnecessary_cast)] // `PriceType` is `f32` with `price_type_f32`
+    let (tick, rate) = (pair.tick_size() as f64, rate as f64);
+    let rounded = if tick >= 1.0 {
+        (rate / tick).round() * tick
+    } else {
+        // 0.001 などは浮動小数点で正確に表せないため、1 / tick 倍して整数で丸めます
+        let scale = (1.0 / tick).round();
+        (rate * scale).round() / scale
+    };
+    rounded as PriceType
+}
+
+/// 1 BTC あたりの satoshi
+pub const SATOSHI_PER_BTC: i64 = 100_000_000;
+
+/// BTC の数量を satoshi に変換します。
+///
+/// 1 satoshi 未満は最も近い値に丸めます (0.5 は 0 から遠い方へ丸めます)。
+/// 計算は `f64` で行います。
+pub fn to_satoshi(amount: PriceType) -> i64 {
+    #[allow(clippy::unnecessary_cast)] // `PriceType` is `f32` with `price_type_f32`
+    let amount = amount as f64;
+    (amount * SATOSHI_PER_BTC as f64).round() as i64
+}
+
+/// satoshi を BTC の数量に変換します。
+///
+/// `f64` では [`to_satoshi`] で元の値に戻ります。`price_type_f32` の場合は有効桁数が
+/// 約 7 桁のため、戻らないことがあります (例: `1.12345678` BTC)。
+pub fn from_satoshi(satoshi: i64) -> PriceType {
+    (satoshi as f64 / SATOSHI_PER_BTC as f64) as PriceType
+}
+
+/// 円の金額を整数に変換します。
+///
+/// 1 円未満は最も近い値に丸めます (0.5 は 0 から遠い方へ丸めます)。
+pub fn to_yen(price: PriceType) -> i64 {
+    price.round() as i64
+}
+
+/// 整数の円を金額に変換します。
+pub fn from_yen(yen: i64) -> PriceType {
+    yen as PriceType
+}
+
+/// 入力された数量の文字列を数値に変換します。
+///
+/// 前後の空白、通貨単位 (`0.1 BTC`, `1000円` など) と円記号 (`¥`, `￥`) を取り除きます。
+/// `,` が 1 つだけで小数点がない場合、後ろが 3 桁でないか整数部が `0` のとき (`0,1`, `0,125` など)
+/// は小数点とみなし、それ以外 (`1,000` など) は桁区切りか小数点か判断できないためエラーを返します。
+/// `,` が複数ある場合や小数点がある場合 (`1,000,000`, `1,000.5` など) は桁区切りとみなします。
+/// 数字と小数点以外を含む場合や、正の数でない場合はエラーを返します。
+pub fn parse_amount(input: &str) -> Result<PriceType> {
+    let number = input
+        .trim()
+        .trim_start_matches(|c: char| c == '¥' || c == '￥' || c.is_whitespace())
+        .trim_end_matches(|c: char| c.is_alphabetic() || c.is_whitespace());
+
+    let invalid = || Error::InvalidParam(format!("invalid amount: {:?}", input));
+    let number = match (number.contains('.'), number.split_once(',')) {
+        (false, Some((int, frac))) if !frac.contains(',') => {
+            if frac.len() != 3 || int.trim_start_matches('0').is_empty() {
+                number.replace(',', ".")
+            } else {
+                return Err(invalid());
+            }
+        }
+        _ => number.replace(',', ""),
+    };
+
+    let is_decimal = number.chars().all(|c| c.is_ascii_digit() || c == '.')
+        && number.chars().any(|c| c.is_ascii_digit())
+        && number.matches('.').count() <= 1;
+    match number.parse::<PriceType>() {
+        Ok(amount) if is_decimal && amount > 0 as PriceType => Ok(amount),
+        _ => Err(invalid()),
+    }
+}
+
+/// 取引ペアの文字列を API のクエリパラメータの形式 (`btc_jpy` など) に変換します。
+///
+/// 小文字にして、区切り (`_`, `/`, `-`, 空白) を `_` 1 つにそろえます。区切りがない場合
+/// (`BTCJPY` など) は、末尾の決済通貨 (`jpy`) の前に `_` を入れます。
+pub fn normalize_pair_string(s: &str) -> String {
+    let words: Vec<String> = s
+        .split(|c: char| c == '_' || c == '/' || c == '-' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .map(str::to_ascii_lowercase)
+        .collect();
+    match words.as_slice() {
+        [word] => match word.strip_suffix("jpy") {
+            Some(base) if !base.is_empty() => format!("{}_jpy", base),
+            _ => word.clone(),
+        },
+        _ => words.join("_"),
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for Currency {
+    type Err = &'static str;
+
+    /// Parse a currency code, case-insensitively (the API uses both `JPY` and `jpy`).
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Currency::ALL
+            .iter()
+            .find(|currency| currency.as_str().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or("undefined Currency type")
+    }
 }
 
 /// 取引ペア
+///
+/// API と同じ `btc_jpy` の形式の文字列としてシリアライズします。
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, SerializeDisplay, DeserializeFromStr,
+)]
 pub enum CoinPair {
     BtcJpy,
     EtcJpy,
@@ -40,6 +327,15 @@ pub enum CoinPair {
 }
 
 impl CoinPair {
+    /// 全ての取引ペア
+    pub const ALL: [CoinPair; 5] = [
+        CoinPair::BtcJpy,
+        CoinPair::EtcJpy,
+        CoinPair::FctJpy,
+        CoinPair::MonaJpy,
+        CoinPair::PltJpy,
+    ];
+
     pub const fn as_str(&self) -> &str {
         match self {
             CoinPair::BtcJpy => "btc_jpy",
@@ -49,9 +345,69 @@ impl CoinPair {
             CoinPair::PltJpy => "plt_jpy",
         }
     }
+
+    /// 取引通貨
+    pub const fn base(&self) -> Currency {
+        match self {
+            CoinPair::BtcJpy => Currency::Btc,
+            CoinPair::EtcJpy => Currency::Etc,
+            CoinPair::FctJpy => Currency::Fct,
+            CoinPair::MonaJpy => Currency::Mona,
+            CoinPair::PltJpy => Currency::Plt,
+        }
+    }
+
+    /// 決済通貨
+    pub const fn quote(&self) -> Currency {
+        Currency::Jpy
+    }
+
+    /// 最小注文数量 (取引通貨建て)
+    ///
+    /// 数量の制限がない取引ペアは `None` です。
+    ///
+    /// <https://faq.coincheck.com/s/article/40218>
+    pub const fn min_order_amount(&self) -> Option<PriceType> {
+        match self {
+            CoinPair::BtcJpy => Some(0.005 as PriceType),
+            _ => None,
+        }
+    }
+
+    /// 呼値の単位 (決済通貨建て)
+    ///
+    /// 注文のレートはこの値の倍数でなければなりません。
+    pub const fn tick_size(&self) -> PriceType {
+        match self {
+            CoinPair::BtcJpy | CoinPair::EtcJpy => 1 as PriceType,
+            CoinPair::FctJpy | CoinPair::MonaJpy | CoinPair::PltJpy => 0.001 as PriceType,
+        }
+    }
+}
+
+impl fmt::Display for CoinPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for CoinPair {
+    type Err = &'static str;
+
+    /// Parse a pair, case-insensitively and with either `_` or `/` as the separator
+    /// (e.g. `btc_jpy`, `BTC_JPY` and `BTC/JPY`).
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.replace('/', "_");
+        CoinPair::ALL
+            .iter()
+            .find(|pair| pair.as_str().eq_ignore_ascii_case(&s))
+            .copied()
+            .ok_or("undefined CoinPair type")
+    }
 }
 
 /// 売り買い
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum BaseOrderType {
     Buy,
     Sell,
@@ -66,7 +422,26 @@ impl BaseOrderType {
     }
 }
 
+impl fmt::Display for BaseOrderType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for BaseOrderType {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        [BaseOrderType::Buy, BaseOrderType::Sell]
+            .iter()
+            .find(|side| side.as_str() == s)
+            .copied()
+            .ok_or("undefined BaseOrderType type")
+    }
+}
+
 /// 注文方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum OrderType {
     Limit(BaseOrderType),
     MarketBuy,
@@ -87,8 +462,45 @@ impl OrderType {
     }
 }
 
+impl std::str::FromStr for OrderType {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        [
+            OrderType::LimitBuy,
+            OrderType::LimitSell,
+            OrderType::MarketBuy,
+            OrderType::MarketSell,
+        ]
+        .iter()
+        .find(|order_type| order_type.as_str() == s)
+        .copied()
+        .ok_or("undefined OrderType type")
+    }
+}
+
+/// 取引ペアで利用できる注文方法
+///
+/// 取引所 (現物) の全ての取引ペアで指値と成行の売り買いが利用できます。
+/// レバレッジ取引は提供されていないため、対応する注文方法はありません。
+pub fn supported_order_types(pair: &CoinPair) -> &'static [OrderType] {
+    const SPOT: &[OrderType] = &[
+        OrderType::LimitBuy,
+        OrderType::LimitSell,
+        OrderType::MarketBuy,
+        OrderType::MarketSell,
+    ];
+    match pair {
+        CoinPair::BtcJpy
+        | CoinPair::EtcJpy
+        | CoinPair::FctJpy
+        | CoinPair::MonaJpy
+        | CoinPair::PltJpy => SPOT,
+    }
+}
+
 /// 並び順
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SortOrder {
     Asc,
     Desc,
@@ -122,15 +534,282 @@ impl std::str::FromStr for SortOrder {
     }
 }
 
+/// A value with the time taken by the request that returned it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timed<T> {
+    pub value: T,
+    pub latency: Duration,
+}
+
+/// A value with the metadata of the response that returned it.
+///
+/// See [`Client::request_and_get_json_with_meta`](crate::client::Client::request_and_get_json_with_meta).
+#[derive(Debug, Clone, PartialEq)]
+pub struct WithMeta<T> {
+    pub value: T,
+    /// The HTTP status of the response.
+    pub status: reqwest::StatusCode,
+    /// The time taken from sending the request to reading the whole response.
+    pub latency: Duration,
+    /// The `Date` header of the response, if any.
+    pub server_date: Option<DateTime<Utc>>,
+}
+
+/// A response of the API, which may have a `success` flag.
+///
+/// [`Client::request_and_get_json`](crate::client::Client::request_and_get_json) returns
+/// [`Error::Api`](crate::error::Error::Api) when `success` is `false`. Responses without the flag
+/// use the default implementation and are always successful.
+pub trait ApiResponse {
+    /// The `success` flag of the response.
+    fn success(&self) -> bool {
+        true
+    }
+}
+
+/// A value with the pair it describes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tagged<T> {
+    pub pair: CoinPair,
+    pub value: T,
+}
+
 /// ページネーション
 ///
+/// `limit` は 1 から [`Pagination::MAX_LIMIT`] の範囲に丸めてからリクエストされます。
+///
 /// <https://coincheck.com/ja/documents/exchange/api#pagination>
 #[serde_as]
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Pagination {
+    /// 1ページあたりの取得件数 (max: [`Pagination::MAX_LIMIT`])
+    pub limit: i32,
+    #[serde_as(as = "DisplayFromStr")]
+    pub order: SortOrder,
+    pub starting_after: Option<IdType>,
+    pub ending_before: Option<IdType>,
+}
+
+impl Pagination {
+    /// Maximum number of records per page accepted by the API.
+    pub const MAX_LIMIT: i32 = 100;
+
+    /// Get the `limit` clamped to the range accepted by the API (`1..=MAX_LIMIT`).
+    pub fn clamped_limit(&self) -> i32 {
+        self.limit.clamp(1, Self::MAX_LIMIT)
+    }
+}
+
+/// ページネーションのリクエストパラメータ
+#[serde_as]
+#[derive(Debug, Serialize)]
+pub(crate) struct PaginationParams {
     pub limit: i32,
     #[serde_as(as = "DisplayFromStr")]
     pub order: SortOrder,
     pub starting_after: Option<IdType>,
     pub ending_before: Option<IdType>,
 }
+
+impl From<&Pagination> for PaginationParams {
+    fn from(pagination: &Pagination) -> Self {
+        PaginationParams {
+            limit: pagination.clamped_limit(),
+            order: pagination.order,
+            starting_after: pagination.starting_after,
+            ending_before: pagination.ending_before,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_amount_input() {
+        let cases = [
+            ("0.1", 0.1),
+            ("0.1 BTC", 0.1),
+            (" 0.1btc ", 0.1),
+            ("0,1", 0.1),
+            ("0,125 BTC", 0.125),
+            (",5", 0.5),
+            ("1,5", 1.5),
+            ("1,000,000", 1000000.0),
+            ("1,000.5", 1000.5),
+            ("1,000,000.5", 1000000.5),
+            ("¥1,000,000", 1000000.0),
+            ("1000円", 1000.0),
+            (".5", 0.5),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                parse_amount(input).unwrap(),
+                expected as PriceType,
+                "{}",
+                input
+            );
+        }
+
+        let invalid = [
+            "", "abc", "BTC", "-1", "0", "1e3", "1.2.3", "0.1 0.2", "NaN", "inf",
+        ];
+        // a thousands separator or a decimal separator
+        let ambiguous = ["1,000", "12,345 BTC", "¥1,000"];
+        for input in invalid.iter().chain(&ambiguous) {
+            let err = parse_amount(input).unwrap_err();
+            assert!(matches!(err, Error::InvalidParam(_)));
+            assert_eq!(err.to_string(), format!("invalid amount: {:?}", input));
+        }
+    }
+
+    #[test]
+    fn normalize_pair() {
+        let cases = [
+            ("btc_jpy", "btc_jpy"),
+            ("BTC_JPY", "btc_jpy"),
+            ("BTC/JPY", "btc_jpy"),
+            (" Mona-JPY ", "mona_jpy"),
+            ("IMX JPY", "imx_jpy"),
+            ("SHIBJPY", "shib_jpy"),
+            ("jpy", "jpy"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(normalize_pair_string(input), expected, "{}", input);
+        }
+        assert_eq!(
+            normalize_pair_string("MONAJPY").parse::<CoinPair>(),
+            Ok(CoinPair::MonaJpy)
+        );
+    }
+
+    #[test]
+    fn spot_order_types_are_supported() {
+        for pair in CoinPair::ALL.iter() {
+            let supported = supported_order_types(pair);
+            assert!(supported.contains(&OrderType::LimitBuy));
+            assert!(supported.contains(&OrderType::MarketSell));
+        }
+    }
+
+    #[test]
+    fn pagination_limit_is_clamped() {
+        let mut pagination = Pagination {
+            limit: 10_000,
+            order: SortOrder::Desc,
+            starting_after: None,
+            ending_before: None,
+        };
+        assert_eq!(pagination.clamped_limit(), Pagination::MAX_LIMIT);
+
+        pagination.limit = 0;
+        assert_eq!(pagination.clamped_limit(), 1);
+
+        pagination.limit = 25;
+        assert_eq!(pagination.clamped_limit(), 25);
+    }
+
+    #[test]
+    fn pagination_params_from_serialize() {
+        let pagination = Pagination {
+            limit: 500,
+            order: SortOrder::Asc,
+            starting_after: Some(42),
+            ending_before: None,
+        };
+        let params = params_from_serialize(&PaginationParams::from(&pagination)).unwrap();
+        assert_eq!(params.len(), 3);
+        assert_eq!(params["limit"], "100");
+        assert_eq!(params["order"], "asc");
+        assert_eq!(params["starting_after"], "42");
+
+        assert!(params_from_serialize(&[1, 2]).is_err());
+    }
+
+    #[test]
+    fn parse_currency() {
+        assert_eq!("JPY".parse::<Currency>(), Ok(Currency::Jpy));
+        assert_eq!("btc".parse::<Currency>(), Ok(Currency::Btc));
+        assert!("XXX".parse::<Currency>().is_err());
+    }
+
+    #[test]
+    fn round_amount_by_currency() {
+        // few enough digits for `f32` with `price_type_f32`
+        let amount = 0.0001234567 as PriceType;
+        assert_eq!(
+            round_amount(&Currency::Btc, amount),
+            0.00012345 as PriceType
+        );
+        assert_eq!(round_amount(&Currency::Xrp, amount), 0.000123 as PriceType);
+        assert_eq!(
+            round_amount(&Currency::Jpy, 1234.9 as PriceType),
+            1234 as PriceType
+        );
+        assert_eq!(
+            round_amount(&Currency::Btc, -amount),
+            -0.00012345 as PriceType
+        );
+
+        // not exactly representable, but already in satoshi
+        assert_eq!(round_amount(&Currency::Btc, 0.29), 0.29 as PriceType);
+        assert_eq!(round_amount(&Currency::Btc, 2.3), 2.3 as PriceType);
+        assert_eq!(round_amount(&Currency::Jpy, 1234.0), 1234 as PriceType);
+    }
+
+    #[test]
+    fn round_rate_to_tick() {
+        assert_eq!(
+            // 4_000_000.25 with `price_type_f32`
+            round_to_tick(&CoinPair::BtcJpy, 4_000_000.2 as PriceType),
+            4_000_000 as PriceType
+        );
+        assert_eq!(
+            round_to_tick(&CoinPair::BtcJpy, 4_000_000.5 as PriceType),
+            4_000_001 as PriceType
+        );
+        assert_eq!(
+            round_to_tick(&CoinPair::MonaJpy, 123.45678 as PriceType),
+            123.457 as PriceType
+        );
+        assert_eq!(
+            round_to_tick(&CoinPair::PltJpy, 0.1234 as PriceType).to_string(),
+            "0.123"
+        );
+    }
+
+    #[test]
+    fn fixed_point_round_trip() {
+        assert_eq!(to_satoshi(0.12345678 as PriceType), 12_345_678);
+        assert_eq!(to_satoshi(0.000000006 as PriceType), 1);
+        assert_eq!(to_satoshi(-1.5 as PriceType), -150_000_000);
+        for satoshi in [0, 1, 12_345_678, 2_100_000_000_000_000] {
+            assert_eq!(to_satoshi(from_satoshi(satoshi)), satoshi);
+        }
+
+        assert_eq!(to_yen(1234.5 as PriceType), 1235);
+        assert_eq!(to_yen(-0.4 as PriceType), 0);
+        assert_eq!(to_yen(from_yen(4_000_000)), 4_000_000);
+    }
+
+    #[test]
+    fn parse_pair_and_order_type() {
+        assert_eq!("btc_jpy".parse::<CoinPair>(), Ok(CoinPair::BtcJpy));
+        assert_eq!("BTC/JPY".parse::<CoinPair>(), Ok(CoinPair::BtcJpy));
+        assert_eq!("Mona_Jpy".parse::<CoinPair>(), Ok(CoinPair::MonaJpy));
+        assert!("xxx_jpy".parse::<CoinPair>().is_err());
+        assert!("btcjpy".parse::<CoinPair>().is_err());
+        assert_eq!(
+            serde_json::from_str::<CoinPair>(r#""btc/jpy""#).unwrap(),
+            CoinPair::BtcJpy
+        );
+        assert_eq!(
+            serde_json::to_string(&CoinPair::EtcJpy).unwrap(),
+            r#""etc_jpy""#
+        );
+        assert_eq!("sell".parse::<OrderType>(), Ok(OrderType::LimitSell));
+        assert_eq!("market_buy".parse::<OrderType>(), Ok(OrderType::MarketBuy));
+        assert!("stop".parse::<OrderType>().is_err());
+    }
+}
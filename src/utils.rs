@@ -1,4 +1,12 @@
-use anyhow::Result;
+use std::collections::HashSet;
+use std::time::Duration;
+
+use crate::error::Result;
+use chrono::{DateTime, TimeZone, Utc};
+
+use crate::private::order::model::OrderTransaction;
+use crate::public::model::{CalculatedRate, Trade};
+use crate::types::*;
 
 /// Run async function and output the result. For quick API checking.
 ///
@@ -26,3 +34,308 @@ where
         Err(err) => println!("error: {}", err),
     };
 }
+
+/// OHLC candle built from trades.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candle {
+    /// Start time of the bucket.
+    pub open_time: DateTime<Utc>,
+    pub open: PriceType,
+    pub high: PriceType,
+    pub low: PriceType,
+    pub close: PriceType,
+    pub volume: PriceType,
+}
+
+/// Bucket trades into OHLC candles by `created_at`.
+///
+/// The trades can be in any order (the API returns the newest first). Candles are returned in
+/// ascending time order, and intervals without any trade are skipped. Trades whose rate or
+/// amount can't be parsed are ignored.
+///
+/// # Examples
+///
+/// ```rust
+/// # use coinchecker::Coincheck;
+/// # use coinchecker::types::CoinPair;
+/// # use coinchecker::utils::trades_to_ohlc;
+/// # use std::time::Duration;
+/// #
+/// #[tokio::main]
+/// async fn main() {
+///     let mut coincheck = Coincheck::new_without_keys();
+///     if let Ok(trades) = coincheck.public.trades(&CoinPair::BtcJpy).await {
+///         let candles = trades_to_ohlc(&trades.data, Duration::from_secs(60));
+///         println!("{:?}", candles);
+///     }
+/// }
+/// ```
+pub fn trades_to_ohlc(trades: &[Trade], interval: Duration) -> Vec<Candle> {
+    let interval_ms = interval.as_millis() as i64;
+    if interval_ms <= 0 {
+        return Vec::new();
+    }
+
+    let mut parsed: Vec<_> = trades
+        .iter()
+        .filter_map(|t| {
+            let rate = t.rate.parse::<PriceType>().ok()?;
+            let amount = t.amount.parse::<PriceType>().ok()?;
+            Some((t.created_at, t.id, rate, amount))
+        })
+        .collect();
+    parsed.sort_by_key(|&(created_at, id, _, _)| (created_at, id));
+
+    let mut candles: Vec<Candle> = Vec::new();
+    for (created_at, _, rate, amount) in parsed {
+        let bucket_ms = created_at.timestamp_millis().div_euclid(interval_ms) * interval_ms;
+        let open_time = match Utc.timestamp_millis_opt(bucket_ms).single() {
+            Some(t) => t,
+            None => continue,
+        };
+
+        match candles.last_mut() {
+            Some(candle) if candle.open_time == open_time => {
+                candle.high = candle.high.max(rate);
+                candle.low = candle.low.min(rate);
+                candle.close = rate;
+                candle.volume += amount;
+            }
+            _ => candles.push(Candle {
+                open_time,
+                open: rate,
+                high: rate,
+                low: rate,
+                close: rate,
+                volume: amount,
+            }),
+        }
+    }
+
+    candles
+}
+
+/// Price at which closing a position breaks even, including the fee on both trades.
+///
+/// `side` is the side of the entry, and `fee_rate` is in percent, like the fees in
+/// [`Account`](crate::private::account::model::Account) (e.g. `0.15` for 0.15%).
+///
+/// - Buy (long): `entry_rate * (1 + fee) / (1 - fee)`
+/// - Sell (short): `entry_rate * (1 - fee) / (1 + fee)`
+pub fn break_even_price(
+    entry_rate: PriceType,
+    fee_rate: PriceType,
+    side: BaseOrderType,
+) -> PriceType {
+    let fee = fee_rate / 100 as PriceType;
+    let one = 1 as PriceType;
+    match side {
+        BaseOrderType::Buy => entry_rate * (one + fee) / (one - fee),
+        BaseOrderType::Sell => entry_rate * (one - fee) / (one + fee),
+    }
+}
+
+/// Slippage of the fills against the estimated rate, in basis points.
+///
+/// The realized rate is the average `rate` of the fills weighted by their amount (the base currency
+/// of `funds`), without the fees, like the estimate from
+/// [`Public::order_rate_from_amount`](crate::public::Public::order_rate_from_amount).
+/// Positive values are worse than the estimate: a higher rate for a buy, a lower rate for a sell.
+/// The side is taken from the first fill. Returns `0` if there is no fill with a known amount.
+pub fn slippage(estimated: &CalculatedRate, fills: &[OrderTransaction]) -> PriceType {
+    let mut total = 0 as PriceType;
+    let mut weighted = 0 as PriceType;
+    for fill in fills {
+        let base = match fill.pair.parse::<CoinPair>() {
+            Ok(pair) => pair.base().as_str().to_lowercase(),
+            Err(_) => continue,
+        };
+        if let Some(amount) = fill.funds.get(&base) {
+            total += amount.abs();
+            weighted += amount.abs() * fill.rate;
+        }
+    }
+    if total == 0 as PriceType || estimated.rate == 0 as PriceType {
+        return 0 as PriceType;
+    }
+
+    let bps = (weighted / total - estimated.rate) / estimated.rate * 10_000 as PriceType;
+    match fills.first() {
+        Some(fill) if fill.side == BaseOrderType::Sell.as_str() => -bps,
+        _ => bps,
+    }
+}
+
+/// Fraction of the orders that got filled, from `0.0` to `1.0`.
+///
+/// The orders are those with a fill in `transactions` or listed in `cancelled_ids`. An order
+/// partially filled and then cancelled counts as filled. Returns `0` if there is no order.
+pub fn fill_ratio(transactions: &[OrderTransaction], cancelled_ids: &[IdType]) -> f64 {
+    let filled: HashSet<IdType> = transactions.iter().map(|t| t.order_id.0).collect();
+    let cancelled = cancelled_ids
+        .iter()
+        .filter(|id| !filled.contains(id))
+        .collect::<HashSet<_>>()
+        .len();
+    let total = filled.len() + cancelled;
+    if total == 0 {
+        return 0.0;
+    }
+    filled.len() as f64 / total as f64
+}
+
+/// Realized volatility of the recent trades: the sample standard deviation of the log returns
+/// between consecutive trade prices.
+///
+/// Only the trades within `window` of the latest trade are used. The result is per trade, not
+/// annualized. Returns `None` if there are less than 2 returns (3 trades) in the window.
+pub fn realized_vol(trades: &[Trade], window: Duration) -> Option<f64> {
+    let mut parsed: Vec<_> = trades
+        .iter()
+        .filter_map(|t| {
+            let rate = t.rate.parse::<f64>().ok()?;
+            (rate > 0.0).then_some((t.created_at, t.id, rate))
+        })
+        .collect();
+    parsed.sort_by_key(|&(created_at, id, _)| (created_at, id));
+
+    let latest = parsed.last()?.0;
+    let since = latest - chrono::Duration::from_std(window).ok()?;
+    let rates: Vec<f64> = parsed
+        .iter()
+        .filter(|&&(created_at, _, _)| created_at >= since)
+        .map(|&(_, _, rate)| rate)
+        .collect();
+
+    let returns: Vec<f64> = rates.windows(2).map(|w| (w[1] / w[0]).ln()).collect();
+    if returns.len() < 2 {
+        return None;
+    }
+    let n = returns.len() as f64;
+    let mean = returns.iter().sum::<f64>() / n;
+    let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    Some(variance.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::order::tests::transaction;
+
+    #[test]
+    fn realized_vol_of_trades() {
+        let trade = |id: IdType, rate: &str, secs: i64| -> Trade {
+            serde_json::from_value(serde_json::json!({
+                "id": id, "amount": "0.1", "rate": rate, "pair": "btc_jpy", "order_type": "buy",
+                "created_at": Utc.timestamp_opt(1_600_000_000 + secs, 0).unwrap(),
+            }))
+            .unwrap()
+        };
+        let window = Duration::from_secs(60);
+
+        // returns ln(1.1), ln(1/1.1): mean 0, sample stddev sqrt(2) * ln(1.1)
+        let trades = [
+            trade(4, "110", 30),
+            trade(3, "100", 20),
+            trade(2, "110", 10),
+            trade(1, "100", 0),
+        ];
+        let vol = realized_vol(&trades[..3], window).unwrap();
+        assert!((vol - 2f64.sqrt() * 1.1f64.ln()).abs() < 1e-12);
+        assert!(realized_vol(&trades, window).unwrap() > 0.0);
+
+        // the old trades are out of the window
+        assert_eq!(realized_vol(&trades, Duration::from_secs(15)), None);
+        // constant price
+        let flat = [trade(1, "100", 0), trade(2, "100", 1), trade(3, "100", 2)];
+        assert_eq!(realized_vol(&flat, window), Some(0.0));
+        assert_eq!(realized_vol(&[], window), None);
+    }
+
+    fn trade(id: IdType, secs: i64, rate: &str, amount: &str) -> Trade {
+        Trade {
+            id,
+            amount: amount.to_owned(),
+            rate: rate.to_owned(),
+            pair: CoinPair::BtcJpy.as_str().to_owned(),
+            order_type: BaseOrderType::Buy,
+            created_at: Utc.timestamp_opt(secs, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn bucket_trades_into_candles() {
+        // newest first, like the API
+        let trades = vec![
+            trade(5, 130, "105", "0.5"),
+            trade(4, 70, "90", "1"),
+            trade(3, 59, "110", "1"),
+            trade(2, 30, "95", "2"),
+            trade(1, 0, "100", "1"),
+        ];
+
+        let candles = trades_to_ohlc(&trades, Duration::from_secs(60));
+        assert_eq!(candles.len(), 3);
+
+        assert_eq!(candles[0].open_time, Utc.timestamp_opt(0, 0).unwrap());
+        assert_eq!(candles[0].open, 100 as PriceType);
+        assert_eq!(candles[0].high, 110 as PriceType);
+        assert_eq!(candles[0].low, 95 as PriceType);
+        assert_eq!(candles[0].close, 110 as PriceType);
+        assert_eq!(candles[0].volume, 4 as PriceType);
+
+        assert_eq!(candles[1].open_time, Utc.timestamp_opt(60, 0).unwrap());
+        assert_eq!(candles[1].close, 90 as PriceType);
+        assert_eq!(candles[2].open_time, Utc.timestamp_opt(120, 0).unwrap());
+        assert_eq!(candles[2].volume, 0.5 as PriceType);
+
+        assert!(trades_to_ohlc(&trades, Duration::from_secs(0)).is_empty());
+    }
+
+    #[test]
+    fn slippage_in_bps() {
+        let estimated: CalculatedRate = serde_json::from_value(serde_json::json!({
+            "success": true, "rate": "100", "price": "100", "amount": "1"
+        }))
+        .unwrap();
+        let fill = |side, btc, rate| transaction(1, 1, side, rate, (btc, "0"), ("JPY", "0"));
+
+        // average 100.5: 50 bps worse for a buy, 50 bps better for a sell
+        let buys = [fill("buy", "0.5", "100"), fill("buy", "0.5", "101")];
+        assert!((slippage(&estimated, &buys) - 50.0).abs() < 1e-6);
+        let sells = [fill("sell", "-0.5", "100"), fill("sell", "-0.5", "101")];
+        assert!((slippage(&estimated, &sells) + 50.0).abs() < 1e-6);
+
+        assert_eq!(slippage(&estimated, &[]), 0.0);
+    }
+
+    #[test]
+    fn fill_ratio_of_orders() {
+        let fill =
+            |id, order_id| transaction(id, order_id, "buy", "1000", ("0.1", "-100"), ("JPY", "0"));
+
+        // orders 1 and 2 filled (2 in two fills, then the rest cancelled), 3 and 4 cancelled
+        let fills = [fill(1, 1), fill(2, 2), fill(3, 2)];
+        assert!((fill_ratio(&fills, &[2, 3, 4, 4]) - 0.5).abs() < 1e-9);
+        assert_eq!(fill_ratio(&fills, &[]), 1.0);
+        assert_eq!(fill_ratio(&[], &[3]), 0.0);
+        assert_eq!(fill_ratio(&[], &[]), 0.0);
+    }
+
+    #[test]
+    fn break_even() {
+        let entry = 4_000_000 as PriceType;
+        assert_eq!(break_even_price(entry, 0.0, BaseOrderType::Buy), entry);
+
+        // relative, since `f32` with `price_type_f32` has about 7 digits
+        let close = |a: PriceType, b: PriceType| (a - b).abs() <= b.abs() * 1e-6;
+
+        let sell = break_even_price(entry, 0.1, BaseOrderType::Buy);
+        assert!(close(sell, 4_008_008.008));
+        // the sale after fees pays back the purchase including fees
+        assert!(close(sell * 0.999, entry * 1.001));
+
+        let buy = break_even_price(entry, 0.1, BaseOrderType::Sell);
+        assert!(close(buy * 1.001, entry * 0.999));
+    }
+}
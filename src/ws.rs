@@ -0,0 +1,398 @@
+//! A minimal WebSocket client (RFC 6455) for the Coincheck streaming API.
+//!
+//! Only what the public channels need: text messages, fragmentation, ping/pong and close.
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use reqwest::Url;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_native_tls::{native_tls, TlsStream};
+
+use crate::error::{Error, Result};
+
+/// Coincheck WebSocket API endpoint.
+pub(crate) const WS_URL: &str = "wss://ws-api.coincheck.com/";
+
+/// Larger messages are rejected, to bound the memory of a broken or hostile stream.
+const MAX_MESSAGE_BYTES: usize = 16 << 20;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xa;
+
+/// A received message. Pings and closes are left to the caller to answer, so that
+/// [`WsConnection::recv`] doesn't write and stays cancel safe.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Message {
+    Text(String),
+    Ping(Vec<u8>),
+    Close(Vec<u8>),
+}
+
+pub(crate) struct WsConnection<S> {
+    stream: BufReader<S>,
+    /// Received bytes not parsed into frames yet.
+    buf: Vec<u8>,
+    /// Payload of the fragments of the message being received.
+    message: Vec<u8>,
+}
+
+impl WsConnection<TlsStream<TcpStream>> {
+    /// Connect to a `wss://` URL.
+    pub(crate) async fn connect(url: &str) -> Result<Self> {
+        let url = Url::parse(url).map_err(|err| Error::WebSocket(err.to_string()))?;
+        if url.scheme() != "wss" {
+            return Err(Error::WebSocket(format!(
+                "unsupported WebSocket scheme: {}",
+                url.scheme()
+            )));
+        }
+        let host = url
+            .host_str()
+            .ok_or_else(|| Error::WebSocket(format!("no host in the WebSocket URL: {}", url)))?;
+        let port = url.port().unwrap_or(443);
+
+        let tcp = TcpStream::connect((host, port)).await?;
+        let tls_error = |err: native_tls::Error| Error::WebSocket(err.to_string());
+        let connector = native_tls::TlsConnector::new().map_err(tls_error)?;
+        let connector = tokio_native_tls::TlsConnector::from(connector);
+        let tls = connector.connect(host, tcp).await.map_err(tls_error)?;
+
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_owned(),
+        };
+        WsConnection::handshake(tls, host, &path).await
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> WsConnection<S> {
+    /// Upgrade the connection to WebSocket.
+    ///
+    /// The `Sec-WebSocket-Accept` header isn't verified (it needs SHA-1); the connection is
+    /// already authenticated by TLS.
+    pub(crate) async fn handshake(io: S, host: &str, path: &str) -> Result<Self> {
+        let mut stream = BufReader::new(io);
+        let key = BASE64.encode(random_bytes::<16>());
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            path, host, key
+        );
+        stream.get_mut().write_all(request.as_bytes()).await?;
+        stream.get_mut().flush().await?;
+
+        let mut status = String::new();
+        stream.read_line(&mut status).await?;
+        if status.split_whitespace().nth(1) != Some("101") {
+            return Err(Error::WebSocket(format!(
+                "WebSocket handshake failed: {}",
+                status.trim_end()
+            )));
+        }
+        loop {
+            let mut line = String::new();
+            if stream.read_line(&mut line).await? == 0 {
+                return Err(Error::WebSocket(
+                    "connection closed during the WebSocket handshake".to_owned(),
+                ));
+            }
+            if line == "\r\n" || line == "\n" {
+                break;
+            }
+        }
+
+        Ok(WsConnection {
+            stream,
+            buf: Vec::new(),
+            message: Vec::new(),
+        })
+    }
+
+    /// Send a text message.
+    pub(crate) async fn send_text(&mut self, text: &str) -> Result<()> {
+        self.send_frame(OPCODE_TEXT, text.as_bytes()).await
+    }
+
+    /// Receive the next message.
+    ///
+    /// This is cancel safe: if the future is dropped (e.g. in `tokio::select!`), no data is lost
+    /// and the next call continues where it stopped.
+    pub(crate) async fn recv(&mut self) -> Result<Message> {
+        loop {
+            while let Some((fin, opcode, payload)) = self.parse_frame()? {
+                match opcode {
+                    OPCODE_PING => return Ok(Message::Ping(payload)),
+                    OPCODE_PONG => {}
+                    OPCODE_CLOSE => return Ok(Message::Close(payload)),
+                    OPCODE_TEXT | OPCODE_BINARY | OPCODE_CONTINUATION => {
+                        if self.message.len() + payload.len() > MAX_MESSAGE_BYTES {
+                            return Err(Error::WebSocket(format!(
+                                "WebSocket message exceeds {} bytes",
+                                MAX_MESSAGE_BYTES
+                            )));
+                        }
+                        self.message.extend_from_slice(&payload);
+                        if fin {
+                            let message = std::mem::take(&mut self.message);
+                            let message = String::from_utf8(message)
+                                .map_err(|err| Error::WebSocket(err.to_string()))?;
+                            return Ok(Message::Text(message));
+                        }
+                    }
+                    _ => {
+                        return Err(Error::WebSocket(format!(
+                            "unknown WebSocket opcode: {:#x}",
+                            opcode
+                        )))
+                    }
+                }
+            }
+            if self.stream.read_buf(&mut self.buf).await? == 0 {
+                return Err(Error::WebSocket(
+                    "WebSocket connection closed without a close frame".to_owned(),
+                ));
+            }
+        }
+    }
+
+    pub(crate) async fn send_pong(&mut self, payload: &[u8]) -> Result<()> {
+        self.send_frame(OPCODE_PONG, payload).await
+    }
+
+    pub(crate) async fn send_close(&mut self, payload: &[u8]) -> Result<()> {
+        self.send_frame(OPCODE_CLOSE, payload).await
+    }
+
+    /// Take the first complete frame out of the buffer, if any.
+    fn parse_frame(&mut self) -> Result<Option<(bool, u8, Vec<u8>)>> {
+        let buf = &self.buf;
+        if buf.len() < 2 {
+            return Ok(None);
+        }
+        let fin = buf[0] & 0x80 != 0;
+        let opcode = buf[0] & 0x0f;
+        let masked = buf[1] & 0x80 != 0;
+        let (len, mut pos) = match buf[1] & 0x7f {
+            126 if buf.len() >= 4 => (u16::from_be_bytes([buf[2], buf[3]]) as u64, 4),
+            127 if buf.len() >= 10 => {
+                let mut len = [0u8; 8];
+                len.copy_from_slice(&buf[2..10]);
+                (u64::from_be_bytes(len), 10)
+            }
+            126 | 127 => return Ok(None),
+            len => (len as u64, 2),
+        };
+        if len > MAX_MESSAGE_BYTES as u64 {
+            return Err(Error::WebSocket(format!(
+                "WebSocket frame exceeds {} bytes",
+                MAX_MESSAGE_BYTES
+            )));
+        }
+
+        let mut mask = [0u8; 4];
+        if masked {
+            if buf.len() < pos + 4 {
+                return Ok(None);
+            }
+            mask.copy_from_slice(&buf[pos..pos + 4]);
+            pos += 4;
+        }
+        let end = pos + len as usize;
+        if buf.len() < end {
+            return Ok(None);
+        }
+
+        let mut payload = buf[pos..end].to_vec();
+        self.buf.drain(..end);
+        if masked {
+            apply_mask(&mut payload, mask);
+        }
+        Ok(Some((fin, opcode, payload)))
+    }
+
+    /// Send a single masked frame, as required for the client.
+    async fn send_frame(&mut self, opcode: u8, payload: &[u8]) -> Result<()> {
+        let mut frame = Vec::with_capacity(payload.len() + 14);
+        frame.push(0x80 | opcode);
+        match payload.len() {
+            len if len < 126 => frame.push(0x80 | len as u8),
+            len if len <= u16::MAX as usize => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(0x80 | 127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        let mask = random_bytes::<4>();
+        frame.extend_from_slice(&mask);
+        let start = frame.len();
+        frame.extend_from_slice(payload);
+        apply_mask(&mut frame[start..], mask);
+
+        let io = self.stream.get_mut();
+        io.write_all(&frame).await?;
+        io.flush().await?;
+        Ok(())
+    }
+}
+
+fn apply_mask(payload: &mut [u8], mask: [u8; 4]) {
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+}
+
+/// Random bytes for the handshake key and the frame masks, from the randomly seeded std hasher.
+/// They only need to be unpredictable to the intermediaries, not cryptographically secure.
+fn random_bytes<const N: usize>() -> [u8; N] {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let state = RandomState::new();
+    let mut bytes = [0u8; N];
+    for chunk in bytes.chunks_mut(8) {
+        let hash = state.hash_one(COUNTER.fetch_add(1, Ordering::Relaxed));
+        chunk.copy_from_slice(&hash.to_le_bytes()[..chunk.len()]);
+    }
+    bytes
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tokio::io::DuplexStream;
+
+    /// Accept a WebSocket handshake on the server side of a duplex stream.
+    pub(crate) async fn accept(server: DuplexStream) -> BufReader<DuplexStream> {
+        let mut server = BufReader::new(server);
+        loop {
+            let mut line = String::new();
+            server.read_line(&mut line).await.unwrap();
+            if line == "\r\n" {
+                break;
+            }
+        }
+        server
+            .get_mut()
+            .write_all(b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\r\n")
+            .await
+            .unwrap();
+        server
+    }
+
+    /// Write an unmasked server frame.
+    pub(crate) async fn write_frame<W: AsyncWrite + Unpin>(
+        io: &mut W,
+        fin: bool,
+        opcode: u8,
+        payload: &[u8],
+    ) {
+        let mut frame = vec![if fin { 0x80 } else { 0 } | opcode];
+        if payload.len() < 126 {
+            frame.push(payload.len() as u8);
+        } else {
+            frame.push(126);
+            frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        }
+        frame.extend_from_slice(payload);
+        io.write_all(&frame).await.unwrap();
+    }
+
+    /// Read a masked client text frame.
+    pub(crate) async fn read_text<R: AsyncRead + Unpin>(io: &mut R) -> String {
+        let mut head = [0u8; 2];
+        io.read_exact(&mut head).await.unwrap();
+        assert_eq!(head[0], 0x80 | OPCODE_TEXT);
+        let mut mask = [0u8; 4];
+        io.read_exact(&mut mask).await.unwrap();
+        let mut payload = vec![0u8; (head[1] & 0x7f) as usize];
+        io.read_exact(&mut payload).await.unwrap();
+        apply_mask(&mut payload, mask);
+        String::from_utf8(payload).unwrap()
+    }
+
+    #[tokio::test]
+    async fn exchange_messages() {
+        let (client, server) = tokio::io::duplex(1 << 16);
+        let server = tokio::spawn(async move {
+            let mut server = accept(server).await;
+
+            assert_eq!(read_text(&mut server).await, "subscribe");
+
+            let io = server.get_mut();
+            write_frame(io, true, OPCODE_PING, b"p").await;
+            write_frame(io, false, OPCODE_TEXT, b"hello ").await;
+            write_frame(io, true, OPCODE_CONTINUATION, b"world").await;
+            let long = "x".repeat(300);
+            write_frame(io, true, OPCODE_TEXT, long.as_bytes()).await;
+            write_frame(io, true, OPCODE_CLOSE, b"").await;
+
+            // pong for the ping
+            let mut head = [0u8; 2];
+            server.read_exact(&mut head).await.unwrap();
+            assert_eq!(head[0], 0x80 | OPCODE_PONG);
+        });
+
+        let mut conn = WsConnection::handshake(client, "localhost", "/")
+            .await
+            .unwrap();
+        conn.send_text("subscribe").await.unwrap();
+        assert_eq!(conn.recv().await.unwrap(), Message::Ping(b"p".to_vec()));
+        conn.send_pong(b"p").await.unwrap();
+        assert_eq!(
+            conn.recv().await.unwrap(),
+            Message::Text("hello world".to_owned())
+        );
+        assert!(matches!(conn.recv().await.unwrap(), Message::Text(text) if text.len() == 300));
+        assert_eq!(conn.recv().await.unwrap(), Message::Close(Vec::new()));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reject_failed_handshake() {
+        let (client, mut server) = tokio::io::duplex(1 << 10);
+        server
+            .write_all(b"HTTP/1.1 404 Not Found\r\n\r\n")
+            .await
+            .unwrap();
+        assert!(WsConnection::handshake(client, "localhost", "/")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn recv_is_cancel_safe() {
+        let (client, server) = tokio::io::duplex(1 << 10);
+        let (mut conn, server) = tokio::join!(
+            async {
+                WsConnection::handshake(client, "localhost", "/")
+                    .await
+                    .unwrap()
+            },
+            accept(server)
+        );
+        let mut server = server.into_inner();
+
+        // a frame split in two writes, with the receive cancelled in between
+        server
+            .write_all(&[0x80 | OPCODE_TEXT, 5, b'h', b'e'])
+            .await
+            .unwrap();
+        let timeout = std::time::Duration::from_millis(10);
+        assert!(tokio::time::timeout(timeout, conn.recv()).await.is_err());
+        server.write_all(b"llo").await.unwrap();
+        assert_eq!(
+            conn.recv().await.unwrap(),
+            Message::Text("hello".to_owned())
+        );
+    }
+}